@@ -1,10 +1,20 @@
-use reqwest::{Client, IntoUrl, Request, RequestBuilder};
+pub mod requests;
+pub mod response_data;
+
+use std::time::Duration;
+
+use reqwest::{Client, IntoUrl, Request, RequestBuilder, StatusCode};
+use response_data::{Arrival, ArrivalsPayload, Departure, DeparturesPayload, Disruption, Payload};
+use serde::de::DeserializeOwned;
 use thiserror::Error;
 
-const API_HOST: &str = "https://gateway.apiportal.ns.nl/";
-const ROUTE_PATH: &str = "Spoorkaart-API/api/v1/spoorkaart";
-const STATION_PATH: &str = "reisinformatie-api/api/v2/stations";
-const TRIP_PATH: &str = "reisinformatie-api/api/v3/trips";
+pub const API_HOST: &str = "https://gateway.apiportal.ns.nl/";
+pub const ROUTE_PATH: &str = "Spoorkaart-API/api/v1/spoorkaart";
+pub const STATION_PATH: &str = "reisinformatie-api/api/v2/stations";
+pub const TRIP_PATH: &str = "reisinformatie-api/api/v3/trips";
+pub const DEPARTURES_PATH: &str = "reisinformatie-api/api/v2/departures";
+pub const ARRIVALS_PATH: &str = "reisinformatie-api/api/v2/arrivals";
+pub const DISRUPTIONS_PATH: &str = "reisinformatie-api/api/v3/disruptions";
 
 #[derive(Error, Debug)]
 pub enum ApiError {
@@ -14,107 +24,68 @@ pub enum ApiError {
     Parsing(serde_json::Error),
 }
 
+/// Time after which an upstream request is abandoned
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Additional attempts after a failed upstream request
+const DEFAULT_RETRIES: u32 = 2;
+/// Delay before the first retry, doubled for every following retry
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
 pub struct NsApi {
     key: String,
+    /// Base URL all paths are appended to, ending in a slash
+    host: String,
     client: Client,
+    timeout: Duration,
+    retries: u32,
 }
 
-pub struct TripAdviceArguments<'a, 'b, 'c> {
-    pub from: &'a str,
-    pub to: &'b str,
-    pub via: Option<&'c str>,
-}
-
-pub mod response_data {
-    use serde::{Deserialize, Serialize};
-
-    #[derive(Deserialize, Debug, Serialize)]
-    #[allow(non_snake_case)]
-    #[serde(tag = "type")]
-    #[serde(rename_all = "UPPERCASE")]
-    pub enum Product {
-        Walk,
-        Train {
-            number: String,
-            categoryCode: String,
-        },
-        // pub number: String,
-        // pub categoryCode: String,
-    }
-
-    impl Product {
-        pub fn get_number(&self) -> Option<&str> {
-            match self {
-                Product::Walk => None,
-                Product::Train {
-                    number,
-                    categoryCode: _,
-                } => Some(number),
-            }
-        }
-    }
-
-    #[derive(Deserialize, Debug, Serialize)]
-    #[serde(tag = "type")]
-    #[serde(rename_all(deserialize = "UPPERCASE"))]
-    #[allow(non_snake_case)]
-    pub enum Location {
-        Station { stationCode: String },
-        Address,
-    }
-
-    impl Location {
-        pub fn get_code(&self) -> Option<&str> {
-            match self {
-                Location::Station { stationCode } => Some(stationCode),
-                Location::Address => None,
-            }
-        }
-    }
-
-    #[derive(Deserialize, Debug, Serialize)]
-
-    // #[serde(rename_all(deserialize = "SCREAMING_SNAKE_CASE"))]
-    pub enum LegKind {
-        #[serde(rename = "WALK")]
-        Walk,
-        #[serde(rename = "PUBLIC_TRANSIT")]
-        PublicTransit,
-    }
-
-    #[derive(Deserialize, Debug, Serialize)]
-    pub struct Leg {
-        pub name: Option<String>,
-        pub origin: Location,
-        pub destination: Location,
-        pub product: Product,
-        #[serde(rename = "travelType")]
-        pub travel_type: LegKind,
-    }
-
-    #[derive(Deserialize, Debug, Serialize)]
-    pub struct Trip {
-        pub legs: Vec<Leg>,
-    }
-
-    #[derive(Deserialize, Debug, Serialize)]
-    pub struct Response {
-        pub trips: Vec<Trip>,
-    }
+/// Whether a failed request might succeed when tried again
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error.status().is_some_and(|status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        })
 }
 
+pub use requests::{
+    DisruptionArguments, DisruptionKind, StationBoardArguments, TripAdviceArguments,
+};
 pub use response_data::Response;
 
 impl NsApi {
     pub fn new(key: String) -> Self {
         Self {
             key,
+            host: API_HOST.to_owned(),
             client: Client::new(),
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Sends requests to `host` instead of the NS API gateway, e.g. a local stand-in
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        if !self.host.ends_with('/') {
+            self.host.push('/');
         }
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
     }
 
     pub async fn fetch_stations(&self) -> Result<Vec<u8>, ApiError> {
-        let url = API_HOST.to_owned() + STATION_PATH;
+        let url = self.host.clone() + STATION_PATH;
         let rb = self.start_request(url);
         let req = rb.build().map_err(ApiError::Network)?;
 
@@ -122,22 +93,39 @@ impl NsApi {
     }
 
     pub async fn fetch_routes(&self) -> Result<Vec<u8>, ApiError> {
-        println!("{}", self.key);
-        let url = API_HOST.to_owned() + ROUTE_PATH;
+        let url = self.host.clone() + ROUTE_PATH;
         let rb = self.start_request(url);
         let req = rb.build().map_err(ApiError::Network)?;
 
         self.fetch_as_bytes(req).await.map(std::convert::Into::into)
     }
 
+    /// Executes `request`, retrying on timeouts, connection errors and server side errors
     async fn fetch_as_bytes(&self, request: Request) -> Result<bytes::Bytes, ApiError> {
-        self.client
-            .execute(request)
-            .await
-            .map_err(ApiError::Network)?
-            .bytes()
-            .await
-            .map_err(ApiError::Network)
+        let mut attempt = 0;
+
+        loop {
+            let retry = request
+                .try_clone()
+                .expect("GET requests without a body to be cloneable");
+
+            let result = match self.client.execute(retry).await {
+                Ok(response) => match response.error_for_status() {
+                    Ok(response) => response.bytes().await,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(bytes) => return Ok(bytes),
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(ApiError::Network(e)),
+            }
+        }
     }
 
     #[allow(dead_code)]
@@ -154,36 +142,170 @@ impl NsApi {
     fn start_request(&self, url: impl IntoUrl) -> RequestBuilder {
         self.client
             .get(url)
+            .timeout(self.timeout)
             .header("Ocp-Apim-Subscription-Key", &self.key)
     }
 
-    pub async fn find_path(
+    async fn fetch_json<T: DeserializeOwned>(
         &self,
-        args: &TripAdviceArguments<'_, '_, '_>,
-    ) -> Result<response_data::Response, ApiError> {
-        let url = API_HOST.to_owned() + TRIP_PATH;
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, ApiError> {
+        let url = self.host.clone() + path;
 
-        let mut request = self
+        let request = self
             .start_request(url)
-            .query(&[("fromStation", args.from), ("toStation", args.to)]);
+            .query(query)
+            .build()
+            .map_err(ApiError::Network)?;
 
-        if let Some(via) = args.via {
-            request = request.query(&[("viaStation", via)]);
-        }
+        let byteslice = self.fetch_as_bytes(request).await?;
 
-        let request = request.build().map_err(ApiError::Network)?;
+        serde_json::from_slice(&byteslice).map_err(ApiError::Parsing)
+    }
 
-        let res = self
-            .client
-            .execute(request)
+    pub async fn find_path(
+        &self,
+        args: &TripAdviceArguments,
+    ) -> Result<response_data::Response, ApiError> {
+        self.fetch_json(TRIP_PATH, &args.query()).await
+    }
+
+    pub async fn departures(
+        &self,
+        args: &StationBoardArguments<'_>,
+    ) -> Result<Vec<Departure>, ApiError> {
+        self.fetch_json::<Payload<DeparturesPayload>>(DEPARTURES_PATH, &args.query())
             .await
-            .map_err(ApiError::Network)?;
+            .map(|response| response.payload.departures)
+    }
+
+    pub async fn arrivals(
+        &self,
+        args: &StationBoardArguments<'_>,
+    ) -> Result<Vec<Arrival>, ApiError> {
+        self.fetch_json::<Payload<ArrivalsPayload>>(ARRIVALS_PATH, &args.query())
+            .await
+            .map(|response| response.payload.arrivals)
+    }
+
+    pub async fn disruptions(
+        &self,
+        args: &DisruptionArguments,
+    ) -> Result<Vec<Disruption>, ApiError> {
+        self.fetch_json(DISRUPTIONS_PATH, &args.query()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use response_data::{CrowdForecast, LegKind, LocationKind, ProductKind};
+
+    #[test]
+    fn parse_trips_fixture() {
+        // Shared with the stand-in server, which serves the same recording
+        let response: Response =
+            serde_json::from_str(include_str!("../../fixtures/standin/trips.json")).unwrap();
+        let legs = &response.trips[0].legs;
+
+        let train = &legs[0];
+        assert_eq!(train.travel_type, LegKind::PublicTransit);
+        assert_eq!(train.product.get_number(), Some("2871"));
+        assert_eq!(train.origin.get_code(), Some("UT"));
+        assert_eq!(
+            train.origin.actual_date_time,
+            Some(DateTime::parse_from_rfc3339("2024-01-02T10:09:00+01:00").unwrap())
+        );
+        assert_eq!(train.origin.actual_track.as_deref(), Some("11a"));
+        assert_eq!(train.crowd_forecast, Some(CrowdForecast::Medium));
+        assert_eq!(train.transfer_messages.len(), 1);
 
-        let byteslice = res.bytes().await.map_err(ApiError::Network)?;
+        let walk = &legs[1];
+        assert_eq!(walk.travel_type, LegKind::Walk);
+        assert_eq!(walk.product.kind, ProductKind::Walk);
+        assert_eq!(walk.product.get_number(), None);
+        assert!(matches!(walk.destination.kind, LocationKind::Address));
+        assert_eq!(walk.distance_in_meters, Some(720));
+    }
+
+    #[test]
+    fn parse_station_board_fixtures() {
+        let departures: Payload<DeparturesPayload> =
+            serde_json::from_str(include_str!("../fixtures/departures.json")).unwrap();
+        let arrivals: Payload<ArrivalsPayload> =
+            serde_json::from_str(include_str!("../fixtures/arrivals.json")).unwrap();
+
+        let departures = departures.payload.departures;
+        assert_eq!(departures.len(), 2);
+        assert_eq!(departures[0].route_stations[0].medium_name, "Gouda");
+        assert!(departures[1].cancelled);
+        assert_eq!(departures[1].actual_date_time, None);
+
+        assert_eq!(arrivals.payload.arrivals[0].origin, "Den Haag Centraal");
+    }
+
+    #[test]
+    fn parse_disruptions_fixture() {
+        let disruptions: Vec<Disruption> =
+            serde_json::from_str(include_str!("../fixtures/disruptions.json")).unwrap();
+
+        let [Disruption::Calamity(calamity), Disruption::Disruption(disruption), Disruption::Maintenance(maintenance), Disruption::Other] =
+            disruptions.as_slice()
+        else {
+            panic!("Unexpected disruptions {disruptions:#?}");
+        };
+
+        assert!(calamity.is_active);
+        assert_eq!(disruption.publication_sections[0].section.stations.len(), 3);
+        assert_eq!(
+            disruption.timespans[0]
+                .additional_travel_time
+                .as_ref()
+                .and_then(|time| time.minimum_duration_in_minutes),
+            Some(15)
+        );
+        assert!(!maintenance.is_active);
+    }
 
-        let response_data: response_data::Response =
-            serde_json::from_slice(&byteslice).map_err(ApiError::Parsing)?;
+    #[test]
+    fn request_queries() {
+        let date_time = DateTime::parse_from_rfc3339("2024-01-02T10:00:00+01:00").unwrap();
 
-        Ok(response_data)
+        assert_eq!(
+            TripAdviceArguments::new("ut", "gd")
+                .via("wd")
+                .date_time(date_time)
+                .search_for_arrival(true)
+                .query(),
+            vec![
+                ("fromStation", "ut".to_owned()),
+                ("toStation", "gd".to_owned()),
+                ("viaStation", "wd".to_owned()),
+                ("dateTime", "2024-01-02T10:00:00+01:00".to_owned()),
+                ("searchForArrival", "true".to_owned()),
+            ]
+        );
+        assert_eq!(
+            StationBoardArguments::new("ut").max_journeys(10).query(),
+            vec![
+                ("station", "ut".to_owned()),
+                ("maxJourneys", "10".to_owned())
+            ]
+        );
+        assert_eq!(
+            DisruptionArguments::new()
+                .active(true)
+                .kind(DisruptionKind::Disruption)
+                .kind(DisruptionKind::Maintenance)
+                .query(),
+            vec![
+                ("isActive", "true".to_owned()),
+                ("type", "disruption,maintenance".to_owned())
+            ]
+        );
     }
 }
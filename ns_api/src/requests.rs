@@ -0,0 +1,157 @@
+//! Typed arguments for the NS API endpoints, each turns into the query string of its request
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+
+fn format_date_time(date_time: &DateTime<FixedOffset>) -> String {
+    date_time.to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+/// Owns its data so it can be used as a cache key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TripAdviceArguments {
+    pub from: String,
+    pub to: String,
+    pub via: Option<String>,
+    /// Departure time, or arrival time when `search_for_arrival` is set. Defaults to now
+    pub date_time: Option<DateTime<FixedOffset>>,
+    pub search_for_arrival: bool,
+}
+
+impl TripAdviceArguments {
+    pub fn new(from: impl Into<String>, to: impl Into<String>) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+            via: None,
+            date_time: None,
+            search_for_arrival: false,
+        }
+    }
+
+    pub fn via(mut self, via: impl Into<String>) -> Self {
+        self.via = Some(via.into());
+        self
+    }
+
+    pub fn date_time(mut self, date_time: DateTime<FixedOffset>) -> Self {
+        self.date_time = Some(date_time);
+        self
+    }
+
+    pub fn search_for_arrival(mut self, search_for_arrival: bool) -> Self {
+        self.search_for_arrival = search_for_arrival;
+        self
+    }
+
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![
+            ("fromStation", self.from.clone()),
+            ("toStation", self.to.clone()),
+        ];
+
+        if let Some(via) = &self.via {
+            query.push(("viaStation", via.clone()));
+        }
+        if let Some(date_time) = &self.date_time {
+            query.push(("dateTime", format_date_time(date_time)));
+        }
+        if self.search_for_arrival {
+            query.push(("searchForArrival", true.to_string()));
+        }
+
+        query
+    }
+}
+
+/// Arguments for both the departures and arrivals endpoints
+pub struct StationBoardArguments<'a> {
+    pub station: &'a str,
+    pub date_time: Option<DateTime<FixedOffset>>,
+    pub max_journeys: Option<u32>,
+}
+
+impl<'a> StationBoardArguments<'a> {
+    pub fn new(station: &'a str) -> Self {
+        Self {
+            station,
+            date_time: None,
+            max_journeys: None,
+        }
+    }
+
+    pub fn date_time(mut self, date_time: DateTime<FixedOffset>) -> Self {
+        self.date_time = Some(date_time);
+        self
+    }
+
+    pub fn max_journeys(mut self, max_journeys: u32) -> Self {
+        self.max_journeys = Some(max_journeys);
+        self
+    }
+
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![("station", self.station.to_owned())];
+
+        if let Some(date_time) = &self.date_time {
+            query.push(("dateTime", format_date_time(date_time)));
+        }
+        if let Some(max_journeys) = self.max_journeys {
+            query.push(("maxJourneys", max_journeys.to_string()));
+        }
+
+        query
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisruptionKind {
+    Calamity,
+    Disruption,
+    Maintenance,
+}
+
+impl DisruptionKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DisruptionKind::Calamity => "calamity",
+            DisruptionKind::Disruption => "disruption",
+            DisruptionKind::Maintenance => "maintenance",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DisruptionArguments {
+    pub is_active: Option<bool>,
+    /// Kinds to include, all kinds when empty
+    pub kinds: Vec<DisruptionKind>,
+}
+
+impl DisruptionArguments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn active(mut self, is_active: bool) -> Self {
+        self.is_active = Some(is_active);
+        self
+    }
+
+    pub fn kind(mut self, kind: DisruptionKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    pub(crate) fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![];
+
+        if let Some(is_active) = self.is_active {
+            query.push(("isActive", is_active.to_string()));
+        }
+        if !self.kinds.is_empty() {
+            let kinds: Vec<_> = self.kinds.iter().map(DisruptionKind::as_str).collect();
+            query.push(("type", kinds.join(",")));
+        }
+
+        query
+    }
+}
@@ -0,0 +1,397 @@
+//! Response data of the NS reisinformatie API
+//! Only fields relevant to us are modelled, unknown enum values deserialize into an `Unknown`/`Other` variant
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+/// NS formats times as `2024-01-02T10:08:00+0100`, without a colon in the offset
+mod date_time {
+    use chrono::{DateTime, FixedOffset};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y-%m-%dT%H:%M:%S%z";
+
+    pub fn serialize<S: Serializer>(
+        value: &DateTime<FixedOffset>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&value.format(FORMAT))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<FixedOffset>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        DateTime::parse_from_str(&value, FORMAT).map_err(serde::de::Error::custom)
+    }
+
+    pub mod optional {
+        use chrono::{DateTime, FixedOffset};
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            value: &Option<DateTime<FixedOffset>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<DateTime<FixedOffset>>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|value| DateTime::parse_from_str(&value, super::FORMAT))
+                .transpose()
+                .map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ProductKind {
+    Train,
+    Bus,
+    Tram,
+    Metro,
+    Ferry,
+    Walk,
+    Bike,
+    Car,
+    Taxi,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Product {
+    #[serde(rename = "type")]
+    pub kind: ProductKind,
+    pub number: Option<String>,
+    pub category_code: Option<String>,
+    pub short_category_name: Option<String>,
+    pub long_category_name: Option<String>,
+    pub operator_code: Option<String>,
+    pub operator_name: Option<String>,
+    pub display_name: Option<String>,
+}
+
+impl Product {
+    pub fn get_number(&self) -> Option<&str> {
+        match self.kind {
+            ProductKind::Walk => None,
+            _ => self.number.as_deref(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LocationKind {
+    #[serde(rename_all = "camelCase")]
+    Station {
+        station_code: String,
+        uic_code: Option<String>,
+    },
+    Address,
+    #[serde(other)]
+    Other,
+}
+
+/// Origin or destination of a leg, times and tracks are only present for stations
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub name: String,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    #[serde(flatten)]
+    pub kind: LocationKind,
+    #[serde(default, with = "date_time::optional")]
+    pub planned_date_time: Option<DateTime<FixedOffset>>,
+    #[serde(default, with = "date_time::optional")]
+    pub actual_date_time: Option<DateTime<FixedOffset>>,
+    pub planned_track: Option<String>,
+    pub actual_track: Option<String>,
+}
+
+impl Location {
+    pub fn get_code(&self) -> Option<&str> {
+        match &self.kind {
+            LocationKind::Station { station_code, .. } => Some(station_code),
+            LocationKind::Address | LocationKind::Other => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum LegKind {
+    Walk,
+    PublicTransit,
+    Transfer,
+    Bike,
+    Car,
+    Kiss,
+    Taxi,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CrowdForecast {
+    Low,
+    Medium,
+    High,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferMessage {
+    pub message: String,
+    pub accessibility_message: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Leg {
+    pub name: Option<String>,
+    pub direction: Option<String>,
+    pub origin: Location,
+    pub destination: Location,
+    pub product: Product,
+    #[serde(rename = "travelType")]
+    pub travel_type: LegKind,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub part_cancelled: bool,
+    pub crowd_forecast: Option<CrowdForecast>,
+    pub planned_duration_in_minutes: Option<u32>,
+    pub distance_in_meters: Option<u32>,
+    #[serde(default)]
+    pub transfer_messages: Vec<TransferMessage>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TripStatus {
+    Normal,
+    Cancelled,
+    ChangeNotPossible,
+    ChangeCouldBePossible,
+    AlternativeTransport,
+    Disruption,
+    Maintenance,
+    #[serde(other)]
+    Other,
+}
+
+/// Disruption or maintenance message attached to a trip
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TripMessage {
+    pub id: Option<String>,
+    pub head: Option<String>,
+    pub text: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trip {
+    pub uid: Option<String>,
+    pub planned_duration_in_minutes: Option<u32>,
+    pub actual_duration_in_minutes: Option<u32>,
+    pub transfers: Option<u32>,
+    pub status: Option<TripStatus>,
+    pub crowd_forecast: Option<CrowdForecast>,
+    #[serde(default)]
+    pub messages: Vec<TripMessage>,
+    pub legs: Vec<Leg>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+pub struct Response {
+    pub trips: Vec<Trip>,
+}
+
+/// Message shown with a departure or arrival
+#[derive(Deserialize, Debug, Serialize)]
+pub struct ServiceMessage {
+    pub message: String,
+    pub style: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteStation {
+    pub uic_code: String,
+    pub medium_name: String,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Departure {
+    pub direction: String,
+    pub name: Option<String>,
+    #[serde(with = "date_time")]
+    pub planned_date_time: DateTime<FixedOffset>,
+    #[serde(default, with = "date_time::optional")]
+    pub actual_date_time: Option<DateTime<FixedOffset>>,
+    pub planned_track: Option<String>,
+    pub actual_track: Option<String>,
+    pub product: Product,
+    pub train_category: Option<String>,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub route_stations: Vec<RouteStation>,
+    #[serde(default)]
+    pub messages: Vec<ServiceMessage>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Arrival {
+    pub origin: String,
+    pub name: Option<String>,
+    #[serde(with = "date_time")]
+    pub planned_date_time: DateTime<FixedOffset>,
+    #[serde(default, with = "date_time::optional")]
+    pub actual_date_time: Option<DateTime<FixedOffset>>,
+    pub planned_track: Option<String>,
+    pub actual_track: Option<String>,
+    pub product: Product,
+    pub train_category: Option<String>,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub messages: Vec<ServiceMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct DeparturesPayload {
+    pub departures: Vec<Departure>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ArrivalsPayload {
+    pub arrivals: Vec<Arrival>,
+}
+
+/// The departures and arrivals endpoints wrap their data in a `payload` object
+#[derive(Deserialize, Debug)]
+pub(crate) struct Payload<T> {
+    pub payload: T,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+pub struct Label {
+    pub label: String,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdditionalTravelTime {
+    pub label: String,
+    pub minimum_duration_in_minutes: Option<u32>,
+    pub maximum_duration_in_minutes: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timespan {
+    #[serde(default, with = "date_time::optional")]
+    pub start: Option<DateTime<FixedOffset>>,
+    #[serde(default, with = "date_time::optional")]
+    pub end: Option<DateTime<FixedOffset>>,
+    pub period: Option<String>,
+    pub situation: Option<Label>,
+    pub cause: Option<Label>,
+    pub additional_travel_time: Option<AdditionalTravelTime>,
+    #[serde(default)]
+    pub advices: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionStation {
+    pub station_code: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+pub struct Section {
+    pub stations: Vec<SectionStation>,
+    pub direction: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+pub struct PublicationSection {
+    pub section: Section,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpectedDuration {
+    pub description: Option<String>,
+    #[serde(default, with = "date_time::optional")]
+    pub end_time: Option<DateTime<FixedOffset>>,
+}
+
+/// A (planned) disruption affecting one or more sections of track
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionDisruption {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub is_active: bool,
+    #[serde(default, with = "date_time::optional")]
+    pub start: Option<DateTime<FixedOffset>>,
+    #[serde(default, with = "date_time::optional")]
+    pub end: Option<DateTime<FixedOffset>>,
+    pub period: Option<String>,
+    #[serde(default)]
+    pub timespans: Vec<Timespan>,
+    #[serde(default)]
+    pub publication_sections: Vec<PublicationSection>,
+    pub expected_duration: Option<ExpectedDuration>,
+}
+
+/// A large scale disruption not tied to specific sections of track
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calamity {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub is_active: bool,
+    #[serde(default, with = "date_time::optional")]
+    pub last_updated: Option<DateTime<FixedOffset>>,
+    #[serde(default, with = "date_time::optional")]
+    pub expire_time: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Deserialize, Debug, Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Disruption {
+    Calamity(Calamity),
+    Disruption(SectionDisruption),
+    Maintenance(SectionDisruption),
+    #[serde(other)]
+    Other,
+}
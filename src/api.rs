@@ -1,14 +1,16 @@
 pub mod datarepo;
 
-use std::{collections::HashSet, fs, path::Path, sync::Arc};
+use std::{borrow::Cow, collections::HashSet, fs, path::Path, sync::Arc, time::Duration};
 
 use active_rides_timespan::active_rides_in_timespan_endpoint;
 use anyhow::{anyhow, Ok};
+use chrono::{DateTime, FixedOffset, NaiveDate};
 
 use company_map::company_endpoint;
-use find_path_endpoint::route_finding_endpoint;
+use errorresponse::InvalidArgument;
+use find_path_endpoint::{route_finding_endpoint, route_finding_stats_endpoint};
 use location_map::location_map_endpoint;
-use ns_api::NsApi;
+use ns_api::{NsApi, TripAdviceArguments};
 use poem::{
     endpoint::StaticFileEndpoint,
     get,
@@ -16,6 +18,7 @@ use poem::{
     middleware::{AddData, CatchPanic, Cors},
     EndpointExt, Route, Server,
 };
+use ride_geojson::ride_geojson_endpoint;
 use serde::{ser::SerializeStruct, Deserialize, Serialize};
 use tokio::sync::mpsc;
 
@@ -26,23 +29,100 @@ mod company_map;
 mod errorresponse;
 mod find_path_endpoint;
 mod location_map;
+mod rate_limit;
+mod ride_geojson;
+mod ttl_cache;
 
 use crate::{
     api::{active_rides::active_rides_endpoint, all_rides::all_rides_endpoint},
+    dayoffset::DayOffset,
     fetch,
-    iff::{Leg, LegKind, Record, Ride, StopKind},
+    iff::{Leg, LegKind, PlatformInfo, Record, Ride, StopKind},
+    realtime::{self, RideRealtime, StopRealtime},
     AppConfig,
 };
 
-use self::datarepo::DataRepo;
+use self::{datarepo::DataRepo, rate_limit::RateLimiter, ttl_cache::TtlCache};
+
+/// Route planner responses by the arguments they were requested with
+type TripCache = TtlCache<TripAdviceArguments, Arc<ns_api::Response>>;
 
 pub struct ApiObject<'a, T: ?Sized> {
     inner: &'a T,
+    repo: &'a DataRepo,
+    realtime: Option<Cow<'a, RideRealtime>>,
+    /// Date of the ride the object belongs to, if known
+    date: Option<NaiveDate>,
 }
 
 pub trait IntoAPIObject {
-    fn as_api_object(&self) -> ApiObject<'_, Self> {
-        ApiObject { inner: self }
+    fn as_api_object<'a>(&'a self, repo: &'a DataRepo) -> ApiObject<'a, Self> {
+        ApiObject {
+            inner: self,
+            repo,
+            realtime: None,
+            date: None,
+        }
+    }
+}
+
+impl<'a> ApiObject<'a, Ride> {
+    /// Overlays the realtime state and platforms of the ride on `date`
+    pub fn on_date(mut self, date: NaiveDate) -> Self {
+        self.realtime = self.repo.ride_realtime(self.inner, date).map(Cow::Owned);
+        self.date = Some(date);
+        self
+    }
+}
+
+impl<'a> ApiObject<'a, Leg> {
+    /// Start and end time with realtime delays applied, None without realtime information
+    fn actual_times(&self) -> Option<(DayOffset, DayOffset)> {
+        let realtime = self.realtime.as_deref()?;
+
+        let (from, to) = (
+            realtime.stop(self.inner.from_stop),
+            realtime.stop(self.inner.to_stop),
+        );
+        let (start_delay, end_delay) = match &self.inner.kind {
+            LegKind::Stationary(_, _) => (
+                from.and_then(StopRealtime::delay_on_arrival),
+                from.and_then(StopRealtime::delay_on_departure),
+            ),
+            LegKind::Moving { .. } => (
+                from.and_then(StopRealtime::delay_on_departure),
+                to.and_then(StopRealtime::delay_on_arrival),
+            ),
+        };
+
+        Some((
+            self.inner
+                .start
+                .offset_by_seconds(start_delay.unwrap_or_default()),
+            self.inner
+                .end
+                .offset_by_seconds(end_delay.unwrap_or_default()),
+        ))
+    }
+
+    /// Platforms of a stationary leg, the variant in effect on the date of the ride when it is known
+    fn platform_info(&self) -> Option<&PlatformInfo> {
+        let platforms = self.inner.kind.platforms();
+
+        match self.date {
+            Some(date) => self.repo.platforms_on(platforms, date),
+            None => platforms.first(),
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.realtime.as_deref().is_some_and(|realtime| {
+            realtime.cancelled
+                || !self.inner.kind.is_moving()
+                    && realtime
+                        .stop(self.inner.from_stop)
+                        .is_some_and(|stop| stop.cancelled)
+        })
     }
 }
 
@@ -60,20 +140,39 @@ fn stopkind_to_num(stop_kind: &StopKind) -> u8 {
     }
 }
 
-impl<'a, 'b> Serialize for ApiObject<'a, Leg> {
+impl<'a> Serialize for ApiObject<'a, Leg> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut leg = serializer.serialize_struct("leg", 9)?;
+        let actual_times = self.actual_times();
+
+        let platform = self.platform_info();
+
+        let mut leg = serializer.serialize_struct("leg", 15)?;
         leg.serialize_field("timeStart", &self.inner.start)?;
         leg.serialize_field("timeEnd", &self.inner.end)?;
+        leg.serialize_field("actualTimeStart", &actual_times.map(|times| times.0))?;
+        leg.serialize_field("actualTimeEnd", &actual_times.map(|times| times.1))?;
+        leg.serialize_field("cancelled", &self.is_cancelled())?;
         leg.serialize_field("moving", &self.inner.kind.is_moving())?;
         leg.serialize_field("waypoints", self.inner.kind.waypoints().unwrap_or(&vec![]))?;
         leg.serialize_field("from", &self.inner.kind.from())?;
         leg.serialize_field("to", &self.inner.kind.to())?;
+        leg.serialize_field("links", &self.repo.leg_links(&self.inner.kind))?;
         leg.serialize_field("stationCode", &self.inner.kind.station_code())?;
-        leg.serialize_field("platform", &self.inner.kind.platform_info())?;
+        leg.serialize_field(
+            "platformVariesByDay",
+            &(self.inner.kind.platforms().len() > 1),
+        )?;
+        leg.serialize_field(
+            "arrivalPlatform",
+            &platform.and_then(|p| p.arrival_platform.as_ref()),
+        )?;
+        leg.serialize_field(
+            "departurePlatform",
+            &platform.and_then(|p| p.departure_platform.as_ref()),
+        )?;
 
         let stoptype = match &self.inner.kind {
             LegKind::Stationary(_, stop_kind) => Some(stopkind_to_num(stop_kind)),
@@ -107,7 +206,7 @@ impl<'a> Serialize for ApiObject<'a, Record> {
                 .inner
                 .generate_legs()
                 .iter()
-                .map(|l| l.as_api_object())
+                .map(|l| l.as_api_object(self.repo))
                 .collect::<Vec<_>>(),
         )?;
         record.end()
@@ -119,7 +218,7 @@ impl<'a> Serialize for ApiObject<'a, Ride> {
     where
         S: serde::Serializer,
     {
-        let mut ride = serializer.serialize_struct("ride", 7)?;
+        let mut ride = serializer.serialize_struct("ride", 8)?;
         ride.serialize_field("id", &self.inner.id)?;
         ride.serialize_field("transit_type", &self.inner.transit_mode)?;
         ride.serialize_field("operator", &self.inner.operator)?;
@@ -128,13 +227,24 @@ impl<'a> Serialize for ApiObject<'a, Ride> {
         ride.serialize_field("distance", &0)?;
         ride.serialize_field("dayValidity", &0)?;
         ride.serialize_field("id", &self.inner.id)?;
+        ride.serialize_field(
+            "cancelled",
+            &self
+                .realtime
+                .as_deref()
+                .is_some_and(|realtime| realtime.cancelled),
+        )?;
         ride.serialize_field(
             "legs",
             &self
                 .inner
                 .generate_legs()
                 .iter()
-                .map(|l| l.as_api_object())
+                .map(|l| ApiObject {
+                    realtime: self.realtime.as_deref().map(Cow::Borrowed),
+                    date: self.date,
+                    ..l.as_api_object(self.repo)
+                })
                 .collect::<Vec<_>>(),
         )?;
         ride.end()
@@ -148,13 +258,19 @@ const HTTP_CACHE_LINK_PATH: &str = "links.json";
 pub fn serve(config: &AppConfig, autofetch: bool) -> Result<(), anyhow::Error> {
     if autofetch {
         println!("Autofetching...");
-        fetch::fetch(&config.cache_dir, config.ns_api_key.as_deref())?;
+        fetch::fetch(
+            &config.cache_dir,
+            config.ns_api_key.as_deref(),
+            &config.upstream,
+            &config.cache_retention,
+            &config.timetable,
+        )?;
         println!("Done autofetching")
     }
     println!("Starting serve...");
 
     let http_dir = config.cache_dir.join(HTTP_CACHE_SUBDIR);
-    let mut data = datarepo::DataRepo::new(&config.cache_dir);
+    let mut data = datarepo::DataRepo::load(&config.cache_dir, &config.timetable)?;
     data.filter_unknown_legs();
 
     prepare_files(&data, &http_dir)?;
@@ -164,7 +280,10 @@ pub fn serve(config: &AppConfig, autofetch: bool) -> Result<(), anyhow::Error> {
         .as_ref()
         .ok_or(anyhow!("NS API key missing!"))?;
 
-    let ns_api = ns_api::NsApi::new(ns_key.to_owned());
+    let ns_api = ns_api::NsApi::new(ns_key.to_owned())
+        .host(&config.upstream.ns_api_host)
+        .timeout(Duration::from_secs(config.route_finding.upstream_timeout))
+        .retries(config.route_finding.upstream_retries);
 
     //Health check
     let timetable_tz = chrono_tz::Europe::Amsterdam;
@@ -205,63 +324,162 @@ struct RoutePlannerTrip {
 }
 
 #[derive(Serialize)]
-struct RoutePlannerLeg {
-    from: String,
-    to: String,
-    id: String,
+#[serde(tag = "type", rename_all = "camelCase")]
+enum RoutePlannerLocation {
+    Station {
+        code: String,
+    },
+    /// Addresses and other places that aren't a station
+    Place {
+        name: String,
+        lat: Option<f64>,
+        lng: Option<f64>,
+    },
+}
+
+impl From<&ns_api::response_data::Location> for RoutePlannerLocation {
+    fn from(location: &ns_api::response_data::Location) -> Self {
+        match location.get_code() {
+            Some(code) => Self::Station {
+                code: code.to_owned(),
+            },
+            None => Self::Place {
+                name: location.name.clone(),
+                lat: location.lat,
+                lng: location.lng,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum RoutePlannerLeg {
+    #[serde(rename_all = "camelCase")]
+    PublicTransit {
+        from: RoutePlannerLocation,
+        to: RoutePlannerLocation,
+        id: Option<String>,
+        /// Timetable date of the ride
+        date: Option<NaiveDate>,
+        /// Index into `RoutePlannerResponse::rides`, None if the ride isn't in our timetable
+        ride: Option<usize>,
+        cancelled: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    Walk {
+        from: RoutePlannerLocation,
+        to: RoutePlannerLocation,
+        duration_minutes: Option<u32>,
+        distance_meters: Option<u32>,
+    },
+    /// Bike, taxi and other legs we don't know any details of
+    #[serde(rename_all = "camelCase")]
+    Other {
+        travel_type: ns_api::response_data::LegKind,
+        from: RoutePlannerLocation,
+        to: RoutePlannerLocation,
+        duration_minutes: Option<u32>,
+    },
 }
 
 impl<'a> RoutePlannerResponse<'a> {
     pub fn new(res: &ns_api::Response, repo: &'a datarepo::DataRepo) -> Self {
-        let now = chrono::Utc::now().with_timezone(&chrono_tz::Europe::Amsterdam);
-        let trips: Vec<_> = res
+        let mut rides: Vec<(&Ride, NaiveDate)> = vec![];
+
+        let trips = res
             .trips
             .iter()
-            .filter(|trip| {
-                trip.legs.iter().all(|leg| {
-                    matches!(
-                        leg.travel_type,
-                        ns_api::response_data::LegKind::PublicTransit
-                    )
-                })
-            })
             .map(|trip| RoutePlannerTrip {
                 legs: trip
                     .legs
                     .iter()
-                    .map(|leg| RoutePlannerLeg {
-                        from: leg.origin.get_code().unwrap().to_owned(),
-                        to: leg.destination.get_code().unwrap().to_owned(),
-                        id: leg.product.get_number().unwrap().to_owned(),
-                    })
+                    .map(|leg| Self::leg(leg, repo, &mut rides))
                     .collect(),
             })
             .collect();
 
-        let trip_ids: HashSet<_> = trips
-            .iter()
-            .flat_map(|trip| &trip.legs)
-            .map(|leg| &leg.id)
-            .collect();
-
         Self {
-            rides: repo
-                .rides()
-                .iter()
-                .filter(|r| repo.is_ride_valid(r.day_validity, now.date_naive()))
-                .filter(|ride| trip_ids.contains(&ride.id))
-                .map(|r| r.as_api_object())
+            rides: rides
+                .into_iter()
+                .map(|(ride, date)| ride.as_api_object(repo).on_date(date))
                 .collect(),
-            // rides: vec![],
             trips,
         }
     }
+
+    fn leg(
+        leg: &ns_api::response_data::Leg,
+        repo: &'a datarepo::DataRepo,
+        rides: &mut Vec<(&'a Ride, NaiveDate)>,
+    ) -> RoutePlannerLeg {
+        use ns_api::response_data::LegKind as NsLegKind;
+
+        let from = RoutePlannerLocation::from(&leg.origin);
+        let to = RoutePlannerLocation::from(&leg.destination);
+
+        match leg.travel_type {
+            NsLegKind::PublicTransit => {
+                let id = leg.product.get_number();
+                let departure = leg.origin.planned_date_time.map(|time| {
+                    time.with_timezone(&chrono_tz::Europe::Amsterdam)
+                        .naive_local()
+                });
+
+                let matched = match (id, leg.origin.get_code(), departure) {
+                    (Some(id), Some(station), Some(departure)) => {
+                        repo.find_ride(id, station, departure)
+                    }
+                    _ => None,
+                };
+
+                let ride = matched.map(|(ride, date)| {
+                    rides
+                        .iter()
+                        .position(|(r, d)| std::ptr::eq(*r, ride) && *d == date)
+                        .unwrap_or_else(|| {
+                            rides.push((ride, date));
+                            rides.len() - 1
+                        })
+                });
+
+                RoutePlannerLeg::PublicTransit {
+                    from,
+                    to,
+                    id: id.map(str::to_owned),
+                    date: matched
+                        .map(|(_, date)| date)
+                        .or(departure.map(|departure| departure.date())),
+                    ride,
+                    cancelled: leg.cancelled,
+                }
+            }
+            NsLegKind::Walk | NsLegKind::Transfer => RoutePlannerLeg::Walk {
+                from,
+                to,
+                duration_minutes: leg.planned_duration_in_minutes,
+                distance_meters: leg.distance_in_meters,
+            },
+            travel_type => RoutePlannerLeg::Other {
+                travel_type,
+                from,
+                to,
+                duration_minutes: leg.planned_duration_in_minutes,
+            },
+        }
+    }
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct PathfindingArguments {
     from: String,
     to: String,
+    via: Option<String>,
+    /// RFC 3339 departure time, or arrival time with `searchForArrival`. Defaults to now
+    date_time: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    search_for_arrival: bool,
 }
 
 impl PathfindingArguments {
@@ -269,13 +487,31 @@ impl PathfindingArguments {
         s.len() < 50 && stations.contains(s)
     }
 
-    pub fn validate(&self, stations: &Arc<HashSet<Box<str>>>) {
-        let val_a = Self::validate_string(&self.from, stations);
-        let val_b = Self::validate_string(&self.to, stations);
+    pub fn validate(&self, stations: &Arc<HashSet<Box<str>>>) -> Result<(), InvalidArgument> {
+        let from = Self::validate_string(&self.from, stations);
+        let to = Self::validate_string(&self.to, stations);
+        let via = self
+            .via
+            .as_deref()
+            .is_none_or(|via| Self::validate_string(via, stations));
+
+        (from && to && via)
+            .then_some(())
+            .ok_or(InvalidArgument("Unknown station"))
+    }
+
+    pub fn trip_advice_arguments(&self) -> TripAdviceArguments {
+        let mut args = TripAdviceArguments::new(&self.from, &self.to)
+            .search_for_arrival(self.search_for_arrival);
 
-        if !val_a || !val_b {
-            panic!("Queries don't pass")
+        if let Some(via) = &self.via {
+            args = args.via(via);
         }
+        if let Some(date_time) = self.date_time {
+            args = args.date_time(date_time);
+        }
+
+        args
     }
 }
 
@@ -304,9 +540,34 @@ async fn start_server(
         .map(|station| station.code.to_lowercase().into_boxed_str())
         .collect();
 
+    let trip_cache: Arc<TripCache> = Arc::new(TtlCache::new(
+        Duration::from_secs(config.route_finding.cache_ttl),
+        config.route_finding.cache_capacity,
+    ));
+    let rate_limiter = Arc::new(
+        RateLimiter::new(
+            config.route_finding.requests_per_minute,
+            Duration::from_secs(60),
+        )
+        .trusted_proxies(config.route_finding.trusted_proxies.clone()),
+    );
+
     let cors = Cors::new().allow_origin(&config.cors_domain);
     let catch_panic = CatchPanic::new();
 
+    let data = Arc::new(data);
+
+    if let Some(source) = &config.realtime {
+        let source = source.clone();
+        let data = data.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = realtime::ingest(source, data).await {
+                eprintln!("Realtime ingestion stopped: {e}");
+            }
+        });
+    }
+
     let app = Route::new()
         .at("/data/stations.json", get(stations_endpoint))
         .at("/data/links.json", get(links_endpoint))
@@ -318,12 +579,16 @@ async fn start_server(
             get(active_rides_in_timespan_endpoint),
         )
         .at("/api/find_route", get(route_finding_endpoint))
+        .at("/api/find_route/stats", get(route_finding_stats_endpoint))
         .at("/api/rides_all", get(all_rides_endpoint))
+        .at("/api/ride_geojson", get(ride_geojson_endpoint))
         .with(catch_panic)
         .with(cors)
-        .with(AddData::new(Arc::new(data)))
+        .with(AddData::new(data))
         .with(AddData::new(Arc::new(ns_api)))
-        .with(AddData::new(Arc::new(station_allowlist)));
+        .with(AddData::new(Arc::new(station_allowlist)))
+        .with(AddData::new(trip_cache))
+        .with(AddData::new(rate_limiter));
 
     let server = Server::new(TcpListener::bind(&config.bind_addr));
 
@@ -345,3 +610,198 @@ async fn start_server(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        fs,
+        path::Path,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use ns_api::NsApi;
+    use poem::{
+        endpoint::make_sync,
+        get,
+        http::StatusCode,
+        listener::{Acceptor, Listener, TcpListener},
+        middleware::AddData,
+        Endpoint, EndpointExt, Request, Route, Server,
+    };
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use testresult::TestResult;
+
+    use super::{
+        datarepo::TimetableSource, find_path_endpoint::route_finding_endpoint,
+        rate_limit::RateLimiter, ttl_cache::TtlCache, DataRepo, RoutePlannerResponse, TripCache,
+    };
+    use crate::iff::test_fixtures::{standin_cache_dir, TempDir, STANDIN_FIXTURES as FIXTURES};
+
+    fn standin_repo(name: &str) -> Result<(DataRepo, TempDir), Box<dyn std::error::Error>> {
+        let cache_dir = standin_cache_dir(&format!("api-{name}"))?;
+        let repo = DataRepo::new(&cache_dir, &TimetableSource::default())?;
+
+        Ok((repo, cache_dir))
+    }
+
+    #[test]
+    fn walk_legs_are_kept() -> TestResult {
+        let (repo, _cache_dir) = standin_repo("walk-legs")?;
+        let trips: ns_api::Response =
+            serde_json::from_slice(&fs::read(Path::new(FIXTURES).join("trips.json"))?)?;
+
+        let response = serde_json::to_value(RoutePlannerResponse::new(&trips, &repo))?;
+        let legs = &response["trips"][0]["legs"];
+
+        assert_eq!(legs[0]["type"], "publicTransit");
+        assert_eq!(legs[0]["id"], "2871");
+        assert_eq!(legs[0]["date"], "2024-01-02");
+        assert_eq!(legs[0]["ride"], 0);
+        assert_eq!(response["rides"][0]["id"], "2871");
+
+        assert_eq!(
+            legs[1],
+            json!({
+                "type": "walk",
+                "from": { "type": "station", "code": "GD" },
+                "to": {
+                    "type": "place",
+                    "name": "Markt 1, Gouda",
+                    "lat": legs[1]["to"]["lat"],
+                    "lng": legs[1]["to"]["lng"],
+                },
+                "durationMinutes": 9,
+                "distanceMeters": 720,
+            })
+        );
+        assert!(legs[1]["to"]["lat"].is_f64());
+
+        Ok(())
+    }
+
+    type Queries = Arc<Mutex<Vec<String>>>;
+
+    /// Stand-in NS API that records the query of every trip request
+    fn recording_upstream(
+        runtime: &tokio::runtime::Runtime,
+    ) -> Result<(NsApi, Queries), Box<dyn std::error::Error>> {
+        let trips = fs::read_to_string(Path::new(FIXTURES).join("trips.json"))?;
+        let queries = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = queries.clone();
+        let app = Route::new().at(
+            format!("/{}", ns_api::TRIP_PATH),
+            make_sync(move |req: Request| {
+                let query = req.uri().query().unwrap_or_default().to_owned();
+                recorded.lock().unwrap().push(query);
+                trips.clone()
+            }),
+        );
+
+        let acceptor = runtime.block_on(TcpListener::bind("127.0.0.1:0").into_acceptor())?;
+        let addr = acceptor.local_addr()[0]
+            .as_socket_addr()
+            .copied()
+            .ok_or("upstream to listen on a socket")?;
+        runtime.spawn(Server::new_with_acceptor(acceptor).run(app));
+
+        let ns_api = NsApi::new("standin".to_owned())
+            .host(format!("http://{addr}/"))
+            .retries(0);
+
+        Ok((ns_api, queries))
+    }
+
+    fn route_finding_app(repo: DataRepo, ns_api: NsApi) -> impl Endpoint {
+        let station_allowlist: HashSet<Box<str>> = repo
+            .stations()
+            .iter()
+            .map(|station| station.code.to_lowercase().into_boxed_str())
+            .collect();
+        let trip_cache: Arc<TripCache> = Arc::new(TtlCache::new(Duration::from_secs(60), 10));
+
+        Route::new()
+            .at("/api/find_route", get(route_finding_endpoint))
+            .with(AddData::new(Arc::new(repo)))
+            .with(AddData::new(Arc::new(ns_api)))
+            .with(AddData::new(Arc::new(station_allowlist)))
+            .with(AddData::new(trip_cache))
+            .with(AddData::new(Arc::new(RateLimiter::new(
+                100,
+                Duration::from_secs(60),
+            ))))
+    }
+
+    fn find_route(
+        runtime: &tokio::runtime::Runtime,
+        app: &impl Endpoint,
+        query: &str,
+    ) -> Result<(StatusCode, String), Box<dyn std::error::Error>> {
+        let request = Request::builder()
+            .uri(format!("/api/find_route?{query}").parse()?)
+            .finish();
+        let response = runtime.block_on(app.get_response(request));
+        let status = response.status();
+        let body = runtime.block_on(response.into_body().into_string())?;
+
+        Ok((status, body))
+    }
+
+    #[test]
+    fn pathfinding_arguments_are_forwarded() -> TestResult {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let (ns_api, queries) = recording_upstream(&runtime)?;
+        let (repo, _cache_dir) = standin_repo("forwarded")?;
+        let app = route_finding_app(repo, ns_api);
+
+        let (status, _) = find_route(&runtime, &app, "from=ut&to=gd")?;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = find_route(
+            &runtime,
+            &app,
+            "from=ut&to=gd&via=wd&dateTime=2024-01-02T10:00:00%2B01:00&searchForArrival=true",
+        )?;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"trips\""));
+
+        assert_eq!(
+            *queries.lock().unwrap(),
+            [
+                "fromStation=ut&toStation=gd",
+                "fromStation=ut&toStation=gd&viaStation=wd\
+                &dateTime=2024-01-02T10%3A00%3A00%2B01%3A00&searchForArrival=true",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_pathfinding_arguments_are_rejected() -> TestResult {
+        let runtime = tokio::runtime::Runtime::new()?;
+        let (ns_api, queries) = recording_upstream(&runtime)?;
+        let (repo, _cache_dir) = standin_repo("rejected")?;
+        let app = route_finding_app(repo, ns_api);
+
+        assert_eq!(
+            find_route(&runtime, &app, "from=ut&to=gd&via=xx")?,
+            (StatusCode::BAD_REQUEST, "Unknown station".to_owned())
+        );
+        for query in [
+            "from=ut&to=gd&dateTime=tomorrow",
+            "from=ut&to=gd&dateTime=2024-01-02T10:00:00",
+            "from=ut&to=gd&searchForArrival=maybe",
+        ] {
+            let (status, _) = find_route(&runtime, &app, query)?;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+
+        assert!(queries.lock().unwrap().is_empty());
+
+        Ok(())
+    }
+}
@@ -18,7 +18,7 @@ pub fn active_rides_endpoint(data: Data<&Arc<DataRepo>>, _req: String) -> Respon
         .as_ref()
         .rides_active_at_time(&now.naive_local().time(), &now.date_naive())
         .iter()
-        .map(|r| r.as_api_object())
+        .map(|r| r.as_api_object(&data).on_date(now.date_naive()))
         .collect();
 
     let data = serde_json::to_vec(&rides);
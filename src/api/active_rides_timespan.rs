@@ -20,7 +20,7 @@ pub fn active_rides_in_timespan_endpoint(data: Data<&Arc<DataRepo>>, _req: Strin
             &start.date_naive(),
         )
         .iter()
-        .map(|r| r.as_api_object())
+        .map(|r| r.as_api_object(&data).on_date(start.date_naive()))
         .collect();
 
     let data = serde_json::to_vec(&rides).unwrap();
@@ -14,7 +14,7 @@ pub fn all_rides_endpoint(data: Data<&Arc<DataRepo>>, _req: String) -> Response
         .as_ref()
         .rides_active_on_date(&now.date_naive())
         .iter()
-        .map(|r| r.as_api_object())
+        .map(|r| r.as_api_object(&data).on_date(now.date_naive()))
         .collect();
 
     let data = serde_json::to_vec(&rides).unwrap();
@@ -1,73 +1,260 @@
 use std::{
     collections::{HashMap, HashSet},
-    fmt::Display,
     fs::File,
     hash::Hash,
+    io::BufReader,
     iter,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
 };
 
-use chrono::{NaiveDate, NaiveTime};
-mod links;
-mod stations;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+pub mod links;
+pub mod quality;
+mod ride_index;
+pub mod snapshot;
+pub mod stations;
+mod stop_index;
 use crate::{
-    api::datarepo::{links::extract_links, stations::extract_stations},
+    api::datarepo::{
+        links::{extract_links, straight_links},
+        ride_index::RideIndex,
+        stations::{extract_stations, read_extra_stations, resolve_unknown_stations},
+        stop_index::{StopIndex, StopRef},
+    },
+    cache::Cache,
     dayoffset::DayOffset,
     fetch::{ROUTE_FILEPATH, STATION_FILEPATH, TIMETABLE_PATH},
-    iff::{self, Company, Iff, Leg, LegKind, LocationCache, LocationCodeHandle, Record, Ride},
+    gtfs::{self, GtfsError},
+    iff::{
+        Company, Header, Iff, IffError, Leg, LegKind, LocationCache, LocationCodeHandle, ParseMode,
+        ParseOptions, PlatformInfo, Ride, RideValidity, TimetableData, TimetableEntry,
+    },
+    realtime::{RealtimeState, RideRealtime, StopRealtime},
 };
 
-use self::{links::Link, stations::Station};
+use self::{
+    links::{Coords2D, Link, LinkReference},
+    stations::Station,
+};
 
 // use super::ApiSerializationContext;
 
 /// A master container for all data, this is the struct eventually passed to the server
 pub struct DataRepo {
     links: Vec<Link>,
+    link_map: HashMap<LinkCode, Link>,
     stations: Vec<stations::Station>,
-    iff: Iff,
-    rides: Vec<iff::Ride>,
-    version: u64,
+    /// Loaded timetable versions, newest first
+    timetables: Vec<TimetableVersion>,
+    /// Locations of all timetable versions
+    locations: LocationCache,
+    realtime: RealtimeState,
 }
 
-/// Key to identify links, looking up links with the waypoint identifiers the wrong way around should return a corrected Link
-#[derive(Eq, Hash, PartialEq, Debug)]
-pub struct LinkCode(LocationCodeHandle, LocationCodeHandle);
+/// A single timetable delivery, its location handles refer to the location cache of the DataRepo
+#[derive(Serialize, Deserialize)]
+struct TimetableVersion {
+    header: Header,
+    rides: Vec<Ride>,
+    validity: RideValidity,
+    companies: Vec<Company>,
+    /// Refers to `rides` by index, rebuilt whenever they change
+    #[serde(skip)]
+    index: RideIndex,
+    #[serde(skip)]
+    stops: StopIndex,
+}
 
-#[derive(Hash, PartialEq, Eq)]
-pub enum MissingLinkReport {
-    NoRoute(LocationCodeHandle, LocationCodeHandle),
-    NoStation(LocationCodeHandle),
+impl TimetableVersion {
+    /// Splits off the location cache, which the rides refer to
+    fn from_data(data: TimetableData) -> (Self, LocationCache) {
+        let mut version = Self {
+            header: data.header,
+            rides: data.rides,
+            validity: data.validity,
+            companies: data.companies,
+            index: RideIndex::default(),
+            stops: StopIndex::default(),
+        };
+        version.reindex();
+
+        (version, data.locations)
+    }
+
+    fn reindex(&mut self) {
+        self.index = RideIndex::new(&self.rides, &self.validity);
+        self.stops = StopIndex::new(&self.rides);
+    }
+
+    /// The calls in `stops` of rides valid on `date`
+    fn calls_on<'a>(
+        &'a self,
+        stops: &'a [StopRef],
+        date: NaiveDate,
+    ) -> impl Iterator<Item = StationCall<'a>> {
+        stops
+            .iter()
+            .filter(move |call| self.index.is_valid_on(call.ride, date))
+            .map(|call| {
+                let ride = &self.rides[call.ride];
+
+                StationCall {
+                    ride,
+                    stop: &ride.timetable[call.stop],
+                    time: call.time,
+                }
+            })
+    }
+
+    fn rides_valid_on(&self, date: NaiveDate) -> impl Iterator<Item = &Ride> {
+        self.index.valid_on(date).map(|index| &self.rides[index])
+    }
+
+    /// Rides valid on `date` that start at or before `to` and end at or after `from`
+    fn rides_active(
+        &self,
+        date: NaiveDate,
+        from: DayOffset,
+        to: DayOffset,
+    ) -> impl Iterator<Item = &Ride> {
+        self.index
+            .active(date, from, to)
+            .into_iter()
+            .map(|index| &self.rides[index])
+    }
+}
+
+/// A scheduled call of a ride at a station, `time` is the departure or arrival time depending on the query
+#[derive(Debug, Clone, Copy)]
+pub struct StationCall<'a> {
+    pub ride: &'a Ride,
+    pub stop: &'a TimetableEntry,
+    pub time: DayOffset,
 }
 
-struct MissingLinkReportDisplay<'a, 'b> {
-    inner: &'a MissingLinkReport,
-    cache: &'b LocationCache,
+/// Where the timetable is loaded from
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum TimetableSource {
+    /// The NDOV IFF archive as fetched into the cache dir, combined with the NS station and route data
+    Iff {
+        /// Number of archived versions to load next to the active one, to serve dates it doesn't cover
+        #[serde(default)]
+        archived_versions: usize,
+        /// Lenient parsing skips and reports invalid records instead of failing to load
+        #[serde(default)]
+        parse_mode: ParseMode,
+        /// Parses the timetable file on all available cores instead of a single thread
+        #[serde(default)]
+        parallel_parsing: bool,
+        /// TOML file with positions of stations missing from the NS data, mostly foreign ones
+        #[serde(default)]
+        extra_stations: Option<PathBuf>,
+    },
+    /// A static GTFS archive, stations are taken from its stops and links are drawn as straight lines between them
+    Gtfs { path: PathBuf },
 }
 
-impl MissingLinkReport {
-    fn display<'a, 'b>(&'a self, cache: &'b LocationCache) -> MissingLinkReportDisplay<'a, 'b> {
-        MissingLinkReportDisplay { inner: self, cache }
+impl Default for TimetableSource {
+    fn default() -> Self {
+        Self::Iff {
+            archived_versions: 0,
+            parse_mode: ParseMode::Strict,
+            parallel_parsing: false,
+            extra_stations: None,
+        }
     }
 }
 
-impl<'a, 'b> Display for MissingLinkReportDisplay<'a, 'b> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.inner {
-            MissingLinkReport::NoRoute(from, to) => {
-                let from = self.cache.get_str(from).unwrap();
-                let to = self.cache.get_str(to).unwrap();
-                f.write_fmt(format_args!("no route from {} to {}", from, to))
-            }
-            MissingLinkReport::NoStation(code) => {
-                let name = self.cache.get_str(code).unwrap();
-                f.write_fmt(format_args!("no station code {name}"))
-            }
+impl TimetableSource {
+    fn parse_options(&self) -> ParseOptions {
+        match self {
+            Self::Iff {
+                parse_mode,
+                parallel_parsing,
+                ..
+            } => ParseOptions {
+                mode: *parse_mode,
+                threads: if *parallel_parsing {
+                    std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+                } else {
+                    NonZeroUsize::MIN
+                },
+            },
+            Self::Gtfs { .. } => ParseOptions::default(),
         }
     }
 }
 
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("Error opening {}: {source}", path.display())]
+    Open {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Error loading {}: {source}", path.display())]
+    Iff { path: PathBuf, source: IffError },
+    #[error("Error loading {}: {source}", path.display())]
+    Gtfs { path: PathBuf, source: GtfsError },
+    #[error("Error loading {}: {source}", path.display())]
+    ExtraStations {
+        path: PathBuf,
+        source: Box<figment::Error>,
+    },
+}
+
+fn open(path: &Path) -> Result<File, LoadError> {
+    File::open(path).map_err(|source| LoadError::Open {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Parses the IFF archive at `path`, reporting the records skipped in lenient mode
+fn parse_iff(path: &Path, options: ParseOptions) -> Result<Iff, LoadError> {
+    let iff = Iff::new_from_archive_with_options(&open(path)?, options).map_err(|source| {
+        LoadError::Iff {
+            path: path.to_owned(),
+            source,
+        }
+    })?;
+
+    if !iff.skipped().is_empty() {
+        eprintln!(
+            "Skipped {} invalid records in {}:",
+            iff.skipped().len(),
+            path.display()
+        );
+        for skipped in iff.skipped() {
+            eprintln!("  {skipped}");
+        }
+    }
+
+    Ok(iff)
+}
+
+/// Key to identify links, looking up links with the waypoint identifiers the wrong way around should return a corrected Link
+#[derive(Eq, Hash, PartialEq, Debug)]
+pub struct LinkCode(LocationCodeHandle, LocationCodeHandle);
+
+impl LinkCode {
+    fn reversed(&self) -> Self {
+        Self(self.1, self.0)
+    }
+}
+
+#[derive(Hash, PartialEq, Eq)]
+pub enum MissingLinkReport {
+    NoRoute(LocationCodeHandle, LocationCodeHandle),
+    NoStation(LocationCodeHandle),
+}
+
 trait LinkMap {
-    #[allow(dead_code)]
+    /// Finds the Link for the given code in either direction, the returned bool is true when the Link was found in reverse
     fn get_undirected(&self, code: &LinkCode) -> Option<(&Link, bool)>;
     fn contains_undirected(&self, code: &LinkCode) -> bool;
     #[allow(dead_code)]
@@ -75,24 +262,14 @@ trait LinkMap {
 }
 
 impl LinkMap for HashMap<LinkCode, Link> {
-    fn get_undirected(&self, _code: &LinkCode) -> Option<(&Link, bool)> {
-        // let normal = self.get(&make_link_code(a, b));
-        // if normal.is_some() {
-        //     return Some((normal.unwrap(), false));
-        // }
-
-        // let inverted = self.get(&make_link_code(b, a));
-        // if inverted.is_some() {
-        //     return Some((inverted.unwrap(), true));
-        // }
-
-        // None
-
-        todo!()
+    fn get_undirected(&self, code: &LinkCode) -> Option<(&Link, bool)> {
+        self.get(code)
+            .map(|link| (link, false))
+            .or_else(|| self.get(&code.reversed()).map(|link| (link, true)))
     }
 
     fn contains_undirected(&self, code: &LinkCode) -> bool {
-        self.contains_key(code) || self.contains_key(&LinkCode(code.1, code.0))
+        self.get_undirected(code).is_some()
     }
 
     fn contains_directed(&self, code: &LinkCode) -> bool {
@@ -119,38 +296,15 @@ fn leg_codes(leg: &LegKind) -> Option<Vec<LinkCode>> {
     }
 }
 
-fn leg_has_complete_data(
-    leg: &Leg,
-    station_codes: &HashSet<String>,
-    location_cache: &LocationCache,
-    links: &HashMap<LinkCode, Link>,
-) -> bool {
-    match &leg.kind {
-        LegKind::Stationary(location, _) => {
-            let code = location_cache.get_str(location).unwrap();
-            station_codes.contains(code)
-        }
-        LegKind::Moving {
-            from: _from,
-            to: _to,
-            waypoints: _waypoints,
-        } => leg_codes(&leg.kind)
-            .iter()
-            .all(|leg_code| leg_code.iter().all(|code| links.contains_undirected(code))),
+/// Appends `segment` to `path`, skipping the first point of `segment` when it duplicates the current end of `path`
+fn append_path(path: &mut Vec<Coords2D>, segment: Vec<Coords2D>) {
+    let mut segment = segment.into_iter().peekable();
+
+    if path.last().is_some() && path.last() == segment.peek() {
+        segment.next();
     }
-}
 
-fn report_missing(
-    record: &Record,
-    station_codes: &HashSet<String>,
-    location_cache: &LocationCache,
-    links: &HashMap<LinkCode, Link>,
-) -> Vec<MissingLinkReport> {
-    record
-        .generate_legs()
-        .iter()
-        .flat_map(|leg| report_missing_leg(leg, station_codes, location_cache, links))
-        .collect()
+    path.extend(segment);
 }
 
 fn report_missing_leg(
@@ -173,16 +327,44 @@ fn report_missing_leg(
     }
 }
 
-fn has_complete_data(
-    record: &Record,
+/// Indices of the first and last stop of the longest section of `ride` that has station data for every stop and links between all its points
+/// None when there's no such section with at least two stops
+fn known_section(
+    ride: &Ride,
     station_codes: &HashSet<String>,
     location_cache: &LocationCache,
     links: &HashMap<LinkCode, Link>,
-) -> bool {
-    record
-        .generate_legs()
-        .iter()
-        .all(|leg| leg_has_complete_data(leg, station_codes, location_cache, links))
+) -> Option<(usize, usize)> {
+    let timetable = &ride.timetable;
+    let mut longest: Option<(usize, usize)> = None;
+    let mut section_start = None;
+
+    for (index, entry) in timetable.iter().enumerate() {
+        let linked = index > 0
+            && links.contains_undirected(&LinkCode(timetable[index - 1].code, entry.code));
+        if !linked {
+            section_start = None;
+        }
+
+        if entry.stop_kind.is_waypoint() {
+            continue;
+        }
+
+        let is_known = location_cache
+            .get_str(&entry.code)
+            .is_some_and(|code| station_codes.contains(code));
+        if !is_known {
+            section_start = None;
+            continue;
+        }
+
+        let start = *section_start.get_or_insert(index);
+        if start < index && longest.is_none_or(|(first, last)| index - start > last - first) {
+            longest = Some((start, index));
+        }
+    }
+
+    longest
 }
 
 pub fn select_station_by_name<'a>(stations: &'a [Station], needle: &str) -> Option<&'a Station> {
@@ -200,164 +382,343 @@ pub fn select_station_by_name<'a>(stations: &'a [Station], needle: &str) -> Opti
         .filter(|(_, name)| name.contains(needle.as_str()))
         .collect();
 
-    return match candidate_matches.len() {
+    match candidate_matches.len() {
         0 => None,
         1 => candidate_matches
             .first()
             .map(|(station, _): &(&Station, String)| *station),
         _ => {
-            println!("Got plenty of matches, figure out some heuristics");
+            eprintln!("Got plenty of matches, figure out some heuristics");
 
             for station in candidate_matches.iter() {
-                println!("{}", station.0.name)
+                eprintln!("{}", station.0.name)
             }
 
             candidate_matches.first().map(|a| a.0)
         }
-    };
+    }
 }
 
-impl DataRepo {
-    pub fn new(cache_dir: &std::path::Path) -> Self {
-        let iff_file = File::open(cache_dir.join(TIMETABLE_PATH)).expect("To find timetable file");
+/// If any date of the delivery with `header` isn't served by a newer or equally recent version in `timetables`
+fn is_needed(header: &Header, timetables: &[TimetableVersion]) -> bool {
+    let newer: Vec<_> = timetables
+        .iter()
+        .map(|timetable| &timetable.header)
+        .filter(|other| other.cmp_recency(header).is_ge())
+        .collect();
 
-        let mut iff = Iff::new_from_archive(&iff_file)
-            .map_err(|e| println!("{e}"))
-            .expect("valid parse");
+    header
+        .first_valid_date
+        .iter_days()
+        .take_while(|date| *date <= header.last_valid_date)
+        .any(|date| !newer.iter().any(|other| other.covers_date(date)))
+}
 
-        let route_file = File::open(cache_dir.join(ROUTE_FILEPATH)).expect("To find route file");
-        let stations_file =
-            File::open(cache_dir.join(STATION_FILEPATH)).expect("To find stations file");
+impl DataRepo {
+    pub fn new(cache_dir: &Path, source: &TimetableSource) -> Result<Self, LoadError> {
+        let (mut timetable, mut stations, mut links) = match source {
+            TimetableSource::Iff { .. } => Self::load_iff(cache_dir, source.parse_options())?,
+            TimetableSource::Gtfs { path } => Self::load_gtfs(path)?,
+        };
 
-        let links: Vec<Link> = extract_links(&route_file, &mut iff.locations);
+        let iff_stations = std::mem::take(&mut timetable.stations);
+        let (timetable, mut locations) = TimetableVersion::from_data(timetable);
+        let mut timetables = vec![timetable];
 
-        let stations = extract_stations(&stations_file);
+        if let TimetableSource::Iff {
+            archived_versions,
+            extra_stations,
+            ..
+        } = source
+        {
+            Self::load_archived(
+                cache_dir,
+                *archived_versions,
+                source.parse_options(),
+                &mut timetables,
+                &mut locations,
+            );
 
-        let duration = iff
-            .timetable()
-            .header
-            .last_valid_date
-            .signed_duration_since(iff.timetable().header.first_valid_date);
+            let extra = match extra_stations {
+                Some(path) => {
+                    read_extra_stations(path).map_err(|source| LoadError::ExtraStations {
+                        path: path.clone(),
+                        source,
+                    })?
+                }
+                None => vec![],
+            };
+            let resolved =
+                resolve_unknown_stations(&mut stations, extra, &iff_stations, &locations);
 
-        println!(
-            "Timetable start date: {}",
-            iff.timetable().header.first_valid_date
-        );
-        println!(
-            "Timetable end date: {}",
-            iff.timetable().header.last_valid_date
-        );
-        println!("Day count: {}", duration.num_days());
-        println!("Version: {}", iff.header().version);
+            // Straight lines stand in for the routes the NS route data lacks, like those abroad
+            let rides = timetables.iter().flat_map(|timetable| &timetable.rides);
+            let synthetic = straight_links(rides, &stations, &locations, &links);
+            eprintln!(
+                "Resolved {resolved} stations and {} links without NS data",
+                synthetic.len()
+            );
+            links.extend(synthetic);
+        }
 
-        let rides: Vec<iff::Ride> = iff
-            .timetable()
-            .rides
+        let link_map = links
             .iter()
-            .flat_map(|r| r.split_on_ride_id())
+            .map(|link| (link.link_code(), link.clone()))
             .collect();
 
-        let version = iff.header().version;
-
-        Self {
-            rides,
+        let repo = Self {
             links,
+            link_map,
             stations,
-            // link_map,
-            iff,
-            version,
+            timetables,
+            locations,
+            realtime: RealtimeState::default(),
+        };
+        repo.print_versions();
+
+        Ok(repo)
+    }
+
+    /// Loads the snapshot written by [`DataRepo::write_snapshot`], falling back to [`DataRepo::new`] when it is missing or outdated
+    pub fn load(cache_dir: &Path, source: &TimetableSource) -> Result<Self, LoadError> {
+        match snapshot::read(cache_dir, source) {
+            Ok(repo) => {
+                eprintln!(
+                    "Loaded snapshot {}",
+                    cache_dir.join(snapshot::SNAPSHOT_PATH).display()
+                );
+                repo.print_versions();
+
+                Ok(repo)
+            }
+            Err(e) => {
+                eprintln!("{e}, parsing timetable");
+
+                Self::new(cache_dir, source)
+            }
         }
     }
 
-    pub fn report_unkown_legs(&self) {
-        let link_map: HashMap<LinkCode, Link> = self
-            .links
-            .iter()
-            .map(|link| (link.link_code(), link.clone()))
-            .collect();
+    /// Stores this repo, built from `source` in `cache_dir`, for [`DataRepo::load`]
+    pub fn write_snapshot(
+        &self,
+        cache_dir: &Path,
+        source: &TimetableSource,
+    ) -> Result<(), snapshot::Error> {
+        snapshot::write(self, cache_dir, source)
+    }
 
-        let station_codes: HashSet<String> = self.stations.iter().map(|s| s.code.clone()).collect();
-        let location_cache = &self.iff.locations;
+    fn print_versions(&self) {
+        for header in self.timetables.iter().map(|timetable| &timetable.header) {
+            let duration = header
+                .last_valid_date
+                .signed_duration_since(header.first_valid_date);
 
-        let reports: Vec<_> = self
-            .iff
-            .timetable()
-            .rides
-            .iter()
-            .filter(|r| !has_complete_data(r, &station_codes, location_cache, &link_map))
-            .flat_map(|r| report_missing(r, &station_codes, location_cache, &link_map))
-            .collect();
+            eprintln!("Timetable start date: {}", header.first_valid_date);
+            eprintln!("Timetable end date: {}", header.last_valid_date);
+            eprintln!("Day count: {}", duration.num_days());
+            eprintln!("Version: {}", header.version);
+        }
+    }
 
-        let mut map = HashMap::new();
+    fn load_iff(
+        cache_dir: &Path,
+        options: ParseOptions,
+    ) -> Result<(TimetableData, Vec<Station>, Vec<Link>), LoadError> {
+        let mut timetable =
+            parse_iff(&cache_dir.join(TIMETABLE_PATH), options)?.into_timetable_data();
 
-        reports
-            .into_iter()
-            .for_each(|r| *map.entry(r).or_insert(0) += 1);
+        let route_file = open(&cache_dir.join(ROUTE_FILEPATH))?;
+        let stations_file = open(&cache_dir.join(STATION_FILEPATH))?;
 
-        let mut entries: Vec<_> = map.into_iter().collect();
-        entries.sort_by_key(|r| r.1);
-        entries
-            .iter()
-            .rev()
-            .for_each(|e| println!("{} ({})", e.0.display(location_cache), e.1))
+        let links: Vec<Link> = extract_links(&route_file, &mut timetable.locations);
+        let stations = extract_stations(&stations_file);
+
+        Ok((timetable, stations, links))
     }
 
-    pub fn filter_unknown_legs(&mut self) {
-        // TODO Drop this check and deal with skipping waypoints throughout the app, or deal with translating stations from the iff into coordinates
-        // This filters out timetable entries that contain stops that we don't have data on, mostly (entirely?) international trains
-        println!(
-            "Pre data filter ride #: {}",
-            self.iff.timetable().rides.len()
-        );
+    /// Adds up to `count` of the most recently archived IFF versions to `timetables`, keeping them sorted newest first
+    /// Versions that are already loaded or whose dates are all served by newer versions are skipped
+    fn load_archived(
+        cache_dir: &Path,
+        count: usize,
+        options: ParseOptions,
+        timetables: &mut Vec<TimetableVersion>,
+        locations: &mut LocationCache,
+    ) {
+        if count == 0 {
+            return;
+        }
 
-        let link_map: HashMap<LinkCode, Link> = self
-            .links
-            .iter()
-            .map(|link| (link.link_code(), link.clone()))
-            .collect();
+        let archived = match Cache::new(cache_dir).and_then(|cache| {
+            cache
+                .archived_files(TIMETABLE_PATH)
+                .map_err(anyhow::Error::from)
+        }) {
+            Ok(archived) => archived,
+            Err(e) => {
+                eprintln!("Skipping archived timetables: {e}");
+                return;
+            }
+        };
+
+        for archived in archived.into_iter().take(count) {
+            let parsed = open(&archived.path).and_then(|file| {
+                let header = Iff::parse_delivery(&file).map_err(|source| LoadError::Iff {
+                    path: archived.path.clone(),
+                    source,
+                })?;
+                if !is_needed(&header, timetables) {
+                    return Ok(None);
+                }
+
+                parse_iff(&archived.path, options).map(Some)
+            });
+
+            let iff = match parsed {
+                Ok(Some(iff)) => iff,
+                Ok(None) => {
+                    eprintln!("Skipping superseded {}", archived.path.display());
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Skipping archived timetable: {e}");
+                    continue;
+                }
+            };
+
+            let (mut timetable, archive_locations) =
+                TimetableVersion::from_data(iff.into_timetable_data());
+            for ride in &mut timetable.rides {
+                ride.relocate(&archive_locations, locations);
+            }
+
+            timetables.push(timetable);
+        }
+
+        timetables.sort_by(|a, b| b.header.cmp_recency(&a.header));
+    }
 
+    fn load_gtfs(path: &Path) -> Result<(TimetableData, Vec<Station>, Vec<Link>), LoadError> {
+        let gtfs_file = open(path)?;
+
+        let (timetable, stations) =
+            gtfs::read_gtfs(BufReader::new(gtfs_file)).map_err(|source| LoadError::Gtfs {
+                path: path.to_owned(),
+                source,
+            })?;
+
+        let links = straight_links(&timetable.rides, &stations, &timetable.locations, &[]);
+
+        Ok((timetable, stations, links))
+    }
+
+    /// Trims rides to the longest section with station data for every stop and links between all its points, dropping rides without one
+    /// Stations and links missing from the NS data are resolved while loading, so this mostly cuts off the foreign part of international trains
+    pub fn filter_unknown_legs(&mut self) {
         let station_codes: HashSet<String> = self.stations.iter().map(|s| s.code.clone()).collect();
-        let location_cache = &self.iff.locations.clone(); // Clone is safe since it's only being
+        let location_cache = &self.locations;
+        let link_map = &self.link_map;
 
-        self.iff
-            .rides_mut()
-            .retain(|ride| has_complete_data(ride, &station_codes, location_cache, &link_map));
+        for timetable in &mut self.timetables {
+            eprintln!("Pre data filter ride #: {}", timetable.rides.len());
+            let mut trimmed = 0;
 
-        println!(
-            "Post data filter ride #: {}",
-            self.iff.timetable().rides.len()
-        );
+            timetable.rides.retain_mut(|ride| {
+                match known_section(ride, &station_codes, location_cache, link_map) {
+                    Some((first, last)) if first == 0 && last == ride.timetable.len() - 1 => true,
+                    Some((first, last)) => {
+                        *ride = ride.section(first, last);
+                        trimmed += 1;
+                        true
+                    }
+                    None => false,
+                }
+            });
+            timetable.reindex();
+
+            eprintln!(
+                "Post data filter ride #: {}, {trimmed} trimmed",
+                timetable.rides.len()
+            );
+        }
+    }
 
-        self.rides = self
-            .iff
-            .timetable()
-            .rides
+    /// The newest loaded timetable version
+    fn current(&self) -> &TimetableVersion {
+        self.timetables
+            .first()
+            .expect("at least one timetable version")
+    }
+
+    /// The newest loaded timetable version covering `date`
+    fn timetable_for_date(&self, date: NaiveDate) -> Option<&TimetableVersion> {
+        self.timetables
             .iter()
-            .flat_map(|r| r.split_on_ride_id())
-            .collect()
+            .find(|timetable| timetable.header.covers_date(date))
+    }
+
+    /// The platforms of a stop in effect on `date`, by the footnotes of the timetable version serving that date
+    pub fn platforms_on<'a>(
+        &self,
+        platforms: &'a [PlatformInfo],
+        date: NaiveDate,
+    ) -> Option<&'a PlatformInfo> {
+        let validity = &self.timetable_for_date(date)?.validity;
+
+        platforms
+            .iter()
+            .find_map(|info| info.on_date(validity, date))
     }
 
+    /// Rides of the newest timetable version
     pub fn rides(&self) -> &[Ride] {
-        &self.rides
+        &self.current().rides
     }
 
+    /// Companies of the newest timetable version
     pub fn companies(&self) -> &[Company] {
-        self.iff.companies()
+        &self.current().companies
+    }
+
+    /// Looks up a company in all timetable versions, newest first
+    pub fn company_by_id(&self, id: u32) -> Option<&Company> {
+        self.timetables
+            .iter()
+            .flat_map(|timetable| &timetable.companies)
+            .find(|company| company.id() == id)
+    }
+
+    /// Rides valid on `date` in the timetable version serving it
+    fn rides_valid_on(&self, date: NaiveDate) -> impl Iterator<Item = &Ride> {
+        self.timetable_for_date(date)
+            .into_iter()
+            .flat_map(move |timetable| timetable.rides_valid_on(date))
+    }
+
+    /// Rides valid on `date` that may be underway between `from` and `to` once realtime delays are applied
+    fn rides_possibly_active(
+        &self,
+        date: NaiveDate,
+        from: DayOffset,
+        to: DayOffset,
+    ) -> impl Iterator<Item = &Ride> {
+        let (earliest, latest) = self.realtime.delay_range(date);
+        let from = from.offset_by_seconds(-latest);
+        let to = to.offset_by_seconds(-earliest);
+
+        self.timetable_for_date(date)
+            .into_iter()
+            .flat_map(move |timetable| timetable.rides_active(date, from, to))
     }
 
     pub fn rides_active_at_time(&self, time: &NaiveTime, date: &NaiveDate) -> Vec<&Ride> {
         let time = DayOffset::from_naivetime(time);
 
-        self.rides()
-            // .timetable()
-            // .rides
-            .iter()
-            .filter(|r| r.start_time() < time && r.end_time() > time)
+        self.rides_possibly_active(*date, time, time)
             .filter(|r| {
-                self.iff
-                    .validity()
-                    .is_valid_on_day(r.day_validity, *date)
-                    .unwrap()
+                self.actual_span(r, *date)
+                    .is_some_and(|(start, end)| start < time && end > time)
             })
             // .cloned()
             .collect()
@@ -372,29 +733,112 @@ impl DataRepo {
         let offset_start = DayOffset::from_naivetime(time_start);
         let offset_end = DayOffset::from_naivetime(time_end);
 
-        self.rides()
-            // .timetable()
-            // .rides
-            .iter()
-            .filter(|r| r.start_time() <= offset_end && r.end_time() > offset_start)
+        self.rides_possibly_active(*date, offset_start, offset_end)
             .filter(|r| {
-                self.iff
-                    .validity()
-                    .is_valid_on_day(r.day_validity, *date)
-                    .unwrap()
+                self.actual_span(r, *date)
+                    .is_some_and(|(start, end)| start <= offset_end && end > offset_start)
             })
             // .cloned()
             .collect()
     }
 
+    /// Start and end time of `ride` on `date` with realtime delays applied, None if the ride is cancelled
+    fn actual_span(&self, ride: &Ride, date: NaiveDate) -> Option<(DayOffset, DayOffset)> {
+        let (start, end) = (ride.start_time(), ride.end_time());
+
+        let Some(realtime) = self.ride_realtime(ride, date) else {
+            return Some((start, end));
+        };
+        if realtime.cancelled {
+            return None;
+        }
+
+        let delay = |index: usize, delay: fn(&StopRealtime) -> Option<i32>| {
+            realtime.stop(index).and_then(delay).unwrap_or_default()
+        };
+
+        Some((
+            start.offset_by_seconds(delay(0, StopRealtime::delay_on_departure)),
+            end.offset_by_seconds(delay(
+                ride.timetable.len() - 1,
+                StopRealtime::delay_on_arrival,
+            )),
+        ))
+    }
+
     pub fn rides_active_on_date(&self, date: &NaiveDate) -> Vec<&Ride> {
-        self.rides()
-            .iter()
-            .filter(|r| {
-                self.iff
-                    .validity()
-                    .is_valid_on_day(r.day_validity, *date)
-                    .unwrap()
+        self.rides_valid_on(*date).collect()
+    }
+
+    pub fn rides_with_id_on_date(&self, id: &str, date: &NaiveDate) -> Vec<&Ride> {
+        self.rides_active_on_date(date)
+            .into_iter()
+            .filter(|r| r.id == id)
+            .collect()
+    }
+
+    /// Finds ride `id` departing from `station` at `departure`, along with the timetable date it runs on
+    /// Rides running past midnight belong to the timetable of the previous day, falls back to any ride `id` valid on the departure date
+    pub fn find_ride(
+        &self,
+        id: &str,
+        station: &str,
+        departure: NaiveDateTime,
+    ) -> Option<(&Ride, NaiveDate)> {
+        let date = departure.date();
+        let code = self.location_cache().lookup_handle(&station.to_lowercase());
+        let time = DayOffset::from_naivetime(&departure.time());
+
+        let departing = |date: NaiveDate, time: DayOffset| {
+            code.and_then(|code| {
+                self.departures(&code, date, time, time)
+                    .into_iter()
+                    .find(|call| call.ride.id == id)
+                    .map(|call| (call.ride, date))
+            })
+        };
+
+        let today = departing(date, time);
+
+        let yesterday = || departing(date.pred_opt()?, time.offset_by(24 * 60));
+
+        let any = || {
+            self.rides_with_id_on_date(id, &date)
+                .first()
+                .map(|ride| (*ride, date))
+        };
+
+        today.or_else(yesterday).or_else(any)
+    }
+
+    /// Scheduled departures from `station` on `date` between `from` and `to`, sorted by departure time
+    pub fn departures(
+        &self,
+        station: &LocationCodeHandle,
+        date: NaiveDate,
+        from: DayOffset,
+        to: DayOffset,
+    ) -> Vec<StationCall<'_>> {
+        self.timetable_for_date(date)
+            .into_iter()
+            .flat_map(|timetable| {
+                timetable.calls_on(timetable.stops.departures(station, from, to), date)
+            })
+            .collect()
+    }
+
+    /// Scheduled arrivals at `station` on `date` between `from` and `to`, sorted by arrival time
+    pub fn arrivals(
+        &self,
+        station: &LocationCodeHandle,
+        date: NaiveDate,
+        from: DayOffset,
+        to: DayOffset,
+    ) -> Vec<StationCall<'_>> {
+        self.timetable_for_date(date)
+            .into_iter()
+            .flat_map(|timetable| {
+                timetable.calls_on(timetable.stops.arrivals(station, from, to), date)
             })
             .collect()
     }
@@ -407,6 +851,49 @@ impl DataRepo {
                     // .as_slice()
     }
 
+    /// Resolves the Links a moving leg traverses from `from` through its waypoints to `to`, in the direction of travel
+    /// Returns None for stationary legs or when any of the required Links is unknown
+    pub fn leg_links(&self, leg: &LegKind) -> Option<Vec<LinkReference>> {
+        leg_codes(leg)?
+            .iter()
+            .map(|code| {
+                self.link_map
+                    .get_undirected(code)
+                    .map(|(link, reversed)| LinkReference {
+                        id: link.id(),
+                        reversed,
+                    })
+            })
+            .collect()
+    }
+
+    /// Stitches the paths of all Links a moving leg traverses into a single line
+    pub fn leg_path(&self, leg: &LegKind) -> Option<Vec<Coords2D>> {
+        let mut path: Vec<Coords2D> = vec![];
+
+        for code in leg_codes(leg)? {
+            let (link, reversed) = self.link_map.get_undirected(&code)?;
+            append_path(&mut path, link.coordinates(reversed));
+        }
+
+        Some(path)
+    }
+
+    /// Stitches the paths of all moving legs of a ride into a single line
+    pub fn ride_path(&self, ride: &Ride) -> Option<Vec<Coords2D>> {
+        let mut path: Vec<Coords2D> = vec![];
+
+        for leg in ride
+            .generate_legs()
+            .iter()
+            .filter(|leg| leg.kind.is_moving())
+        {
+            append_path(&mut path, self.leg_path(&leg.kind)?);
+        }
+
+        Some(path)
+    }
+
     pub fn stations(&self) -> &[Station] {
         &self.stations
     }
@@ -416,15 +903,453 @@ impl DataRepo {
         self.stations.iter().find(|station| station.code == code)
     }
 
+    /// Version of the newest timetable
+    #[allow(dead_code)]
     pub fn version(&self) -> u64 {
-        self.version
+        self.current().header.version
+    }
+
+    /// If the given date falls within the validity period of any loaded timetable version
+    pub fn covers_date(&self, date: NaiveDate) -> bool {
+        self.timetable_for_date(date).is_some()
     }
 
-    pub fn is_ride_valid(&self, footnote: u64, day: NaiveDate) -> bool {
-        self.iff.validity().is_valid_on_day(footnote, day).unwrap()
+    /// Ride validity of the newest timetable version
+    pub fn validity(&self) -> &RideValidity {
+        &self.current().validity
+    }
+
+    pub fn realtime(&self) -> &RealtimeState {
+        &self.realtime
+    }
+
+    /// Realtime state of `ride` on `date` with delays propagated to every stop, None without any realtime messages
+    pub fn ride_realtime(&self, ride: &Ride, date: NaiveDate) -> Option<RideRealtime> {
+        self.realtime
+            .ride(&ride.id, date)
+            .map(|realtime| realtime.propagated(ride))
     }
 
     pub fn location_cache(&self) -> &LocationCache {
-        &self.iff.locations
+        &self.locations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::IntoAPIObject;
+    use crate::iff::{
+        test_fixtures::{
+            edited_standin_timetable, encoded_standin_timetable, standin_cache_dir, with_file,
+            TempDir, STANDIN_FIXTURES as FIXTURES,
+        },
+        Platform, StopKind,
+    };
+    use chrono::Datelike;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+    use testresult::TestResult;
+
+    #[test]
+    fn platforms_follow_their_footnotes() -> TestResult {
+        // Ride 2871 departs from platform 11 on odd days of January only
+        let timetable = edited_standin_timetable(|content| {
+            let content = content.replace("?11 ,11 ,00001", "?11 ,12 ,00002");
+            if content.contains("#00001\r\n") {
+                content + "#00002\r\n" + &"10".repeat(15) + "1\r\n"
+            } else {
+                content
+            }
+        })?;
+        let cache_dir = cache_dir_without("platforms", &timetable, &[], &[])?;
+
+        let repo = DataRepo::new(&cache_dir, &TimetableSource::default())?;
+        let date = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+
+        let ride = repo.rides().iter().find(|ride| ride.id == "2871").unwrap();
+        let platforms = ride.timetable[0].stop_kind.platforms();
+
+        let on_first = repo.platforms_on(platforms, date(1)).unwrap();
+        assert_eq!(on_first.arrival_platform, Some(Platform::plain(11)));
+        assert_eq!(on_first.departure_platform, Some(Platform::plain(12)));
+        assert!(repo.platforms_on(platforms, date(2)).is_none());
+        // Dates no timetable version serves
+        assert!(repo
+            .platforms_on(platforms, date(1) - chrono::Days::new(1))
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn platform_variants_are_chosen_by_date() -> TestResult {
+        // Ride 2871 departs from platform 12 on odd days and from platform 5 on even days
+        let timetable = encoded_standin_timetable(|name, content| {
+            match name {
+                "footnote.dat" => format!(
+                    "{content}#00002\r\n{}1\r\n#00003\r\n{}0\r\n",
+                    "10".repeat(15),
+                    "01".repeat(15)
+                ),
+                "timetbls.dat" => {
+                    content.replace("?11 ,11 ,00001", "?11 ,12 ,00002\r\n?5 ,5 ,00003")
+                }
+                _ => content,
+            }
+            .into_bytes()
+        })?;
+        let cache_dir = cache_dir_without("platform-variants", &timetable, &[], &[])?;
+        let repo = DataRepo::new(&cache_dir, &TimetableSource::default())?;
+        let date = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+
+        let ride = repo.rides().iter().find(|ride| ride.id == "2871").unwrap();
+        let platforms = ride.timetable[0].stop_kind.platforms();
+        assert_eq!(platforms.len(), 2);
+
+        let departure_on = |day| {
+            repo.platforms_on(platforms, date(day))
+                .and_then(|info| info.departure_platform.clone())
+        };
+        assert_eq!(departure_on(1), Some(Platform::plain(12)));
+        assert_eq!(departure_on(2), Some(Platform::plain(5)));
+        assert_eq!(departure_on(3), Some(Platform::plain(12)));
+
+        // The API serves the variant of the requested date
+        let legs = |day| -> Result<serde_json::Value, serde_json::Error> {
+            let ride = serde_json::to_value(ride.as_api_object(&repo).on_date(date(day)))?;
+            Ok(ride["legs"][0].clone())
+        };
+        let (odd, even) = (legs(1)?, legs(2)?);
+        assert_eq!(
+            odd["departurePlatform"],
+            serde_json::to_value(Platform::plain(12))?
+        );
+        assert_eq!(
+            even["departurePlatform"],
+            serde_json::to_value(Platform::plain(5))?
+        );
+        assert_eq!(even["platformVariesByDay"], true);
+        assert_eq!(even.get("platform"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dates_are_served_by_the_newest_covering_version() -> TestResult {
+        let cache_dir = standin_cache_dir("versions")?;
+        let remote = cache_dir.join("remote");
+
+        // An older delivery from 15 December, in which ride 2871 was still numbered 9871
+        let older = edited_standin_timetable(|content| {
+            content
+                .replace(
+                    "@100,01012024,31012024,0001,",
+                    "@100,15122023,15012024,0000,",
+                )
+                .replace("%100,02871,", "%100,09871,")
+                .replace(&"1".repeat(31), &"1".repeat(32))
+        })?;
+        fs::write(remote.join("ns_iff_1.zip"), older)?;
+        // Entirely covered by the active version, so never loaded
+        let superseded = edited_standin_timetable(|content| {
+            content.replace(
+                "@100,01012024,31012024,0001,",
+                "@100,01012024,20012024,0000,",
+            )
+        })?;
+        fs::write(remote.join("ns_iff_2.zip"), superseded)?;
+
+        let source = TimetableSource::Iff {
+            archived_versions: 2,
+            parse_mode: ParseMode::Strict,
+            parallel_parsing: false,
+            extra_stations: None,
+        };
+        let repo = DataRepo::new(&cache_dir, &source)?;
+        let date = |year, month, day| NaiveDate::from_ymd_opt(year, month, day).unwrap();
+
+        assert_eq!(repo.timetables.len(), 2);
+        assert_eq!(repo.version(), 1);
+
+        assert_eq!(
+            repo.rides_with_id_on_date("9871", &date(2023, 12, 20))
+                .len(),
+            1
+        );
+        assert!(repo
+            .rides_with_id_on_date("2871", &date(2023, 12, 20))
+            .is_empty());
+
+        assert_eq!(
+            repo.rides_with_id_on_date("2871", &date(2024, 1, 10)).len(),
+            1
+        );
+        assert!(repo
+            .rides_with_id_on_date("9871", &date(2024, 1, 10))
+            .is_empty());
+
+        assert!(!repo.covers_date(date(2024, 2, 10)));
+        assert!(repo.rides_active_on_date(&date(2024, 2, 10)).is_empty());
+
+        // Rides of the older version use the same location handles
+        let ride = repo.rides_with_id_on_date("9871", &date(2023, 12, 20))[0];
+        let ut = repo.location_cache().lookup_handle("ut").unwrap();
+        assert_eq!(ride.timetable[0].code, ut);
+
+        Ok(())
+    }
+
+    /// A cache dir holding `timetable` with the stand-in NS data, without the stations in `without_stations` and links from `without_links`
+    fn cache_dir_without(
+        name: &str,
+        timetable: &[u8],
+        without_stations: &[&str],
+        without_links: &[&str],
+    ) -> Result<TempDir, Box<dyn std::error::Error>> {
+        let cache_dir = TempDir::new(name)?;
+        fs::create_dir_all(cache_dir.join("remote"))?;
+        fs::write(cache_dir.join(TIMETABLE_PATH), timetable)?;
+
+        let mut stations: serde_json::Value =
+            serde_json::from_slice(&fs::read(Path::new(FIXTURES).join("stations.json"))?)?;
+        stations["payload"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|station| !without_stations.contains(&station["code"].as_str().unwrap()));
+        fs::write(cache_dir.join(STATION_FILEPATH), stations.to_string())?;
+
+        let mut routes: serde_json::Value =
+            serde_json::from_slice(&fs::read(Path::new(FIXTURES).join("spoorkaart.json"))?)?;
+        routes["payload"]["features"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|link| !without_links.contains(&link["properties"]["from"].as_str().unwrap()));
+        fs::write(cache_dir.join(ROUTE_FILEPATH), routes.to_string())?;
+
+        Ok(cache_dir)
+    }
+
+    fn stop_codes<'a>(repo: &'a DataRepo, id: &str) -> Vec<&'a str> {
+        let ride = repo.rides().iter().find(|ride| ride.id == id).unwrap();
+
+        ride.timetable
+            .iter()
+            .map(|entry| repo.location_cache().get_str(&entry.code).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rides_are_trimmed_to_known_stations() -> TestResult {
+        let timetable = fs::read(Path::new(FIXTURES).join("ns-latest.zip"))?;
+        let cache_dir = cache_dir_without("trimmed", &timetable, &["GD"], &[])?;
+
+        let mut repo = DataRepo::new(&cache_dir, &TimetableSource::default())?;
+        repo.filter_unknown_legs();
+
+        assert_eq!(repo.rides().len(), 2);
+        assert_eq!(stop_codes(&repo, "2871"), ["ut", "wd"]);
+        assert_eq!(stop_codes(&repo, "2870"), ["wd", "ut"]);
+
+        let ride = repo.rides().iter().find(|ride| ride.id == "2871").unwrap();
+        assert!(matches!(
+            ride.timetable[1].stop_kind,
+            StopKind::Arrival(_, _)
+        ));
+        assert_eq!(ride.end_time(), DayOffset::from_hour_minute(10, 18));
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_ride_picks_the_ride_valid_on_the_date() -> TestResult {
+        // Ride 2871 runs at 10:08 on odd days and at 11:08 on even days
+        let timetable =
+            encoded_standin_timetable(|name, content| {
+                match name {
+                "footnote.dat" => format!(
+                    "{content}#00002\r\n{}0\r\n#00003\r\n{}1\r\n",
+                    "01".repeat(15),
+                    "10".repeat(15)
+                ),
+                "timetbls.dat" => content.replace(
+                    "%100,02871, ,001,003,\r\n-00001",
+                    "%100,02871, ,001,003,\r\n-00003",
+                ) + "#00000003\r\n%100,02871, ,001,003,\r\n-00002,000,999\r\n&IC ,001,003\r\n\
+                    >ut ,1108\r\n.wd ,1118\r\n<gd ,1127\r\n",
+                _ => content,
+            }
+            .into_bytes()
+            })?;
+        let cache_dir = cache_dir_without("find-ride", &timetable, &[], &[])?;
+        let repo = DataRepo::new(&cache_dir, &TimetableSource::default())?;
+
+        let at = |day: u32, hour: u32| {
+            NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(hour, 8, 0)
+                .unwrap()
+        };
+        let start = |found: Option<(&Ride, NaiveDate)>| {
+            found.map(|(ride, date)| (ride.start_time(), date.day()))
+        };
+
+        assert_eq!(
+            start(repo.find_ride("2871", "UT", at(2, 11))),
+            Some((DayOffset::from_hour_minute(11, 8), 2))
+        );
+        assert_eq!(
+            start(repo.find_ride("2871", "UT", at(2, 10))),
+            Some((DayOffset::from_hour_minute(11, 8), 2))
+        );
+        assert_eq!(
+            start(repo.find_ride("2871", "UT", at(3, 10))),
+            Some((DayOffset::from_hour_minute(10, 8), 3))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_stations_are_resolved() -> TestResult {
+        let timetable = with_file(
+            fs::read(Path::new(FIXTURES).join("ns-latest.zip"))?,
+            "stations.dat",
+            b"@100,01012024,31012024,0001,Stand-in dienstregeling\r\n\
+            0,wd     ,02,02,NL  ,0000,00,000000,000000,Woerden\r\n\
+            1,gd     ,03,04,NL  ,0000,00,108110,447900,Gouda\r\n",
+        )?;
+        let cache_dir = cache_dir_without("resolved", &timetable, &["WD", "GD"], &["wd"])?;
+        let extra_stations = cache_dir.join("stations.toml");
+        fs::write(
+            &extra_stations,
+            "[[stations]]\ncode = \"WD\"\nname = \"Woerden\"\nlatitude = 52.0853\nlongitude = 4.8911\n",
+        )?;
+
+        let source = TimetableSource::Iff {
+            archived_versions: 0,
+            parse_mode: ParseMode::Strict,
+            parallel_parsing: false,
+            extra_stations: Some(extra_stations),
+        };
+        let mut repo = DataRepo::new(&cache_dir, &source)?;
+        repo.filter_unknown_legs();
+
+        assert_eq!(stop_codes(&repo, "2871"), ["ut", "wd", "gd"]);
+        assert_eq!(stop_codes(&repo, "2870"), ["gd", "wd", "ut"]);
+
+        let woerden = repo.station_by_code("wd").unwrap();
+        assert_eq!(woerden.position, Coords2D::new(4.8911, 52.0853));
+        let gouda = repo.station_by_code("gd").unwrap();
+        assert_eq!(gouda.name, "Gouda");
+        assert!((gouda.position.latitude() - 52.0175).abs() < 0.002);
+        assert!((gouda.position.longitude() - 4.7042).abs() < 0.002);
+
+        let synthetic: Vec<_> = repo
+            .links()
+            .iter()
+            .filter(|link| link.is_synthetic())
+            .collect();
+        assert_eq!(synthetic.len(), 1);
+        assert_eq!(synthetic[0].id(), 1);
+        assert_eq!(
+            synthetic[0].coordinates(false),
+            [woerden.position, gouda.position]
+        );
+
+        // The served stations and links look the same whether they were resolved or not
+        assert_eq!(serde_json::to_value(woerden)?.get("resolved"), None);
+        assert_eq!(serde_json::to_value(synthetic[0])?.get("synthetic"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn links_are_found_in_either_direction() {
+        let mut locations = LocationCache::new();
+        let (ut, wd, gd) = (
+            locations.get_handle("ut"),
+            locations.get_handle("wd"),
+            locations.get_handle("gd"),
+        );
+        let link = Link::new(
+            0,
+            ut,
+            wd,
+            &[Coords2D::new(5.11, 52.09), Coords2D::new(4.89, 52.09)],
+            false,
+        );
+        let links = HashMap::from([(link.link_code(), link.clone())]);
+
+        assert_eq!(
+            links.get_undirected(&LinkCode(ut, wd)),
+            Some((&link, false))
+        );
+        assert_eq!(links.get_undirected(&LinkCode(wd, ut)), Some((&link, true)));
+        assert_eq!(links.get_undirected(&LinkCode(ut, gd)), None);
+    }
+
+    #[test]
+    fn leg_links_follow_the_direction_of_travel() -> TestResult {
+        let timetable = fs::read(Path::new(FIXTURES).join("ns-latest.zip"))?;
+        let cache_dir = cache_dir_without("leg-links", &timetable, &[], &[])?;
+        let repo = DataRepo::new(&cache_dir, &TimetableSource::default())?;
+
+        let (ut, wd) = (
+            repo.location_cache().lookup_handle("ut").unwrap(),
+            repo.location_cache().lookup_handle("wd").unwrap(),
+        );
+        let (link, _) = repo.link_map.get_undirected(&LinkCode(ut, wd)).unwrap();
+        assert_eq!(link.link_code(), LinkCode(ut, wd));
+
+        // Ride 2871 runs from Utrecht to Woerden along the stored link, 2870 the other way around
+        let moving_leg = |id: &str| {
+            let ride = repo.rides().iter().find(|ride| ride.id == id).unwrap();
+            ride.generate_legs()
+                .into_iter()
+                .find(|leg| {
+                    matches!(leg.kind, LegKind::Moving { from, to, .. }
+                        if [from, to] == [ut, wd] || [from, to] == [wd, ut])
+                })
+                .unwrap()
+                .kind
+        };
+
+        let along = moving_leg("2871");
+        assert_eq!(
+            repo.leg_links(&along),
+            Some(vec![LinkReference {
+                id: link.id(),
+                reversed: false
+            }])
+        );
+        assert_eq!(repo.leg_path(&along), Some(link.coordinates(false)));
+
+        let against = moving_leg("2870");
+        assert_eq!(
+            repo.leg_links(&against),
+            Some(vec![LinkReference {
+                id: link.id(),
+                reversed: true
+            }])
+        );
+        let path = repo.leg_path(&against).unwrap();
+        assert_eq!(path, link.coordinates(true));
+        assert_eq!(path.first(), link.coordinates(false).last());
+
+        Ok(())
+    }
+
+    #[test]
+    fn append_path_skips_shared_point() {
+        let a = Coords2D::new(5.0, 52.0);
+        let b = Coords2D::new(5.1, 52.1);
+        let c = Coords2D::new(5.2, 52.2);
+
+        let mut path = vec![];
+        append_path(&mut path, vec![a, b]);
+        append_path(&mut path, vec![b, c]);
+
+        assert_eq!(path, vec![a, b, c]);
     }
 }
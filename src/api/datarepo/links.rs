@@ -1,10 +1,14 @@
-use std::{fs::File, io::BufReader};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::BufReader,
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::iff::{LocationCache, LocationCodeHandle};
+use crate::iff::{LocationCache, LocationCodeHandle, Ride};
 
-use super::LinkCode;
+use super::{stations::Station, LinkCode};
 
 pub fn extract_links(file: &File, locations: &mut LocationCache) -> Vec<Link> {
     let reader = BufReader::new(file);
@@ -31,10 +35,51 @@ pub fn extract_links(file: &File, locations: &mut LocationCache) -> Vec<Link> {
 
     links
         .into_iter()
-        .map(|l| Link::new_from_json_link(&l, locations))
+        .enumerate()
+        .map(|(index, l)| Link::new_from_json_link(index as u32, &l, locations))
         .collect()
 }
 
+/// Draws synthetic straight Links between consecutive timetable points of the given rides that none of `existing` connects, numbered after them
+/// Points without a known station position are skipped
+pub fn straight_links<'a>(
+    rides: impl IntoIterator<Item = &'a Ride>,
+    stations: &[Station],
+    locations: &LocationCache,
+    existing: &[Link],
+) -> Vec<Link> {
+    let positions: HashMap<LocationCodeHandle, Coords2D> = stations
+        .iter()
+        .filter_map(|station| {
+            locations
+                .lookup_handle(&station.code)
+                .map(|handle| (handle, station.position))
+        })
+        .collect();
+
+    let mut seen: HashSet<LinkCode> = existing.iter().map(Link::link_code).collect();
+    let first_id = existing.iter().map(|link| link.id + 1).max().unwrap_or(0);
+    let mut links = vec![];
+
+    for ride in rides {
+        for pair in ride.timetable.windows(2) {
+            let code = LinkCode(pair[0].code, pair[1].code);
+            if seen.contains(&code) || seen.contains(&code.reversed()) {
+                continue;
+            }
+
+            if let (Some(from), Some(to)) = (positions.get(&code.0), positions.get(&code.1)) {
+                let id = first_id + links.len() as u32;
+                links.push(Link::new(id, code.0, code.1, &[*from, *to], true));
+            }
+
+            seen.insert(code);
+        }
+    }
+
+    links
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
 pub struct Coords2D {
     // #[serde(serialize_with = "bin_float")]
@@ -53,15 +98,79 @@ impl Coords2D {
             latitude,
         }
     }
+
+    pub fn longitude(&self) -> f64 {
+        self.longitude
+    }
+
+    pub fn latitude(&self) -> f64 {
+        self.latitude
+    }
+
+    /// Converts Rijksdriehoek coordinates in metres, as the IFF stations file uses, with the approximation of Schreutelkamp and Strang van Hees
+    /// Accurate to about a metre within the Netherlands
+    pub fn from_rijksdriehoek(x: f64, y: f64) -> Self {
+        const LATITUDE: [(i32, i32, f64); 11] = [
+            (0, 1, 3235.65389),
+            (2, 0, -32.58297),
+            (0, 2, -0.24750),
+            (2, 1, -0.84978),
+            (0, 3, -0.06550),
+            (2, 2, -0.01709),
+            (1, 0, -0.00738),
+            (4, 0, 0.00530),
+            (2, 3, -0.00039),
+            (4, 1, 0.00033),
+            (1, 1, -0.00012),
+        ];
+        const LONGITUDE: [(i32, i32, f64); 12] = [
+            (1, 0, 5260.52916),
+            (1, 1, 105.94684),
+            (1, 2, 2.45656),
+            (3, 0, -0.81885),
+            (1, 3, 0.05594),
+            (3, 1, -0.05607),
+            (0, 1, 0.01199),
+            (3, 2, -0.00256),
+            (1, 4, 0.00128),
+            (0, 2, 0.00022),
+            (2, 0, -0.00022),
+            (5, 0, 0.00026),
+        ];
+
+        // Offsets from Amersfoort, the origin of the projection, in units of 100 km
+        let dx = (x - 155_000f64) * 1e-5;
+        let dy = (y - 463_000f64) * 1e-5;
+        let sum = |terms: &[(i32, i32, f64)]| {
+            terms
+                .iter()
+                .map(|(p, q, k)| k * dx.powi(*p) * dy.powi(*q))
+                .sum::<f64>()
+                / 3600f64
+        };
+
+        Self {
+            longitude: 5.38720621 + sum(&LONGITUDE),
+            latitude: 52.15517440 + sum(&LATITUDE),
+        }
+    }
+
+    /// Coordinates as a `[longitude, latitude]` pair, the ordering GeoJSON uses
+    pub fn as_array(&self) -> [f64; 2] {
+        [self.longitude, self.latitude]
+    }
 }
 
 /// A path between two timetable points
 #[derive(Debug, Clone, Serialize)]
 pub struct Link {
-    // id: u32,
+    id: u32,
     from: LocationCodeHandle,
     to: LocationCodeHandle,
     path: Path,
+    /// Drawn as a straight line for lack of route geometry, left out of the served links
+    #[serde(skip)]
+    synthetic: bool,
 }
 
 // struct LinkSerializable<'a, 'b> {
@@ -79,6 +188,32 @@ impl Link {
     pub fn link_code(&self) -> LinkCode {
         LinkCode(self.from, self.to)
     }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn is_synthetic(&self) -> bool {
+        self.synthetic
+    }
+
+    /// Coordinates along this Link's path, from `to` to `from` when `reversed` is set
+    pub fn coordinates(&self, reversed: bool) -> Vec<Coords2D> {
+        let points = self.path.points.iter().map(|point| point.coordinates);
+
+        if reversed {
+            points.rev().collect()
+        } else {
+            points.collect()
+        }
+    }
+}
+
+/// Reference from a Leg to a Link it traverses, `reversed` is set when the Link is traversed from `to` to `from`
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct LinkReference {
+    pub id: u32,
+    pub reversed: bool,
 }
 
 // const EARTH_RADIUS: f32 = 12742f32;
@@ -151,16 +286,29 @@ struct PathPoint {
 }
 
 impl Link {
-    fn new_from_json_link(json: &JsonLink, location_cache: &mut LocationCache) -> Self {
-        let from = location_cache.get_handle(&json.properties.from);
-        let to = location_cache.get_handle(&json.properties.to);
-
+    /// A Link following `coordinates` from `from` to `to`
+    pub fn new(
+        id: u32,
+        from: LocationCodeHandle,
+        to: LocationCodeHandle,
+        coordinates: &[Coords2D],
+        synthetic: bool,
+    ) -> Self {
         Self {
+            id,
             from,
             to,
-            path: Path::new_from_coords(&json.geometry.coordinates),
+            path: Path::new_from_coords(coordinates),
+            synthetic,
         }
     }
+
+    fn new_from_json_link(id: u32, json: &JsonLink, location_cache: &mut LocationCache) -> Self {
+        let from = location_cache.get_handle(&json.properties.from);
+        let to = location_cache.get_handle(&json.properties.to);
+
+        Self::new(id, from, to, &json.geometry.coordinates, false)
+    }
 }
 
 #[derive(Deserialize, Debug)]
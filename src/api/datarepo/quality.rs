@@ -0,0 +1,408 @@
+//! Data quality checks over a loaded [`DataRepo`], as reported by the `verify` command
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::iff::{Company, LegKind, LocationCodeHandle, Ride, TimetableEntry};
+
+use super::{report_missing_leg, DataRepo, LinkCode, LinkMap, MissingLinkReport};
+
+/// A single data quality check, each finding issues with a subject like a station, ride or footnote
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// Rides with a time before the one preceding it
+    NonMonotonicTimes,
+    /// Stops without an arrival or departure platform
+    MissingPlatform,
+    /// Rides operated by a company missing from the company file
+    UnknownCompany,
+    /// Footnotes rides or platforms refer to, missing from the footnote file
+    UndefinedFootnote,
+    /// Stops at locations without station data
+    MissingStation,
+    /// Consecutive stops without a link between them
+    MissingLink,
+    /// Stops at stations resolved from other data than the NS station list
+    ResolvedStation,
+    /// Consecutive stops linked by a straight line, for lack of NS route data
+    SyntheticLink,
+    /// Links with a path of zero length
+    EmptyLinkPath,
+    /// Stations no ride stops at
+    UnservedStation,
+}
+
+impl Check {
+    pub const ALL: [Check; 10] = [
+        Check::NonMonotonicTimes,
+        Check::MissingPlatform,
+        Check::UnknownCompany,
+        Check::UndefinedFootnote,
+        Check::MissingStation,
+        Check::MissingLink,
+        Check::ResolvedStation,
+        Check::SyntheticLink,
+        Check::EmptyLinkPath,
+        Check::UnservedStation,
+    ];
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Check::NonMonotonicTimes => "Rides with non-monotonic times",
+            Check::MissingPlatform => "Stops without platforms",
+            Check::UnknownCompany => "Unknown companies",
+            Check::UndefinedFootnote => "Undefined footnotes",
+            Check::MissingStation => "Stops without station data",
+            Check::MissingLink => "Missing links",
+            Check::ResolvedStation => "Stops at stations without NS data",
+            Check::SyntheticLink => "Links without NS route data",
+            Check::EmptyLinkPath => "Links with zero-length paths",
+            Check::UnservedStation => "Stations absent from the timetable",
+        })
+    }
+}
+
+/// How often a check found an issue with a single subject
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub subject: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CheckResult {
+    pub check: Check,
+    /// Issues found across all subjects
+    pub count: usize,
+    /// Most frequent subjects first
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct QualityReport {
+    /// Results in the order of [`Check::ALL`]
+    pub checks: Vec<CheckResult>,
+}
+
+impl QualityReport {
+    #[allow(dead_code)]
+    pub fn result(&self, check: Check) -> Option<&CheckResult> {
+        self.checks.iter().find(|result| result.check == check)
+    }
+}
+
+/// Issue counts per check and subject
+#[derive(Default)]
+struct Findings(HashMap<Check, HashMap<String, usize>>);
+
+impl Findings {
+    fn add(&mut self, check: Check, subject: impl Into<String>) {
+        *self
+            .0
+            .entry(check)
+            .or_default()
+            .entry(subject.into())
+            .or_default() += 1;
+    }
+
+    fn into_report(mut self) -> QualityReport {
+        let checks = Check::ALL
+            .into_iter()
+            .map(|check| {
+                let mut findings: Vec<_> = self
+                    .0
+                    .remove(&check)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(subject, count)| Finding { subject, count })
+                    .collect();
+                findings.sort_by(|a, b| b.count.cmp(&a.count).then(a.subject.cmp(&b.subject)));
+
+                CheckResult {
+                    check,
+                    count: findings.iter().map(|finding| finding.count).sum(),
+                    findings,
+                }
+            })
+            .collect();
+
+        QualityReport { checks }
+    }
+}
+
+/// The first stop of `ride` with a time before the time preceding it
+fn first_decreasing_time(ride: &Ride) -> Option<&TimetableEntry> {
+    let mut previous = None;
+
+    for entry in &ride.timetable {
+        let times = [
+            entry.stop_kind.arrival_time(),
+            entry.stop_kind.departure_time(),
+        ];
+
+        for time in times.into_iter().flatten() {
+            if previous.is_some_and(|previous| time < previous) {
+                return Some(entry);
+            }
+            previous = Some(time);
+        }
+    }
+
+    None
+}
+
+impl DataRepo {
+    /// Runs every [`Check`] over all loaded timetable versions, links and stations
+    pub fn quality_report(&self) -> QualityReport {
+        let mut findings = Findings::default();
+        let locations = &self.locations;
+        let code = |handle: &LocationCodeHandle| locations.get_str(handle).unwrap_or_default();
+
+        let station_codes: HashSet<String> = self.stations.iter().map(|s| s.code.clone()).collect();
+        let resolved: HashSet<&str> = self
+            .stations
+            .iter()
+            .filter(|station| station.resolved)
+            .map(|station| station.code.as_str())
+            .collect();
+        let mut served = HashSet::new();
+
+        for timetable in &self.timetables {
+            let companies: HashSet<u32> = timetable.companies.iter().map(Company::id).collect();
+            let validity = &timetable.validity;
+
+            for ride in &timetable.rides {
+                if let Some(entry) = first_decreasing_time(ride) {
+                    findings.add(
+                        Check::NonMonotonicTimes,
+                        format!("ride {} at {}", ride.id, code(&entry.code)),
+                    );
+                }
+
+                if !companies.contains(&ride.operator) {
+                    findings.add(Check::UnknownCompany, format!("company {}", ride.operator));
+                }
+
+                if !validity.has_footnote(ride.day_validity) {
+                    findings.add(
+                        Check::UndefinedFootnote,
+                        format!("footnote {}", ride.day_validity),
+                    );
+                }
+
+                for entry in ride.timetable.iter().filter(|e| !e.stop_kind.is_waypoint()) {
+                    served.insert(entry.code);
+
+                    let platforms = entry.stop_kind.platforms();
+                    for info in platforms {
+                        if !validity.has_footnote(info.footnote()) {
+                            findings.add(
+                                Check::UndefinedFootnote,
+                                format!("footnote {}", info.footnote()),
+                            );
+                        }
+                    }
+                    if platforms.iter().all(|info| {
+                        info.arrival_platform.is_none() && info.departure_platform.is_none()
+                    }) {
+                        findings.add(Check::MissingPlatform, code(&entry.code));
+                    }
+                }
+
+                // Stations and links resolved while loading are reported apart from those still missing
+                for leg in ride.generate_legs() {
+                    let missing =
+                        report_missing_leg(&leg, &station_codes, locations, &self.link_map);
+
+                    match (missing, &leg.kind) {
+                        (Some(MissingLinkReport::NoStation(station)), _) => {
+                            findings.add(Check::MissingStation, code(&station));
+                        }
+                        (Some(MissingLinkReport::NoRoute(from, to)), _) => {
+                            findings.add(
+                                Check::MissingLink,
+                                format!("{} - {}", code(&from), code(&to)),
+                            );
+                        }
+                        (None, LegKind::Stationary(station, _)) => {
+                            if resolved.contains(code(station)) {
+                                findings.add(Check::ResolvedStation, code(station));
+                            }
+                        }
+                        (None, LegKind::Moving { from, to, .. }) => {
+                            let link = self.link_map.get_undirected(&LinkCode(*from, *to));
+                            if link.is_some_and(|(link, _)| link.is_synthetic()) {
+                                findings.add(
+                                    Check::SyntheticLink,
+                                    format!("{} - {}", code(from), code(to)),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        for link in &self.links {
+            if link
+                .coordinates(false)
+                .windows(2)
+                .all(|pair| pair[0] == pair[1])
+            {
+                let link_code = link.link_code();
+                findings.add(
+                    Check::EmptyLinkPath,
+                    format!("{} - {}", code(&link_code.0), code(&link_code.1)),
+                );
+            }
+        }
+
+        let served: HashSet<&str> = served.iter().map(code).collect();
+        for station in &self.stations {
+            if !served.contains(station.code.as_str()) {
+                findings.add(
+                    Check::UnservedStation,
+                    format!("{} ({})", station.name, station.code),
+                );
+            }
+        }
+
+        findings.into_report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{
+        api::datarepo::TimetableSource,
+        fetch::{ROUTE_FILEPATH, STATION_FILEPATH, TIMETABLE_PATH},
+        iff::test_fixtures::{
+            edited_standin_timetable, standin_cache_dir, with_file, TempDir,
+            STANDIN_FIXTURES as FIXTURES,
+        },
+    };
+
+    fn subjects(report: &QualityReport, check: Check) -> Vec<(&str, usize)> {
+        report
+            .result(check)
+            .map(|result| {
+                result
+                    .findings
+                    .iter()
+                    .map(|finding| (finding.subject.as_str(), finding.count))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn issues_are_found_per_check() -> TestResult {
+        let cache_dir = TempDir::new("quality")?;
+        fs::create_dir_all(cache_dir.join("remote"))?;
+
+        let timetable = edited_standin_timetable(|content| {
+            content
+                .replace(".wd ,1011", ".wd ,0950")
+                .replace("%100,02871", "%200,02871")
+                .replace("?2 ,2 ,00001", "?2 ,2 ,00007")
+                .replace("?12 ,12 ,00001", "?  ,  ,00001")
+        })?;
+        fs::write(cache_dir.join(TIMETABLE_PATH), timetable)?;
+
+        // Woerden under another code, and the link from Woerden to Gouda collapsed into a single point
+        let stations = fs::read_to_string(Path::new(FIXTURES).join("stations.json"))?;
+        fs::write(
+            cache_dir.join(STATION_FILEPATH),
+            stations.replace("\"code\": \"WD\"", "\"code\": \"WDX\""),
+        )?;
+        let mut routes: serde_json::Value =
+            serde_json::from_slice(&fs::read(Path::new(FIXTURES).join("spoorkaart.json"))?)?;
+        routes["payload"]["features"][1]["properties"]["to"] = "gdx".into();
+        routes["payload"]["features"][1]["geometry"]["coordinates"] =
+            serde_json::json!([[4.8911, 52.0853], [4.8911, 52.0853]]);
+        fs::write(cache_dir.join(ROUTE_FILEPATH), routes.to_string())?;
+
+        let report = DataRepo::new(&cache_dir, &TimetableSource::default())?.quality_report();
+
+        assert_eq!(
+            subjects(&report, Check::NonMonotonicTimes),
+            [("ride 2870 at wd", 1)]
+        );
+        assert_eq!(subjects(&report, Check::MissingPlatform), [("ut", 1)]);
+        assert_eq!(
+            subjects(&report, Check::UnknownCompany),
+            [("company 200", 1)]
+        );
+        assert_eq!(
+            subjects(&report, Check::UndefinedFootnote),
+            [("footnote 7", 1)]
+        );
+        assert_eq!(subjects(&report, Check::MissingStation), [("wd", 2)]);
+        assert_eq!(
+            subjects(&report, Check::MissingLink),
+            [("gd - wd", 1), ("wd - gd", 1)]
+        );
+        assert_eq!(subjects(&report, Check::EmptyLinkPath), [("wd - gdx", 1)]);
+        assert_eq!(
+            subjects(&report, Check::UnservedStation),
+            [("Woerden (wdx)", 1)]
+        );
+        assert_eq!(report.checks.len(), Check::ALL.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolved_stations_and_synthetic_links_are_reported() -> TestResult {
+        let cache_dir = standin_cache_dir("quality-resolved")?;
+
+        // Gouda only has a position in the IFF stations file, and no route leads there
+        let timetable = with_file(
+            fs::read(cache_dir.join(TIMETABLE_PATH))?,
+            "stations.dat",
+            b"@100,01012024,31012024,0001,Stand-in dienstregeling\r\n\
+            1,gd     ,03,04,NL  ,0000,00,108110,447900,Gouda\r\n",
+        )?;
+        fs::write(cache_dir.join(TIMETABLE_PATH), timetable)?;
+
+        let mut stations: serde_json::Value =
+            serde_json::from_slice(&fs::read(cache_dir.join(STATION_FILEPATH))?)?;
+        stations["payload"]
+            .as_array_mut()
+            .ok_or("stations to be a list")?
+            .retain(|station| station["code"] != "GD");
+        fs::write(cache_dir.join(STATION_FILEPATH), stations.to_string())?;
+
+        let mut routes: serde_json::Value =
+            serde_json::from_slice(&fs::read(cache_dir.join(ROUTE_FILEPATH))?)?;
+        routes["payload"]["features"]
+            .as_array_mut()
+            .ok_or("routes to be a list")?
+            .retain(|link| link["properties"]["to"] != "gd");
+        fs::write(cache_dir.join(ROUTE_FILEPATH), routes.to_string())?;
+
+        let report = DataRepo::new(&cache_dir, &TimetableSource::default())?.quality_report();
+
+        assert_eq!(subjects(&report, Check::MissingStation), []);
+        assert_eq!(subjects(&report, Check::MissingLink), []);
+        assert_eq!(subjects(&report, Check::ResolvedStation), [("gd", 2)]);
+        assert_eq!(
+            subjects(&report, Check::SyntheticLink),
+            [("gd - wd", 1), ("wd - gd", 1)]
+        );
+
+        Ok(())
+    }
+}
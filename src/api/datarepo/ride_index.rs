@@ -0,0 +1,316 @@
+//! Lookup of the rides of a timetable version by date and time, so queries don't have to scan every ride
+use chrono::NaiveDate;
+
+use crate::{
+    dayoffset::DayOffset,
+    iff::{Ride, RideValidity},
+};
+
+/// Indices of the rides valid on every day of a timetable version, along with an interval tree over their start and end times
+#[derive(Default)]
+pub struct RideIndex {
+    first_date: Option<NaiveDate>,
+    /// A set of ride indices for every day from `first_date` on
+    dates: Vec<RideSet>,
+    intervals: IntervalTree,
+}
+
+impl RideIndex {
+    /// Indexes `rides` by the dates `validity` lists for them, rides with unknown footnotes are never valid
+    pub fn new(rides: &[Ride], validity: &RideValidity) -> Self {
+        let (first_date, last_date) = validity.date_range();
+        let day_count = last_date.signed_duration_since(first_date).num_days() + 1;
+
+        let mut dates: Vec<_> = (0..day_count.max(0))
+            .map(|_| RideSet::with_capacity(rides.len()))
+            .collect();
+
+        for (index, ride) in rides.iter().enumerate() {
+            for date in validity
+                .valid_dates(ride.day_validity)
+                .into_iter()
+                .flatten()
+            {
+                let day = date.signed_duration_since(first_date).num_days() as usize;
+                if let Some(set) = dates.get_mut(day) {
+                    set.insert(index);
+                }
+            }
+        }
+
+        Self {
+            first_date: Some(first_date),
+            dates,
+            intervals: IntervalTree::new(rides),
+        }
+    }
+
+    fn rides_on(&self, date: NaiveDate) -> Option<&RideSet> {
+        let day = date.signed_duration_since(self.first_date?).num_days();
+
+        usize::try_from(day)
+            .ok()
+            .and_then(|day| self.dates.get(day))
+    }
+
+    /// Indices of the rides valid on `date`, in timetable order
+    pub fn valid_on(&self, date: NaiveDate) -> impl Iterator<Item = usize> + '_ {
+        self.rides_on(date).into_iter().flat_map(RideSet::iter)
+    }
+
+    /// If the ride at `index` is valid on `date`
+    pub fn is_valid_on(&self, index: usize, date: NaiveDate) -> bool {
+        self.rides_on(date)
+            .is_some_and(|rides| rides.contains(index))
+    }
+
+    /// Indices of the rides valid on `date` that start at or before `to` and end at or after `from`
+    pub fn active(&self, date: NaiveDate, from: DayOffset, to: DayOffset) -> Vec<usize> {
+        let Some(rides) = self.rides_on(date) else {
+            return vec![];
+        };
+
+        let mut active = self.intervals.overlapping(from, to);
+        active.retain(|index| rides.contains(*index));
+        active.sort_unstable();
+
+        active
+    }
+}
+
+/// A fixed size bitset of ride indices
+struct RideSet {
+    words: Vec<u64>,
+}
+
+impl RideSet {
+    fn with_capacity(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.words[index / 64] |= 1 << (index % 64);
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.words
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .flat_map(|(word_index, word)| {
+                let mut word = *word;
+                std::iter::from_fn(move || {
+                    if word == 0 {
+                        return None;
+                    }
+
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+
+                    Some(word_index * 64 + bit)
+                })
+            })
+    }
+}
+
+/// Ride spans sorted by start, read as an implicit balanced tree where the middle of every range is its root
+/// Every root holds the latest end within its range, so subtrees ending too early are skipped entirely
+#[derive(Default)]
+struct IntervalTree {
+    spans: Vec<Span>,
+}
+
+struct Span {
+    start: DayOffset,
+    end: DayOffset,
+    ride: usize,
+    /// Latest end of all spans in the subtree rooted at this span
+    max_end: DayOffset,
+}
+
+impl IntervalTree {
+    fn new(rides: &[Ride]) -> Self {
+        let mut spans: Vec<_> = rides
+            .iter()
+            .enumerate()
+            .filter(|(_, ride)| !ride.timetable.is_empty())
+            .map(|(ride, r)| Span {
+                start: r.start_time(),
+                end: r.end_time(),
+                ride,
+                max_end: r.end_time(),
+            })
+            .collect();
+        spans.sort_by_key(|span| span.start);
+
+        Self::fill_max_end(&mut spans);
+
+        Self { spans }
+    }
+
+    fn fill_max_end(spans: &mut [Span]) -> Option<DayOffset> {
+        if spans.is_empty() {
+            return None;
+        }
+
+        let mid = spans.len() / 2;
+        let left = Self::fill_max_end(&mut spans[..mid]);
+        let right = Self::fill_max_end(&mut spans[mid + 1..]);
+
+        let root = &mut spans[mid];
+        root.max_end = [left, right].into_iter().flatten().fold(root.end, Ord::max);
+
+        Some(root.max_end)
+    }
+
+    /// Rides of all spans with a start at or before `to` and an end at or after `from`
+    fn overlapping(&self, from: DayOffset, to: DayOffset) -> Vec<usize> {
+        let mut rides = vec![];
+        Self::collect(&self.spans, from, to, &mut rides);
+
+        rides
+    }
+
+    fn collect(spans: &[Span], from: DayOffset, to: DayOffset, rides: &mut Vec<usize>) {
+        if spans.is_empty() {
+            return;
+        }
+
+        let mid = spans.len() / 2;
+        let root = &spans[mid];
+        if root.max_end < from {
+            return;
+        }
+
+        Self::collect(&spans[..mid], from, to, rides);
+
+        if root.start <= to {
+            if root.end >= from {
+                rides.push(root.ride);
+            }
+
+            Self::collect(&spans[mid + 1..], from, to, rides);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::Days;
+    use pretty_assertions::assert_eq;
+
+    use crate::iff::{Header, StopKind, TimetableEntry};
+
+    use super::*;
+
+    fn ride(id: usize, start: DayOffset, end: DayOffset, footnote: u64) -> Ride {
+        let mut locations = crate::iff::LocationCache::new();
+
+        Ride {
+            id: id.to_string(),
+            transit_mode: "IC".to_owned(),
+            timetable: vec![
+                TimetableEntry {
+                    code: locations.get_handle("ut"),
+                    stop_kind: StopKind::Departure(vec![], start),
+                },
+                TimetableEntry {
+                    code: locations.get_handle("gd"),
+                    stop_kind: StopKind::Arrival(vec![], end),
+                },
+            ],
+            day_validity: footnote,
+            previous: None,
+            next: None,
+            operator: 100,
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    /// The rides a linear scan finds, which the index has to match
+    fn scan(
+        rides: &[Ride],
+        validity: &RideValidity,
+        date: NaiveDate,
+        from: DayOffset,
+        to: DayOffset,
+    ) -> Vec<usize> {
+        rides
+            .iter()
+            .enumerate()
+            .filter(|(_, ride)| {
+                validity
+                    .is_valid_on_day(ride.day_validity, date)
+                    .unwrap_or(false)
+            })
+            .filter(|(_, ride)| ride.start_time() <= to && ride.end_time() >= from)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    #[test]
+    fn index_matches_linear_scan() {
+        let header = Header {
+            company_id: 100,
+            first_valid_date: date(1),
+            last_valid_date: date(7),
+            version: 1,
+            description: String::new(),
+        };
+        let validity = RideValidity::new(
+            header,
+            HashMap::from([
+                (1, vec![true; 7]),
+                (2, vec![true, false, true, false, true, false, true]),
+                (3, vec![false; 7]),
+            ]),
+        );
+
+        // A deterministic spread of rides over the day, some running past midnight
+        let rides: Vec<_> = (0..200)
+            .map(|i| {
+                let start = DayOffset::from_hour_minute((i * 7) % 24, (i * 13) % 60);
+                let end = start.offset_by(((i * 37) % 300) as i32 + 5);
+                ride(i as usize, start, end, (i % 4) as u64)
+            })
+            .collect();
+
+        let index = RideIndex::new(&rides, &validity);
+
+        for day in 0..9 {
+            let date = date(1).checked_add_days(Days::new(day)).unwrap();
+
+            let valid: Vec<_> = index.valid_on(date).collect();
+            let expected = scan(
+                &rides,
+                &validity,
+                date,
+                DayOffset::from_hour_minute(0, 0),
+                DayOffset::from_hour_minute(48, 0),
+            );
+            assert_eq!(valid, expected);
+
+            for hour in [0, 6, 12, 23, 25] {
+                let from = DayOffset::from_hour_minute(hour, 30);
+                let to = from.offset_by(20);
+
+                assert_eq!(
+                    index.active(date, from, to),
+                    scan(&rides, &validity, date, from, to)
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! Binary snapshot of a fully built [`DataRepo`], so starting up doesn't require parsing the IFF timetable again
+//!
+//! A snapshot starts with a [`SnapshotHeader`] and a description of the cached files it was built from,
+//! the repo contents only get decoded when both still match.
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    cache,
+    fetch::{ROUTE_FILEPATH, STATION_FILEPATH, TIMETABLE_PATH},
+    iff::{LocationCache, LocationCodeHandle, ParseMode},
+    realtime::RealtimeState,
+};
+
+use super::{
+    links::{Coords2D, Link},
+    stations::{Station, StationType},
+    DataRepo, TimetableSource, TimetableVersion,
+};
+
+pub const SNAPSHOT_PATH: &str = "snapshot/datarepo.bin";
+
+const MAGIC: [u8; 4] = *b"RRDR";
+/// Bump whenever the layout of the snapshot or any type in it changes
+const FORMAT_VERSION: u32 = 6;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("No snapshot available: {0}")]
+    Io(#[from] io::Error),
+    #[error("Snapshot could not be decoded: {0}")]
+    Decode(#[from] bincode::Error),
+    #[error("Not a snapshot file")]
+    NotASnapshot,
+    #[error("Snapshot format version {found} does not match {expected}")]
+    VersionMismatch { found: u32, expected: u32 },
+    #[error("Snapshot was built from other cached files")]
+    Stale,
+    #[error("Snapshots are only built for IFF timetables")]
+    UnsupportedSource,
+}
+
+/// Leads the snapshot, decoded on its own so snapshots of other format versions are recognised
+#[derive(Serialize, Deserialize)]
+struct SnapshotHeader {
+    magic: [u8; 4],
+    format_version: u32,
+}
+
+/// The cached files a repo was built from, along with the configuration affecting which of them were used
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Inputs {
+    archived_versions: usize,
+    parse_mode: ParseMode,
+    files: Vec<InputFile>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct InputFile {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+impl InputFile {
+    fn new(path: PathBuf) -> Result<Self, io::Error> {
+        let metadata = fs::metadata(&path)?;
+
+        Ok(Self {
+            path,
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+impl Inputs {
+    fn collect(cache_dir: &Path, source: &TimetableSource) -> Result<Self, Error> {
+        let TimetableSource::Iff {
+            archived_versions,
+            parse_mode,
+            extra_stations,
+            ..
+        } = source
+        else {
+            return Err(Error::UnsupportedSource);
+        };
+
+        let mut paths: Vec<_> = [TIMETABLE_PATH, ROUTE_FILEPATH, STATION_FILEPATH]
+            .iter()
+            .map(|path| cache_dir.join(path))
+            .chain(extra_stations.clone())
+            .collect();
+
+        if *archived_versions > 0 {
+            let archived = cache::archived_versions(&cache_dir.join(TIMETABLE_PATH))?;
+
+            paths.extend(
+                archived
+                    .into_iter()
+                    .take(*archived_versions)
+                    .map(|archived| archived.path),
+            );
+        }
+
+        Ok(Self {
+            archived_versions: *archived_versions,
+            parse_mode: *parse_mode,
+            files: paths
+                .into_iter()
+                .map(InputFile::new)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StationRecord {
+    code: String,
+    name: String,
+    position: Coords2D,
+    station_type: StationType,
+    resolved: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LinkRecord {
+    id: u32,
+    from: LocationCodeHandle,
+    to: LocationCodeHandle,
+    coordinates: Vec<Coords2D>,
+    synthetic: bool,
+}
+
+#[derive(Serialize)]
+struct SnapshotBody<'a> {
+    timetables: &'a [TimetableVersion],
+    locations: &'a LocationCache,
+    stations: Vec<StationRecord>,
+    links: Vec<LinkRecord>,
+}
+
+#[derive(Deserialize)]
+struct OwnedSnapshotBody {
+    timetables: Vec<TimetableVersion>,
+    locations: LocationCache,
+    stations: Vec<StationRecord>,
+    links: Vec<LinkRecord>,
+}
+
+/// Writes a snapshot of `repo`, which was built from `source` in `cache_dir`
+pub fn write(repo: &DataRepo, cache_dir: &Path, source: &TimetableSource) -> Result<(), Error> {
+    let header = SnapshotHeader {
+        magic: MAGIC,
+        format_version: FORMAT_VERSION,
+    };
+    let inputs = Inputs::collect(cache_dir, source)?;
+
+    let body = SnapshotBody {
+        timetables: &repo.timetables,
+        locations: &repo.locations,
+        stations: repo
+            .stations
+            .iter()
+            .map(|station| StationRecord {
+                code: station.code.clone(),
+                name: station.name.clone(),
+                position: station.position,
+                station_type: station.station_type,
+                resolved: station.resolved,
+            })
+            .collect(),
+        links: repo
+            .links
+            .iter()
+            .map(|link| {
+                let code = link.link_code();
+                LinkRecord {
+                    id: link.id(),
+                    from: code.0,
+                    to: code.1,
+                    coordinates: link.coordinates(false),
+                    synthetic: link.is_synthetic(),
+                }
+            })
+            .collect(),
+    };
+
+    let mut content = bincode::serialize(&header)?;
+    bincode::serialize_into(&mut content, &inputs)?;
+    bincode::serialize_into(&mut content, &body)?;
+
+    Ok(cache::write_atomic(
+        &cache_dir.join(SNAPSHOT_PATH),
+        &content,
+    )?)
+}
+
+/// Decodes the header and inputs, checking them against this version and the current cached files
+fn check(
+    content: &mut Cursor<Vec<u8>>,
+    cache_dir: &Path,
+    source: &TimetableSource,
+) -> Result<(), Error> {
+    let header: SnapshotHeader =
+        bincode::deserialize_from(&mut *content).map_err(|_| Error::NotASnapshot)?;
+
+    if header.magic != MAGIC {
+        return Err(Error::NotASnapshot);
+    }
+
+    if header.format_version != FORMAT_VERSION {
+        return Err(Error::VersionMismatch {
+            found: header.format_version,
+            expected: FORMAT_VERSION,
+        });
+    }
+
+    let inputs: Inputs = bincode::deserialize_from(content)?;
+    if inputs != Inputs::collect(cache_dir, source)? {
+        return Err(Error::Stale);
+    }
+
+    Ok(())
+}
+
+/// If the snapshot in `cache_dir` was written by this version for the current cached files
+pub fn is_current(cache_dir: &Path, source: &TimetableSource) -> Result<(), Error> {
+    let mut content = Cursor::new(fs::read(cache_dir.join(SNAPSHOT_PATH))?);
+
+    check(&mut content, cache_dir, source)
+}
+
+/// Reads the snapshot in `cache_dir`, failing when it wasn't written by this version for the current cached files
+pub fn read(cache_dir: &Path, source: &TimetableSource) -> Result<DataRepo, Error> {
+    let mut content = Cursor::new(fs::read(cache_dir.join(SNAPSHOT_PATH))?);
+
+    check(&mut content, cache_dir, source)?;
+
+    let mut body: OwnedSnapshotBody = bincode::deserialize_from(content)?;
+    for timetable in &mut body.timetables {
+        timetable.reindex();
+    }
+
+    let links: Vec<Link> = body
+        .links
+        .into_iter()
+        .map(|link| {
+            Link::new(
+                link.id,
+                link.from,
+                link.to,
+                &link.coordinates,
+                link.synthetic,
+            )
+        })
+        .collect();
+
+    let link_map = links
+        .iter()
+        .map(|link| (link.link_code(), link.clone()))
+        .collect();
+
+    let stations = body
+        .stations
+        .into_iter()
+        .map(|station| Station {
+            code: station.code,
+            name: station.name,
+            position: station.position,
+            station_type: station.station_type,
+            resolved: station.resolved,
+        })
+        .collect();
+
+    Ok(DataRepo {
+        links,
+        link_map,
+        stations,
+        timetables: body.timetables,
+        locations: body.locations,
+        realtime: RealtimeState::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iff::test_fixtures::standin_cache_dir;
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    #[test]
+    fn snapshot_round_trips() -> TestResult {
+        let cache_dir = standin_cache_dir("snapshot-round-trip")?;
+        let source = TimetableSource::default();
+
+        let parsed = DataRepo::new(&cache_dir, &source)?;
+        write(&parsed, &cache_dir, &source)?;
+        let loaded = read(&cache_dir, &source)?;
+
+        assert_eq!(loaded.rides(), parsed.rides());
+        assert_eq!(loaded.stations(), parsed.stations());
+        assert_eq!(
+            loaded.location_cache().codes(),
+            parsed.location_cache().codes()
+        );
+        assert_eq!(loaded.version(), parsed.version());
+        assert_eq!(loaded.companies().len(), parsed.companies().len());
+        assert_eq!(loaded.links().len(), parsed.links().len());
+        for (loaded, parsed) in loaded.links().iter().zip(parsed.links()) {
+            assert_eq!(loaded.coordinates(false), parsed.coordinates(false));
+        }
+
+        let ut = loaded.location_cache().lookup_handle("ut");
+        assert_eq!(ut, parsed.location_cache().lookup_handle("ut"));
+
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        assert_eq!(
+            loaded.rides_active_on_date(&date),
+            parsed.rides_active_on_date(&date)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn outdated_snapshots_are_rejected() -> TestResult {
+        let cache_dir = standin_cache_dir("snapshot-outdated")?;
+        let source = TimetableSource::default();
+
+        assert!(matches!(read(&cache_dir, &source), Err(Error::Io(_))));
+
+        write(&DataRepo::new(&cache_dir, &source)?, &cache_dir, &source)?;
+        is_current(&cache_dir, &source)?;
+
+        // Another format version
+        let snapshot_path = cache_dir.join(SNAPSHOT_PATH);
+        let mut content = fs::read(&snapshot_path)?;
+        content[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        fs::write(&snapshot_path, &content)?;
+        assert!(matches!(
+            read(&cache_dir, &source),
+            Err(Error::VersionMismatch { .. })
+        ));
+
+        // Built for another configuration
+        write(&DataRepo::new(&cache_dir, &source)?, &cache_dir, &source)?;
+        let lenient_source = TimetableSource::Iff {
+            archived_versions: 0,
+            parse_mode: ParseMode::Lenient,
+            parallel_parsing: false,
+            extra_stations: None,
+        };
+        assert!(matches!(
+            read(&cache_dir, &lenient_source),
+            Err(Error::Stale)
+        ));
+
+        // Cached files changed since
+        let stations = fs::read(cache_dir.join(STATION_FILEPATH))?;
+        fs::write(
+            cache_dir.join(STATION_FILEPATH),
+            [&stations[..], b"\n"].concat(),
+        )?;
+        assert!(matches!(read(&cache_dir, &source), Err(Error::Stale)));
+
+        fs::write(&snapshot_path, b"garbage")?;
+        assert!(matches!(
+            read(&cache_dir, &source),
+            Err(Error::NotASnapshot)
+        ));
+
+        Ok(())
+    }
+}
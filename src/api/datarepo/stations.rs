@@ -1,5 +1,11 @@
+use figment::{
+    providers::{Format, Toml},
+    Figment,
+};
 use serde::{Deserialize, Serialize, Serializer};
-use std::{fs::File, io::BufReader, str::FromStr};
+use std::{collections::HashSet, fs::File, io::BufReader, path::Path, str::FromStr};
+
+use crate::iff::{IffStation, LocationCache};
 
 use super::links::Coords2D;
 
@@ -12,6 +18,9 @@ pub struct Station {
     #[serde(serialize_with = "serialize_station_to_rank")]
     #[serde(rename(serialize = "rank"))]
     pub station_type: StationType,
+    /// Added by [`resolve_unknown_stations`] for lack of NS station data
+    #[serde(skip)]
+    pub resolved: bool,
 }
 
 impl Station {
@@ -21,6 +30,7 @@ impl Station {
             name: json.namen.lang.clone(),
             position: Coords2D::new(json.lng, json.lat),
             station_type: StationType::from_str(&json.stationType).unwrap(),
+            resolved: false,
         }
     }
 }
@@ -49,7 +59,7 @@ struct StationJSON {
     stationType: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum StationType {
     Mega,
     InterCityTransfer,
@@ -136,6 +146,71 @@ pub fn extract_stations(file: &File) -> Vec<Station> {
         .collect()
 }
 
+/// Stations missing from the NS station data, as listed in a supplementary stations file
+#[derive(Deserialize)]
+struct ExtraStations {
+    #[serde(default)]
+    stations: Vec<ExtraStation>,
+}
+
+#[derive(Deserialize)]
+struct ExtraStation {
+    code: String,
+    name: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+/// Reads the stations listed in the TOML file at `path`
+pub fn read_extra_stations(path: &Path) -> Result<Vec<Station>, Box<figment::Error>> {
+    let extra: ExtraStations = Figment::from(Toml::file_exact(path)).extract()?;
+
+    Ok(extra
+        .stations
+        .into_iter()
+        .map(|station| Station {
+            code: station.code.to_lowercase(),
+            name: station.name,
+            position: Coords2D::new(station.longitude, station.latitude),
+            // Foreign calls are mostly made at major stations
+            station_type: StationType::InterCity,
+            resolved: true,
+        })
+        .collect())
+}
+
+/// Adds the timetable points missing from `stations` that `extra` lists, or that the IFF stations file has a position for
+/// Returns the number of stations added
+pub fn resolve_unknown_stations(
+    stations: &mut Vec<Station>,
+    extra: Vec<Station>,
+    iff_stations: &[IffStation],
+    locations: &LocationCache,
+) -> usize {
+    let mut known: HashSet<String> = stations.iter().map(|s| s.code.clone()).collect();
+    let before = stations.len();
+
+    let from_iff = iff_stations.iter().filter_map(|station| {
+        let (x, y) = station.position?;
+
+        Some(Station {
+            code: station.code.to_lowercase(),
+            name: station.name.to_string(),
+            position: Coords2D::from_rijksdriehoek(x.into(), y.into()),
+            station_type: StationType::InterCity,
+            resolved: true,
+        })
+    });
+
+    for station in extra.into_iter().chain(from_iff) {
+        if locations.lookup_handle(&station.code).is_some() && known.insert(station.code.clone()) {
+            stations.push(station);
+        }
+    }
+
+    stations.len() - before
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,7 +229,8 @@ mod tests {
                 code: String::from("gp"),
                 name: String::from("Geldrop"),
                 position: Coords2D::new(5.55055570602417, 51.4197235107422),
-                station_type: StationType::LocalTransfer
+                station_type: StationType::LocalTransfer,
+                resolved: false,
             }
         )
     }
@@ -0,0 +1,189 @@
+//! Lookup of the calls at a station, so station queries don't have to walk the timetable of every ride
+use std::collections::HashMap;
+
+use crate::{
+    dayoffset::DayOffset,
+    iff::{LocationCodeHandle, Ride},
+};
+
+/// A call of a ride at a station
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StopRef {
+    /// Index of the ride in its timetable version
+    pub ride: usize,
+    /// Index of the stop in the timetable of the ride
+    pub stop: usize,
+    pub time: DayOffset,
+}
+
+#[derive(Default)]
+struct StationStops {
+    /// Boardable stops, sorted by departure time
+    departures: Vec<StopRef>,
+    /// Stops with an arrival time, sorted by arrival time
+    arrivals: Vec<StopRef>,
+}
+
+/// Calls at every station of a timetable version, sorted by time
+#[derive(Default)]
+pub struct StopIndex {
+    stations: HashMap<LocationCodeHandle, StationStops>,
+}
+
+impl StopIndex {
+    pub fn new(rides: &[Ride]) -> Self {
+        let mut stations: HashMap<_, StationStops> = HashMap::new();
+
+        for (ride, r) in rides.iter().enumerate() {
+            for (stop, entry) in r.timetable.iter().enumerate() {
+                let call = |time: &DayOffset| StopRef {
+                    ride,
+                    stop,
+                    time: *time,
+                };
+                if entry.stop_kind.is_waypoint() {
+                    continue;
+                }
+                let stops = stations.entry(entry.code).or_default();
+
+                if let Some(time) = entry.stop_kind.departure_time() {
+                    if entry.stop_kind.is_boardable() {
+                        stops.departures.push(call(time));
+                    }
+                }
+                if let Some(time) = entry.stop_kind.arrival_time() {
+                    stops.arrivals.push(call(time));
+                }
+            }
+        }
+
+        for stops in stations.values_mut() {
+            stops.departures.sort_by_key(|call| call.time);
+            stops.arrivals.sort_by_key(|call| call.time);
+        }
+
+        Self { stations }
+    }
+
+    /// Departures from `station` between `from` and `to`, both inclusive
+    pub fn departures(
+        &self,
+        station: &LocationCodeHandle,
+        from: DayOffset,
+        to: DayOffset,
+    ) -> &[StopRef] {
+        self.stations
+            .get(station)
+            .map_or(&[], |stops| between(&stops.departures, from, to))
+    }
+
+    /// Arrivals at `station` between `from` and `to`, both inclusive
+    pub fn arrivals(
+        &self,
+        station: &LocationCodeHandle,
+        from: DayOffset,
+        to: DayOffset,
+    ) -> &[StopRef] {
+        self.stations
+            .get(station)
+            .map_or(&[], |stops| between(&stops.arrivals, from, to))
+    }
+}
+
+fn between(calls: &[StopRef], from: DayOffset, to: DayOffset) -> &[StopRef] {
+    let start = calls.partition_point(|call| call.time < from);
+    let end = calls.partition_point(|call| call.time <= to);
+
+    &calls[start..end.max(start)]
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::iff::{LocationCache, StopKind, TimetableEntry};
+
+    use super::*;
+
+    #[test]
+    fn calls_are_found_by_station_and_time() {
+        let mut locations = LocationCache::new();
+        let (ut, wd, gd) = (
+            locations.get_handle("ut"),
+            locations.get_handle("wd"),
+            locations.get_handle("gd"),
+        );
+        let time = DayOffset::from_hour_minute;
+
+        let ride = |id: &str, timetable: Vec<(LocationCodeHandle, StopKind)>| Ride {
+            id: id.to_owned(),
+            transit_mode: "SPR".to_owned(),
+            timetable: timetable
+                .into_iter()
+                .map(|(code, stop_kind)| TimetableEntry { code, stop_kind })
+                .collect(),
+            day_validity: 1,
+            previous: None,
+            next: None,
+            operator: 100,
+        };
+
+        let rides = [
+            ride(
+                "2871",
+                vec![
+                    (ut, StopKind::Departure(vec![], time(10, 8))),
+                    (wd, StopKind::StopShort(vec![], time(10, 18))),
+                    (gd, StopKind::Arrival(vec![], time(10, 27))),
+                ],
+            ),
+            ride(
+                "2870",
+                vec![
+                    (gd, StopKind::Departure(vec![], time(10, 2))),
+                    (wd, StopKind::Waypoint),
+                    (ut, StopKind::Arrival(vec![], time(10, 21))),
+                ],
+            ),
+            ride(
+                "2873",
+                vec![
+                    (ut, StopKind::Departure(vec![], time(9, 38))),
+                    (wd, StopKind::StopLong(vec![], time(9, 46), time(9, 48))),
+                    (gd, StopKind::Arrival(vec![], time(9, 57))),
+                ],
+            ),
+        ];
+
+        let index = StopIndex::new(&rides);
+
+        assert_eq!(
+            index.departures(&wd, time(0, 0), time(23, 59)),
+            [
+                StopRef {
+                    ride: 2,
+                    stop: 1,
+                    time: time(9, 48)
+                },
+                StopRef {
+                    ride: 0,
+                    stop: 1,
+                    time: time(10, 18)
+                },
+            ]
+        );
+        assert_eq!(
+            index.arrivals(&wd, time(9, 46), time(9, 46)),
+            [StopRef {
+                ride: 2,
+                stop: 1,
+                time: time(9, 46)
+            }]
+        );
+
+        // Arrivals at the end of a ride aren't boardable
+        assert!(index.departures(&gd, time(9, 0), time(10, 0)).is_empty());
+        assert_eq!(index.arrivals(&gd, time(9, 0), time(11, 0)).len(), 2);
+        assert!(index.departures(&ut, time(10, 9), time(9, 0)).is_empty());
+    }
+}
@@ -17,3 +17,34 @@ impl ResponseError for UpstreamError {
         StatusCode::INTERNAL_SERVER_ERROR
     }
 }
+
+/// Request argument that didn't pass validation, the message is returned to the client
+#[derive(Error, Debug)]
+pub struct InvalidArgument(pub &'static str);
+
+impl Display for InvalidArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl ResponseError for InvalidArgument {
+    fn status(&self) -> poem::http::StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+#[derive(Error, Debug)]
+pub struct RateLimited;
+
+impl Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Too many requests")
+    }
+}
+
+impl ResponseError for RateLimited {
+    fn status(&self) -> poem::http::StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+}
@@ -1,10 +1,15 @@
-use crate::api::{datarepo::DataRepo, errorresponse::UpstreamError};
+use crate::api::{
+    datarepo::DataRepo,
+    errorresponse::{RateLimited, UpstreamError},
+    rate_limit::RateLimiter,
+    ttl_cache::CacheStats,
+    TripCache,
+};
 
 use super::RoutePlannerResponse;
 
-use ns_api::TripAdviceArguments;
-
-use poem::{handler, http::header, IntoResponse, Response, Result};
+use poem::{handler, http::header, web::Json, IntoResponse, Request, Response, Result};
+use serde::Serialize;
 
 use super::PathfindingArguments;
 
@@ -20,20 +25,35 @@ pub async fn route_finding_endpoint(
     datarepo: Data<&Arc<DataRepo>>,
     query: poem::web::Query<PathfindingArguments>,
     station_allow_list: Data<&Arc<HashSet<Box<str>>>>,
+    trip_cache: Data<&Arc<TripCache>>,
+    rate_limiter: Data<&Arc<RateLimiter>>,
+    req: &Request,
 ) -> Result<Response> {
-    query.validate(&station_allow_list);
+    if let Some(addr) = req.remote_addr().as_socket_addr() {
+        if !rate_limiter.check(rate_limiter.client(addr.ip(), req.headers())) {
+            return Err(RateLimited.into());
+        }
+    }
+
+    query.validate(&station_allow_list)?;
 
     println!("Request from: {} to: {}", query.from, query.to);
 
-    let ns_data = ns_api
-        .find_path(&TripAdviceArguments {
-            from: &query.from,
-            to: &query.to,
-            via: None,
-        })
-        .await
-        .map_err(|e| eprint!("{:?}", e))
-        .map_err(|_| UpstreamError {})?;
+    let args = query.trip_advice_arguments();
+    let ns_data = match trip_cache.get(&args) {
+        Some(ns_data) => ns_data,
+        None => {
+            let ns_data = ns_api
+                .find_path(&args)
+                .await
+                .map_err(|e| eprint!("{:?}", e))
+                .map_err(|_| UpstreamError {})?;
+
+            let ns_data = Arc::new(ns_data);
+            trip_cache.insert(args, ns_data.clone());
+            ns_data
+        }
+    };
 
     let out = RoutePlannerResponse::new(&ns_data, &datarepo);
     let body = serde_json::to_vec(&out)
@@ -45,3 +65,21 @@ pub async fn route_finding_endpoint(
         .body(body)
         .into_response())
 }
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteFindingStats {
+    cache: CacheStats,
+    rate_limited: u64,
+}
+
+#[handler]
+pub fn route_finding_stats_endpoint(
+    trip_cache: Data<&Arc<TripCache>>,
+    rate_limiter: Data<&Arc<RateLimiter>>,
+) -> Json<RouteFindingStats> {
+    Json(RouteFindingStats {
+        cache: trip_cache.stats(),
+        rate_limited: rate_limiter.rejected(),
+    })
+}
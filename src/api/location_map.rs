@@ -1,4 +1,3 @@
-use chrono::Duration;
 use poem::{handler, http::header, Response};
 
 use std::sync::Arc;
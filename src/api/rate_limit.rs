@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use poem::http::HeaderMap;
+
+/// Client count after which clients with an expired window are forgotten
+const CLIENT_CLEANUP_THRESHOLD: usize = 10_000;
+
+/// Allows every client `limit` requests per fixed `window`
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    clients: Mutex<HashMap<IpAddr, (Instant, u32)>>,
+    rejected: AtomicU64,
+    /// Peers whose forwarding headers are believed
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            clients: Mutex::new(HashMap::new()),
+            rejected: AtomicU64::new(0),
+            trusted_proxies: vec![],
+        }
+    }
+
+    pub fn trusted_proxies(mut self, proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// Address of the client behind `peer`, read from the forwarding headers if `peer` is a trusted proxy
+    ///
+    /// `X-Forwarded-For` is read from the right, skipping trusted proxies, since clients can prepend any address
+    pub fn client(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusted_proxies.contains(&peer) {
+            return peer;
+        }
+
+        let forwarded_for = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|addr| addr.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+
+        let forwarded_client = forwarded_for
+            .into_iter()
+            .rev()
+            .take_while(Option::is_some)
+            .flatten()
+            .find(|addr| !self.trusted_proxies.contains(addr));
+
+        let real_ip = || {
+            headers
+                .get("x-real-ip")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+        };
+
+        forwarded_client.or_else(real_ip).unwrap_or(peer)
+    }
+
+    /// Counts a request by `client`, returns false if the client exceeded its limit
+    pub fn check(&self, client: IpAddr) -> bool {
+        let mut clients = self.clients.lock().unwrap();
+
+        if clients.len() > CLIENT_CLEANUP_THRESHOLD {
+            clients.retain(|_, (window_start, _)| window_start.elapsed() < self.window);
+        }
+
+        let (window_start, count) = clients.entry(client).or_insert((Instant::now(), 0));
+        if window_start.elapsed() >= self.window {
+            *window_start = Instant::now();
+            *count = 0;
+        }
+
+        *count += 1;
+
+        let allowed = *count <= self.limit;
+        if !allowed {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+
+        allowed
+    }
+
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use poem::http::{HeaderName, HeaderValue};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn limits_per_client() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let a = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert!(limiter.check(a));
+        assert!(limiter.check(a));
+        assert!(!limiter.check(a));
+        assert!(limiter.check(b));
+        assert_eq!(limiter.rejected(), 1);
+    }
+
+    #[test]
+    fn clients_behind_trusted_proxies() {
+        let proxy = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let limiter = RateLimiter::new(1, Duration::from_secs(60)).trusted_proxies(vec![proxy]);
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        HeaderName::from_static(name),
+                        HeaderValue::from_static(value),
+                    )
+                })
+                .collect::<HeaderMap>()
+        };
+        let a = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        let from_a = headers(&[("x-forwarded-for", "10.0.0.1")]);
+        let from_b = headers(&[("x-real-ip", "10.0.0.2")]);
+        assert_eq!(limiter.client(proxy, &from_a), a);
+        assert_eq!(limiter.client(proxy, &from_b), b);
+        assert_eq!(limiter.client(proxy, &headers(&[])), proxy);
+
+        // Addresses the client added itself are ignored, only the one the proxy appended counts
+        let spoofed = headers(&[("x-forwarded-for", "192.168.0.1, 10.0.0.1, 127.0.0.1")]);
+        assert_eq!(limiter.client(proxy, &spoofed), a);
+        let garbage = headers(&[("x-forwarded-for", "10.0.0.2, unknown, 10.0.0.1")]);
+        assert_eq!(limiter.client(proxy, &garbage), a);
+
+        // Untrusted peers can't pick their own address
+        assert_eq!(limiter.client(b, &from_a), b);
+
+        assert!(limiter.check(limiter.client(proxy, &from_a)));
+        assert!(limiter.check(limiter.client(proxy, &from_b)));
+        assert!(!limiter.check(limiter.client(proxy, &from_a)));
+    }
+
+    #[test]
+    fn window_resets() {
+        let limiter = RateLimiter::new(1, Duration::ZERO);
+        let a = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+        assert!(limiter.check(a));
+        assert!(limiter.check(a));
+    }
+}
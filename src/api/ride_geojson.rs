@@ -0,0 +1,53 @@
+use chrono::NaiveDate;
+use poem::{
+    handler,
+    http::{header, StatusCode},
+    web::Query,
+    IntoResponse, Response,
+};
+use serde::Deserialize;
+
+use std::sync::Arc;
+
+use poem::web::Data;
+
+use crate::{api::datarepo::DataRepo, geojson::FeatureCollection, time};
+
+#[derive(Deserialize)]
+pub struct RideGeoJsonArguments {
+    /// Ride to export, all rides valid on `date` are exported when omitted
+    id: Option<String>,
+    /// Defaults to today
+    date: Option<NaiveDate>,
+}
+
+#[handler]
+pub fn ride_geojson_endpoint(
+    data: Data<&Arc<DataRepo>>,
+    query: Query<RideGeoJsonArguments>,
+) -> Response {
+    let date = query
+        .date
+        .unwrap_or_else(|| time::timetable_now().date_naive());
+
+    if !data.covers_date(date) {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let rides = match &query.id {
+        Some(id) => data.rides_with_id_on_date(id, &date),
+        None => data.rides_active_on_date(&date),
+    };
+
+    let data = serde_json::to_vec(&FeatureCollection::from_rides(&data, rides, date));
+
+    match data {
+        Ok(json) => Response::builder()
+            .header(header::CONTENT_TYPE, "application/geo+json; charset=utf-8")
+            .body(json),
+        Err(e) => {
+            eprintln!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// In-memory cache where entries expire `ttl` after insertion
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl<K: Hash + Eq, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+
+        let value = entries
+            .get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone());
+
+        let counter = match value {
+            Some(_) => &self.hits,
+            None => &self.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        value
+    }
+
+    /// Inserts `value`, when the cache is full expired entries are dropped first, then the oldest entry
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, (inserted, _)| inserted.elapsed() < self.ttl);
+        }
+
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries.values().map(|(inserted, _)| *inserted).min();
+
+            if let Some(oldest) = oldest {
+                entries.retain(|_, (inserted, _)| *inserted != oldest);
+            }
+        }
+
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = TtlCache::new(Duration::from_secs(60), 10);
+
+        assert_eq!(cache.get(&"ut-gd"), None);
+        cache.insert("ut-gd", 1);
+        assert_eq!(cache.get(&"ut-gd"), Some(1));
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 1,
+                entries: 1
+            }
+        );
+    }
+
+    #[test]
+    fn entries_expire() {
+        let cache = TtlCache::new(Duration::ZERO, 10);
+
+        cache.insert("ut-gd", 1);
+        assert_eq!(cache.get(&"ut-gd"), None);
+    }
+
+    #[test]
+    fn full_cache_evicts_oldest() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+
+        cache.insert("ut-gd", 1);
+        std::thread::sleep(Duration::from_millis(1));
+        cache.insert("ut-asd", 2);
+        cache.insert("ut-rtd", 3);
+
+        assert_eq!(cache.get(&"ut-gd"), None);
+        assert_eq!(cache.get(&"ut-asd"), Some(2));
+        assert_eq!(cache.get(&"ut-rtd"), Some(3));
+    }
+}
@@ -1,19 +1,31 @@
 // use core::fmt;
 use std::{
+    ffi::OsString,
     fmt::Debug,
     fs::{self},
     future::Future,
-    path::{Path, PathBuf}, // time::Duration,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug)]
+#[allow(dead_code)]
 pub enum Action {
     Sourced,
     Updated {
         archived: PathBuf,
         new: PathBuf,
-        versions: Option<(String, String)>,
+        report: UpdateReport,
     },
+    /// The remote content is older than the cached content, which is kept
+    RejectedDowngrade(Option<(String, String)>),
+    /// Refreshed after exceeding its max age
+    Refreshed,
+    /// The source reported the content unchanged since the last download
+    NotModified,
     Skipped,
 }
 
@@ -25,13 +37,48 @@ pub trait SourceAsync<E: Into<Box<dyn std::error::Error>>> {
     async fn get_async(&self) -> Result<Vec<u8>, E>;
 }
 
+#[allow(dead_code)]
 pub trait Source<E> {
     fn get(&self) -> Result<Vec<u8>, E>;
 }
 
+/// HTTP validators of a download, sent along with the next request to allow a "not modified" answer
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Result of a conditional request
+pub enum Fetched {
+    NotModified,
+    Content {
+        data: Vec<u8>,
+        validators: Validators,
+    },
+}
+
+/// A source that can skip sending content the cache already has, given the validators of the cached copy
+pub trait ConditionalSourceAsync<E: Into<Box<dyn std::error::Error>>> {
+    async fn get_conditional_async(&self, validators: Validators) -> Result<Fetched, E>;
+}
+
+/// Outcome of comparing cached content with remote content, versions are (cached, remote)
+#[derive(Debug, PartialEq, Eq)]
 pub enum UpdateReport {
     NotRequired,
+    /// The remote content is newer
     Required(Option<(String, String)>),
+    /// Same version but different content, e.g. a republished delivery, replaces the cached content
+    ContentChanged(Option<String>),
+    /// The remote content is older, the cached content is kept
+    Downgrade(Option<(String, String)>),
 }
 
 /// Implementations on bare functions for convinience
@@ -46,6 +93,18 @@ where
     }
 }
 
+/// Implementations on bare functions for convinience
+impl<E, F, Fut> ConditionalSourceAsync<E> for F
+where
+    E: Into<Box<dyn std::error::Error>>,
+    F: Fn(Validators) -> Fut,
+    Fut: Future<Output = Result<Fetched, E>>,
+{
+    async fn get_conditional_async(&self, validators: Validators) -> Result<Fetched, E> {
+        self(validators).await
+    }
+}
+
 /// Implementations on bare functions for convinience
 impl<E, F> Source<E> for F
 where
@@ -87,6 +146,133 @@ fn append_to_file_stem(path: &mut PathBuf, suffix: &str) -> Result<(), std::io::
     Ok(())
 }
 
+/// Appends `suffix` to the full file name, extension included
+fn with_file_name_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut filename = path.file_name().map(OsString::from).unwrap_or_default();
+    filename.push(suffix);
+
+    path.with_file_name(filename)
+}
+
+/// Path of the file storing the validators of `path`
+fn validators_path(path: &Path) -> PathBuf {
+    with_file_name_suffix(path, ".validators.json")
+}
+
+/// Writes `content` to a temporary file next to `path` and renames it into place,
+/// so `path` either holds the old or the complete new content, never a partial write
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp_path = with_file_name_suffix(path, ".partial");
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temp_path, path)
+}
+
+fn read_validators(path: &Path) -> Validators {
+    fs::read(validators_path(path))
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_validators(path: &Path, validators: &Validators) -> Result<(), std::io::Error> {
+    let validators_path = validators_path(path);
+
+    if validators.is_empty() {
+        return match fs::remove_file(validators_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let content = serde_json::to_vec(validators).map_err(std::io::Error::other)?;
+    write_atomic(&validators_path, &content)
+}
+
+/// Whether the file at `path` was last written longer than `max_age` ago
+fn is_expired(path: &Path, max_age: Duration) -> Result<bool, std::io::Error> {
+    let modified = fs::metadata(path)?.modified()?;
+
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|age| age > max_age))
+}
+
+/// Which archived versions of a file are kept, versions exceeding either limit are deleted
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Number of most recent archived versions to keep
+    pub keep_versions: Option<usize>,
+    /// Days an archived version is kept after being archived
+    pub keep_days: Option<u64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            keep_versions: Some(10),
+            keep_days: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ArchivedFile {
+    pub path: PathBuf,
+    /// Sequence number in the file name, higher numbers were archived later
+    pub number: u32,
+    pub archived_at: SystemTime,
+}
+
+/// Lists the archived versions of `path`, named `<stem>_<number>.<extension>`, newest first
+pub fn archived_versions(path: &Path) -> Result<Vec<ArchivedFile>, std::io::Error> {
+    let (Some(stem), Some(dir)) = (path.file_stem().and_then(|s| s.to_str()), path.parent()) else {
+        return Ok(vec![]);
+    };
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{e}"))
+        .unwrap_or_default();
+    let prefix = format!("{stem}_");
+
+    if !dir.try_exists()? {
+        return Ok(vec![]);
+    }
+
+    let mut archived = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let number = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|name| name.strip_suffix(&extension))
+            .and_then(|number| number.parse().ok());
+
+        if let Some(number) = number {
+            archived.push(ArchivedFile {
+                path: entry.path(),
+                number,
+                archived_at: entry.metadata()?.modified()?,
+            });
+        }
+    }
+
+    archived.sort_by_key(|archived| std::cmp::Reverse(archived.number));
+
+    Ok(archived)
+}
+
 impl Cache {
     pub fn new(base_dir: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
         fs::create_dir_all(base_dir.as_ref())?;
@@ -96,33 +282,96 @@ impl Cache {
         })
     }
 
+    /// Moves `filepath` aside as the newest archived version, numbered after all existing archived versions
     fn archive_file(filepath: impl AsRef<Path>) -> Result<PathBuf, std::io::Error> {
-        const MAX_FREE_PATH_ATTEMPTS: u16 = 512;
-
-        for n in 1..MAX_FREE_PATH_ATTEMPTS {
-            let mut path_candidate = filepath.as_ref().to_owned();
-            append_to_file_stem(
-                &mut path_candidate,
-                ("_".to_owned() + &n.to_string()).as_str(),
-            )?;
-
-            println!("Path candidate: {}", path_candidate.display());
-            let is_empty = path_candidate.try_exists().is_ok_and(|exists| !exists);
-            println!("is empty: {:?}", is_empty);
-            if is_empty {
-                println!(
-                    "Renaming from {} to {}",
-                    filepath.as_ref().display(),
-                    path_candidate.display()
-                );
-                std::fs::rename(filepath, &path_candidate)?;
-                return Ok(path_candidate);
+        let filepath = filepath.as_ref();
+        let next = archived_versions(filepath)?
+            .iter()
+            .map(|archived| archived.number)
+            .max()
+            .unwrap_or_default()
+            + 1;
+
+        let mut archived_path = filepath.to_owned();
+        append_to_file_stem(&mut archived_path, &format!("_{next}"))?;
+
+        eprintln!(
+            "Archiving {} as {}",
+            filepath.display(),
+            archived_path.display()
+        );
+        fs::rename(filepath, &archived_path)?;
+
+        // The modification time marks when the file was archived, which retention is based on
+        fs::File::options()
+            .write(true)
+            .open(&archived_path)?
+            .set_modified(SystemTime::now())?;
+
+        Ok(archived_path)
+    }
+
+    /// Archived versions of `output_path`, newest first
+    pub fn archived_files(
+        &self,
+        output_path: impl AsRef<Path>,
+    ) -> Result<Vec<ArchivedFile>, std::io::Error> {
+        archived_versions(&self.base_dir.join(output_path))
+    }
+
+    /// Deletes the archived versions of `output_path` the policy doesn't keep, returns the deleted paths
+    pub fn apply_retention(
+        &self,
+        output_path: impl AsRef<Path>,
+        policy: &RetentionPolicy,
+    ) -> Result<Vec<PathBuf>, std::io::Error> {
+        let now = SystemTime::now();
+        let max_age = policy
+            .keep_days
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+
+        let mut removed = vec![];
+
+        for (index, archived) in self.archived_files(output_path)?.into_iter().enumerate() {
+            let too_many = policy.keep_versions.is_some_and(|keep| index >= keep);
+            let too_old = max_age.is_some_and(|max_age| {
+                now.duration_since(archived.archived_at)
+                    .is_ok_and(|age| age > max_age)
+            });
+
+            if too_many || too_old {
+                fs::remove_file(&archived.path)?;
+                removed.push(archived.path);
             }
         }
 
-        Err(std::io::Error::other("out of possible candidate paths"))
+        Ok(removed)
+    }
+
+    /// Makes the archived file at `archived` the active `output_path` again,
+    /// archiving the currently active file if any and returning where it was archived to
+    pub fn restore(
+        &self,
+        output_path: impl AsRef<Path>,
+        archived: &Path,
+    ) -> Result<Option<PathBuf>, std::io::Error> {
+        let file_path = self.base_dir.join(output_path);
+
+        let previous = if file_path.try_exists()? {
+            Some(Self::archive_file(&file_path)?)
+        } else {
+            None
+        };
+
+        fs::rename(archived, &file_path)?;
+        // The stored validators describe the previously active content
+        write_validators(&file_path, &Validators::default())?;
+
+        Ok(previous)
     }
 
+    /// Downloads `output_path` from `source` unless the source reports it unchanged,
+    /// replacing the cached file and archiving the old one when `update_fn` decides so
     pub async fn ensure_versioned_async<SourceFn, SourceErr, VersionError, UpdateFn>(
         &self,
         source: SourceFn,
@@ -130,19 +379,34 @@ impl Cache {
         update_fn: UpdateFn,
     ) -> Result<Action, Error>
     where
-        SourceFn: SourceAsync<SourceErr>,
+        SourceFn: ConditionalSourceAsync<SourceErr>,
         UpdateFn: Fn(&[u8], &[u8]) -> Result<UpdateReport, VersionError>, // E: Error + Send + Sync + 'static,
         SourceErr: Into<Box<dyn std::error::Error>>,
         VersionError: Into<Box<dyn std::error::Error>>,
     {
         let file_path = self.base_dir.join(output_path);
-        let source_result = source.get_async().await;
-        let remote_content = source_result.map_err(|e: SourceErr| Error::Source(e.into()))?;
+        let exists = file_path.try_exists().is_ok_and(|exists| exists);
 
-        if file_path.try_exists().is_err() || file_path.try_exists().is_ok_and(|f| !f) {
-            println!("File path {} seems empty, creating", file_path.display());
-            fs::create_dir_all(file_path.parent().expect("valid parent dir"))?;
-            fs::write(file_path, remote_content)?;
+        // Validators are only meaningful while the content they describe is still there
+        let validators = if exists {
+            read_validators(&file_path)
+        } else {
+            Validators::default()
+        };
+
+        let (remote_content, validators) = match source
+            .get_conditional_async(validators)
+            .await
+            .map_err(|e: SourceErr| Error::Source(e.into()))?
+        {
+            Fetched::NotModified => return Ok(Action::NotModified),
+            Fetched::Content { data, validators } => (data, validators),
+        };
+
+        if !exists {
+            eprintln!("File path {} seems empty, creating", file_path.display());
+            write_atomic(&file_path, &remote_content)?;
+            write_validators(&file_path, &validators)?;
 
             Ok(Action::Sourced)
         } else {
@@ -150,26 +414,37 @@ impl Cache {
             let update_required = update_fn(&existing_content, &remote_content)
                 .map_err(|e| Error::UpdateFunction(e.into()))?;
 
-            match update_required {
-                UpdateReport::NotRequired => Ok(Action::Skipped),
-                UpdateReport::Required(version_info) => {
+            let action = match update_required {
+                UpdateReport::NotRequired => Action::Skipped,
+                UpdateReport::Downgrade(versions) => Action::RejectedDowngrade(versions),
+                report @ (UpdateReport::Required(_) | UpdateReport::ContentChanged(_)) => {
+                    // Stage the new content first, the cached file is only touched once it's complete
+                    let staged_path = with_file_name_suffix(&file_path, ".new");
+                    write_atomic(&staged_path, &remote_content)?;
+
                     let archived_path = Self::archive_file(&file_path)?;
-                    fs::write(&file_path, &remote_content)?;
+                    fs::rename(&staged_path, &file_path)?;
+                    // Rejected content keeps the old validators, so it's fetched and judged again next time
+                    write_validators(&file_path, &validators)?;
 
-                    Ok(Action::Updated {
+                    Action::Updated {
                         archived: archived_path,
-                        new: file_path,
-                        versions: version_info,
-                    })
+                        new: file_path.clone(),
+                        report,
+                    }
                 }
-            }
+            };
+
+            Ok(action)
         }
     }
 
+    /// Downloads `output_path` from `source` when it's missing, or older than `max_age` if given
     pub async fn ensure_async<SourceFn, SourceErr>(
         &self,
         source: SourceFn,
         output_path: impl AsRef<Path>,
+        max_age: Option<Duration>,
     ) -> Result<Action, Error>
     where
         SourceFn: SourceAsync<SourceErr>,
@@ -177,18 +452,22 @@ impl Cache {
     {
         let file_path = self.base_dir.join(output_path);
 
-        if !file_path.exists() {
-            let remote_content = source
-                .get_async()
-                .await
-                .map_err(|a| Error::Source(a.into()))?;
+        let action = if !file_path.exists() {
+            Action::Sourced
+        } else if max_age.is_some_and(|max_age| is_expired(&file_path, max_age).unwrap_or(true)) {
+            Action::Refreshed
+        } else {
+            return Ok(Action::Skipped);
+        };
 
-            fs::write(file_path, remote_content)?;
+        let remote_content = source
+            .get_async()
+            .await
+            .map_err(|a| Error::Source(a.into()))?;
 
-            Ok(Action::Sourced)
-        } else {
-            Ok(Action::Skipped)
-        }
+        write_atomic(&file_path, &remote_content)?;
+
+        Ok(action)
     }
 }
 
@@ -196,7 +475,26 @@ impl Cache {
 mod tests {
 
     use super::Error as SuperError;
-    use std::error::Error;
+    use super::*;
+    use crate::iff::test_fixtures::TempDir;
+    use std::{convert::Infallible, error::Error};
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    /// A cache in its own temp dir, which is removed when the returned guard is dropped
+    fn test_cache(name: &str) -> Result<(TempDir, Cache), anyhow::Error> {
+        let dir = TempDir::new(&format!("cache-{name}"))?;
+        let cache = Cache::new(&*dir)?;
+
+        Ok((dir, cache))
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new()
+            .expect("a runtime")
+            .block_on(future)
+    }
 
     #[test]
     fn source_err() {
@@ -207,4 +505,163 @@ mod tests {
         let source = a.source().expect("source to be set").to_string();
         assert_eq!(source, "foo".to_owned())
     }
+
+    #[test]
+    fn conditional_source_gets_stored_validators() -> TestResult {
+        let (_dir, cache) = test_cache("conditional")?;
+        let always_update = |_: &[u8], _: &[u8]| Ok::<_, Infallible>(UpdateReport::Required(None));
+
+        let first = block_on(cache.ensure_versioned_async(
+            |validators: Validators| async move {
+                assert_eq!(validators, Validators::default());
+                Ok::<_, Infallible>(Fetched::Content {
+                    data: b"v1".to_vec(),
+                    validators: Validators {
+                        etag: Some("\"v1\"".to_owned()),
+                        last_modified: None,
+                    },
+                })
+            },
+            "file.zip",
+            always_update,
+        ))?;
+        assert!(matches!(first, Action::Sourced));
+
+        let second = block_on(cache.ensure_versioned_async(
+            |validators: Validators| async move {
+                assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+                Ok::<_, Infallible>(Fetched::NotModified)
+            },
+            "file.zip",
+            always_update,
+        ))?;
+        assert!(matches!(second, Action::NotModified));
+        assert_eq!(fs::read(cache.base_dir.join("file.zip"))?, b"v1");
+
+        let third = block_on(cache.ensure_versioned_async(
+            |_| async {
+                Ok::<_, Infallible>(Fetched::Content {
+                    data: b"v2".to_vec(),
+                    validators: Validators::default(),
+                })
+            },
+            "file.zip",
+            always_update,
+        ))?;
+        let Action::Updated { archived, new, .. } = third else {
+            panic!("Expected an update, got {third:?}");
+        };
+        assert_eq!(fs::read(archived)?, b"v1");
+        assert_eq!(fs::read(new)?, b"v2");
+        assert!(!validators_path(&cache.base_dir.join("file.zip")).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejected_updates_keep_validators() -> TestResult {
+        let (_dir, cache) = test_cache("rejected")?;
+        let fetched = |etag: &'static str| {
+            move |_| async move {
+                Ok::<_, Infallible>(Fetched::Content {
+                    data: etag.as_bytes().to_vec(),
+                    validators: Validators {
+                        etag: Some(etag.to_owned()),
+                        last_modified: None,
+                    },
+                })
+            }
+        };
+        let stored_etag = || read_validators(&cache.base_dir.join("file.zip")).etag;
+
+        block_on(
+            cache.ensure_versioned_async(fetched("\"v2\""), "file.zip", |_, _| {
+                Ok::<_, Infallible>(UpdateReport::Required(None))
+            }),
+        )?;
+        assert_eq!(stored_etag().as_deref(), Some("\"v2\""));
+
+        let downgrade = block_on(cache.ensure_versioned_async(
+            fetched("\"v1\""),
+            "file.zip",
+            |_, _| Ok::<_, Infallible>(UpdateReport::Downgrade(None)),
+        ))?;
+        assert!(matches!(downgrade, Action::RejectedDowngrade(None)));
+
+        let skipped = block_on(cache.ensure_versioned_async(
+            fetched("\"v2b\""),
+            "file.zip",
+            |_, _| Ok::<_, Infallible>(UpdateReport::NotRequired),
+        ))?;
+        assert!(matches!(skipped, Action::Skipped));
+
+        assert_eq!(fs::read(cache.base_dir.join("file.zip"))?, b"\"v2\"");
+        assert_eq!(stored_etag().as_deref(), Some("\"v2\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn retention_and_restore() -> TestResult {
+        let (_dir, cache) = test_cache("retention")?;
+        let path = cache.base_dir.join("remote/ns_iff.zip");
+
+        for version in 1..=4 {
+            if path.exists() {
+                Cache::archive_file(&path)?;
+            }
+            write_atomic(&path, format!("v{version}").as_bytes())?;
+        }
+
+        // The latest is still active, older ones are archived newest first
+        let numbers: Vec<_> = cache
+            .archived_files("remote/ns_iff.zip")?
+            .iter()
+            .map(|archived| archived.number)
+            .collect();
+        assert_eq!(numbers, vec![3, 2, 1]);
+
+        let policy = RetentionPolicy {
+            keep_versions: Some(2),
+            keep_days: None,
+        };
+        let removed = cache.apply_retention("remote/ns_iff.zip", &policy)?;
+        assert_eq!(removed, vec![cache.base_dir.join("remote/ns_iff_1.zip")]);
+
+        let v2 = cache.base_dir.join("remote/ns_iff_2.zip");
+        let previous = cache.restore("remote/ns_iff.zip", &v2)?;
+        assert_eq!(previous, Some(cache.base_dir.join("remote/ns_iff_4.zip")));
+        assert_eq!(fs::read(&path)?, b"v2");
+        assert_eq!(fs::read(cache.base_dir.join("remote/ns_iff_4.zip"))?, b"v4");
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_are_refreshed_after_max_age() -> TestResult {
+        let (_dir, cache) = test_cache("max-age")?;
+        let path = cache.base_dir.join("remote/stations.json");
+        let source = || async { Ok::<_, Infallible>(b"new".to_vec()) };
+        let max_age = Some(Duration::from_secs(60 * 60));
+
+        write_atomic(&path, b"old")?;
+
+        let fresh = block_on(cache.ensure_async(source, "remote/stations.json", max_age))?;
+        assert!(matches!(fresh, Action::Skipped));
+
+        fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))?;
+
+        let unlimited = block_on(cache.ensure_async(source, "remote/stations.json", None))?;
+        assert!(matches!(unlimited, Action::Skipped));
+
+        let expired = block_on(cache.ensure_async(source, "remote/stations.json", max_age))?;
+        assert!(matches!(expired, Action::Refreshed));
+        assert_eq!(fs::read(&path)?, b"new");
+        assert!(!with_file_name_suffix(&path, ".partial").exists());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,90 @@
+use std::{fs::File, path::Path, time::SystemTime};
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Local};
+
+use crate::{
+    cache::Cache,
+    cli,
+    fetch::TIMETABLE_PATH,
+    iff::{Header, Iff},
+    AppConfig,
+};
+
+pub fn cache(config: &AppConfig, args: cli::CacheStruct) -> Result<(), anyhow::Error> {
+    let cache = Cache::new(&config.cache_dir)?;
+
+    match args.command {
+        cli::CacheSubCommand::List => list(&cache),
+        cli::CacheSubCommand::Rollback { version } => rollback(&cache, version),
+    }
+}
+
+fn read_header(path: &Path) -> Result<Header, anyhow::Error> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+
+    Iff::parse_delivery(file).map_err(|e| anyhow!("reading {}: {e}", path.display()))
+}
+
+fn format_header(header: &Result<Header, anyhow::Error>) -> String {
+    match header {
+        Ok(header) => format!("{header} ({})", header.description.trim()),
+        Err(e) => format!("unreadable, {e}"),
+    }
+}
+
+fn list(cache: &Cache) -> Result<(), anyhow::Error> {
+    let active = cache.base_dir.join(TIMETABLE_PATH);
+
+    if active.try_exists()? {
+        println!("active: {}", format_header(&read_header(&active)));
+    } else {
+        println!("active: none, run fetch first");
+    }
+
+    for archived in cache.archived_files(TIMETABLE_PATH)? {
+        println!(
+            "#{} archived {}: {}",
+            archived.number,
+            format_time(archived.archived_at),
+            format_header(&read_header(&archived.path))
+        );
+    }
+
+    Ok(())
+}
+
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+fn rollback(cache: &Cache, version: u64) -> Result<(), anyhow::Error> {
+    let active = cache.base_dir.join(TIMETABLE_PATH);
+    if read_header(&active).is_ok_and(|header| header.version == version) {
+        return Err(anyhow!("version {version} is already active"));
+    }
+
+    // Archived files are listed newest first, prefer the latest download of a version
+    let archived = cache
+        .archived_files(TIMETABLE_PATH)?
+        .into_iter()
+        .find(|archived| read_header(&archived.path).is_ok_and(|header| header.version == version))
+        .ok_or_else(|| anyhow!("no archived timetable with version {version}"))?;
+
+    let previous = cache
+        .restore(TIMETABLE_PATH, &archived.path)
+        .context("restoring archived timetable")?;
+
+    println!("Version {version} is now the active timetable");
+    if let Some(previous) = previous {
+        println!(
+            "Previously active timetable archived as {}",
+            previous.display()
+        );
+    }
+    println!("The next fetch replaces it again if a newer version is available");
+
+    Ok(())
+}
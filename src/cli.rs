@@ -1,4 +1,7 @@
-use clap::{command, Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 pub struct Options {
@@ -23,11 +26,45 @@ pub enum SubCommand {
         #[arg(long)]
         autofetch: bool,
     },
-    // Print timetable oddities
-    Verify,
+    // Report timetable data quality, failing when issues exceed the configured thresholds
+    Verify(VerifyStruct),
     // Print timetable data
     Print(PrintStruct),
+    // Export timetable data to other formats
+    Export(ExportStruct),
+    // Inspect and restore archived timetables
+    Cache(CacheStruct),
+    // Compare two IFF timetable archives
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        // Print the differences as JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
     Bench,
+    // Serve recorded NS and NDOV responses, for fetching and route finding without internet
+    Standin {
+        // Directory with the recorded responses
+        #[arg(long, default_value = "./fixtures/standin")]
+        fixtures: PathBuf,
+        #[arg(long, default_value = "localhost:9003")]
+        bind_addr: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyStruct {
+    #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+    pub format: ReportFormat,
+    // Output file, writes to stdout when omitted
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Markdown,
+    Json,
 }
 
 #[derive(Debug, Args)]
@@ -39,6 +76,46 @@ pub struct PrintStruct {
 #[derive(Debug, Subcommand)]
 pub enum PrintSubCommand {
     Departures { station: String },
+    Arrivals { station: String },
+}
+
+#[derive(Debug, Args)]
+pub struct ExportStruct {
+    #[command(subcommand)]
+    pub command: ExportSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExportSubCommand {
+    // Export ride paths as a GeoJSON FeatureCollection
+    Geojson {
+        // Ride to export, exports all rides valid on the date when omitted
+        #[arg(long)]
+        ride: Option<String>,
+        // Date the rides are valid on, defaults to today
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        // Output file, writes to stdout when omitted
+        output: Option<PathBuf>,
+    },
+    // Export the timetable as a static GTFS zip archive
+    Gtfs {
+        output: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct CacheStruct {
+    #[command(subcommand)]
+    pub command: CacheSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheSubCommand {
+    // List the active and archived timetables
+    List,
+    // Make an archived timetable version the active one
+    Rollback { version: u64 },
 }
 
 pub fn get_cli_args() -> Options {
@@ -2,6 +2,7 @@ use serde::Serializer;
 
 use crate::iff::{LocationCache, LocationCodeHandle};
 
+#[allow(dead_code)]
 pub trait SerializeExtras {
     type Ok;
     type Error;
@@ -1,7 +1,7 @@
 use std::{cmp, error::Error, fmt::Display, str::FromStr};
 
 use chrono::{NaiveTime, Timelike};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 const MILLISECOND: u32 = 1;
 const SECOND: u32 = MILLISECOND * 1000;
@@ -15,7 +15,7 @@ Does not keep any date information.
 Offset might overflow into next day.
 Precision in milliseconds.
 */
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct DayOffset {
     offset: u32,
@@ -36,6 +36,21 @@ impl<'a> Display for DayOffsetTimetableDisplay<'a> {
     }
 }
 
+/// Displays as HH:MM:SS without wrapping times past midnight, as GTFS expects for rides that run into the next day
+pub struct DayOffsetGtfsDisplay<'a> {
+    inner: &'a DayOffset,
+}
+
+impl<'a> Display for DayOffsetGtfsDisplay<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hours = self.inner.offset / HOUR;
+        let minutes = (self.inner.offset % HOUR) / MINUTE;
+        let seconds = (self.inner.offset % MINUTE) / SECOND;
+
+        write!(f, "{:02}:{:02}:{:02}", hours, minutes, seconds)
+    }
+}
+
 impl Ord for DayOffset {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.offset.cmp(&other.offset)
@@ -55,6 +70,27 @@ impl DayOffset {
         }
     }
 
+    pub fn from_hour_minute_second(hours: u32, minutes: u32, seconds: u32) -> Self {
+        Self {
+            offset: hours * HOUR + minutes * MINUTE + seconds * SECOND,
+        }
+    }
+
+    /// Parses GTFS times formatted as H:MM:SS, hours exceed 23 for rides running past midnight
+    pub fn from_gtfs_str(value: &str) -> Result<Self, ParseError> {
+        let mut parts = value.trim().split(':').map(|part| {
+            part.parse::<u32>()
+                .map_err(|_| ParseError::SubsliceParseFailed)
+        });
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(hours), Some(minutes), Some(seconds), None) => {
+                Ok(Self::from_hour_minute_second(hours?, minutes?, seconds?))
+            }
+            _ => Err(ParseError::GtfsFormatInvalid),
+        }
+    }
+
     pub fn from_naivetime(time: &NaiveTime) -> Self {
         Self::from_hour_minute(time.hour(), time.minute())
     }
@@ -65,15 +101,26 @@ impl DayOffset {
         }
     }
 
+    pub fn offset_by_seconds(&self, seconds: i32) -> Self {
+        Self {
+            offset: self.offset.saturating_add_signed(seconds * (SECOND as i32)),
+        }
+    }
+
     pub fn display_for_timetable(&self) -> DayOffsetTimetableDisplay<'_> {
         DayOffsetTimetableDisplay { inner: self }
     }
+
+    pub fn display_for_gtfs(&self) -> DayOffsetGtfsDisplay<'_> {
+        DayOffsetGtfsDisplay { inner: self }
+    }
 }
 
 #[derive(Debug)]
 pub enum ParseError {
     StringSizeInvalid,
     SubsliceParseFailed,
+    GtfsFormatInvalid,
 }
 
 impl Error for ParseError {}
@@ -83,6 +130,7 @@ impl Display for ParseError {
         match self {
             Self::StringSizeInvalid => f.write_str("String size invalid, should be 4"),
             Self::SubsliceParseFailed => f.write_str("Subslice failed"),
+            Self::GtfsFormatInvalid => f.write_str("Expected time formatted as H:MM:SS"),
         }
     }
 }
@@ -106,3 +154,28 @@ impl FromStr for DayOffset {
         Ok(Self::from_hour_minute(hours, minutes))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gtfs_display_keeps_times_past_midnight() {
+        let time: DayOffset = "2512".parse().unwrap();
+
+        assert_eq!(time.display_for_gtfs().to_string(), "25:12:00");
+        assert_eq!(time.display_for_timetable().to_string(), "01:12");
+    }
+
+    #[test]
+    fn gtfs_parse_roundtrip() {
+        let time = DayOffset::from_gtfs_str("25:12:30").unwrap();
+
+        assert_eq!(time.display_for_gtfs().to_string(), "25:12:30");
+        assert!(DayOffset::from_gtfs_str("7:05").is_err());
+        assert_eq!(
+            DayOffset::from_gtfs_str("7:05:00").unwrap(),
+            DayOffset::from_hour_minute(7, 5)
+        );
+    }
+}
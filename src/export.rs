@@ -0,0 +1,77 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use chrono::NaiveDate;
+
+use crate::{
+    api::datarepo::{self, DataRepo},
+    cli,
+    geojson::FeatureCollection,
+    gtfs, time, AppConfig,
+};
+
+pub fn export(config: &AppConfig, args: cli::ExportStruct) -> Result<(), anyhow::Error> {
+    let data = datarepo::DataRepo::new(&config.cache_dir, &config.timetable)?;
+
+    match args.command {
+        cli::ExportSubCommand::Geojson { ride, date, output } => export_geojson(
+            &data,
+            ride.as_deref(),
+            date.unwrap_or_else(|| time::timetable_now().date_naive()),
+            output.as_deref(),
+        ),
+        cli::ExportSubCommand::Gtfs { output } => export_gtfs(data, &output),
+    }
+}
+
+fn export_geojson(
+    data: &DataRepo,
+    ride_id: Option<&str>,
+    date: NaiveDate,
+    output: Option<&Path>,
+) -> Result<(), anyhow::Error> {
+    if !data.covers_date(date) {
+        return Err(anyhow!(
+            "{date} is outside of the timetable validity period"
+        ));
+    }
+
+    let rides = match ride_id {
+        Some(id) => data.rides_with_id_on_date(id, &date),
+        None => data.rides_active_on_date(&date),
+    };
+
+    if let Some(id) = ride_id {
+        if rides.is_empty() {
+            return Err(anyhow!("no ride {id} on {date}"));
+        }
+    }
+
+    let content = serde_json::to_vec(&FeatureCollection::from_rides(data, rides, date))?;
+
+    match output {
+        Some(path) => fs::write(path, content)
+            .with_context(|| format!("writing GeoJSON to {}", path.display())),
+        None => std::io::stdout()
+            .write_all(&content)
+            .context("writing GeoJSON to stdout"),
+    }
+}
+
+fn export_gtfs(mut data: DataRepo, output: &Path) -> Result<(), anyhow::Error> {
+    // GTFS requires coordinates for every stop, so drop rides calling at stations we don't know
+    data.filter_unknown_legs();
+
+    let file = File::create(output)
+        .with_context(|| format!("creating GTFS archive at {}", output.display()))?;
+
+    gtfs::write_gtfs(&data, BufWriter::new(file)).context("writing GTFS archive")?;
+
+    println!("Wrote GTFS archive to {}", output.display());
+
+    Ok(())
+}
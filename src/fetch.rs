@@ -1,14 +1,16 @@
 use anyhow::{anyhow, Context};
-use std::path::Path;
+use std::{cmp::Ordering, io::Cursor, path::Path, time::Duration};
 use tokio::fs;
 
 use crate::{
+    api::datarepo::{snapshot, DataRepo, TimetableSource},
     cache::UpdateReport,
-    iff,
+    iff::Iff,
     ndovloket_api::{self},
+    UpstreamConfig,
 };
 
-use crate::cache::{Action, Cache, Error};
+use crate::cache::{Action, Cache, Error, RetentionPolicy};
 
 pub static TIMETABLE_PATH: &str = "remote/ns_iff.zip";
 pub const STATION_FILEPATH: &str = "remote/stations.json";
@@ -22,21 +24,29 @@ fn print_cacheresult(res: Result<Action, Error>) {
 }
 
 fn is_update_required(old: &[u8], new: &[u8]) -> Result<UpdateReport, Box<dyn std::error::Error>> {
-    let old_version = iff::Iff::parse_version_only(old).map_err(|_| anyhow!(" version old"))?;
-    let new_version = iff::Iff::parse_version_only(new).map_err(|_| anyhow!(" version new"))?;
-
-    if new > old {
-        Ok(UpdateReport::Required(Some((
-            old_version.to_string(),
-            new_version.to_string(),
-        ))))
-    } else {
-        Ok(UpdateReport::NotRequired)
-    }
+    let old_header = Iff::parse_delivery(Cursor::new(old))
+        .map_err(|e| anyhow!("Reading cached delivery: {e}"))?;
+    let new_header = Iff::parse_delivery(Cursor::new(new))
+        .map_err(|e| anyhow!("Reading downloaded delivery: {e}"))?;
+
+    let versions = || Some((old_header.to_string(), new_header.to_string()));
+
+    Ok(match new_header.cmp_recency(&old_header) {
+        Ordering::Greater => UpdateReport::Required(versions()),
+        Ordering::Less => UpdateReport::Downgrade(versions()),
+        Ordering::Equal if old == new => UpdateReport::NotRequired,
+        Ordering::Equal => UpdateReport::ContentChanged(Some(new_header.to_string())),
+    })
 }
 
 #[tokio::main]
-pub async fn fetch(storage_dir: &Path, ns_key: Option<&str>) -> Result<(), anyhow::Error> {
+pub async fn fetch(
+    storage_dir: &Path,
+    ns_key: Option<&str>,
+    upstream: &UpstreamConfig,
+    retention: &RetentionPolicy,
+    timetable: &TimetableSource,
+) -> Result<(), anyhow::Error> {
     println!("Fetching into {}", storage_dir.display());
     if storage_dir.try_exists().is_err() || storage_dir.try_exists().is_ok_and(|f| !f) {
         println!(
@@ -64,7 +74,9 @@ pub async fn fetch(storage_dir: &Path, ns_key: Option<&str>) -> Result<(), anyho
 
     let timetable_result = cache
         .ensure_versioned_async(
-            ndovloket_api::NDovLoket::fetch_timetable,
+            |validators| {
+                ndovloket_api::NDovLoket::fetch_timetable(&upstream.timetable_url, validators)
+            },
             TIMETABLE_PATH,
             is_update_required,
         )
@@ -72,17 +84,25 @@ pub async fn fetch(storage_dir: &Path, ns_key: Option<&str>) -> Result<(), anyho
 
     print_cacheresult(timetable_result);
 
+    for removed in cache
+        .apply_retention(TIMETABLE_PATH, retention)
+        .context("Removing archived timetables")?
+    {
+        println!("Removed archived timetable {}", removed.display());
+    }
+
     if let Some(key) = ns_key {
-        let ns = ns_api::NsApi::new(key.to_owned());
+        let ns = ns_api::NsApi::new(key.to_owned()).host(&upstream.ns_api_host);
+        let max_age = Some(Duration::from_secs(upstream.ns_data_max_age));
 
         let a = cache
-            .ensure_async(|| ns.fetch_routes(), ROUTE_FILEPATH)
+            .ensure_async(|| ns.fetch_routes(), ROUTE_FILEPATH, max_age)
             .await;
 
         print_cacheresult(a);
 
         let b = cache
-            .ensure_async(|| ns.fetch_stations(), STATION_FILEPATH)
+            .ensure_async(|| ns.fetch_stations(), STATION_FILEPATH, max_age)
             .await;
 
         print_cacheresult(b);
@@ -90,5 +110,130 @@ pub async fn fetch(storage_dir: &Path, ns_key: Option<&str>) -> Result<(), anyho
         println!("Skipping updating NS data, no key");
     }
 
-    Ok(())
+    ensure_snapshot(storage_dir, timetable)
+}
+
+/// Rebuilds the DataRepo snapshot unless it already matches the cached files
+fn ensure_snapshot(storage_dir: &Path, source: &TimetableSource) -> Result<(), anyhow::Error> {
+    if !matches!(source, TimetableSource::Iff { .. }) {
+        return Ok(());
+    }
+
+    if ![TIMETABLE_PATH, ROUTE_FILEPATH, STATION_FILEPATH]
+        .iter()
+        .all(|path| storage_dir.join(path).is_file())
+    {
+        println!("Skipping snapshot, not all data is cached");
+        return Ok(());
+    }
+
+    match snapshot::is_current(storage_dir, source) {
+        Ok(()) => {
+            println!("Snapshot is up to date");
+            return Ok(());
+        }
+        Err(e) => println!("{e}, building snapshot"),
+    }
+
+    DataRepo::new(storage_dir, source)
+        .context("Loading timetable for snapshot")?
+        .write_snapshot(storage_dir, source)
+        .context("Writing snapshot")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    /// A zip with only the files the update decision looks at
+    fn delivery_zip(header: &str, timetable: &str) -> Result<Vec<u8>, zip::result::ZipError> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+        zip.start_file("delivery.dat", FileOptions::default())?;
+        zip.write_all(header.as_bytes())?;
+        zip.start_file("timetbls.dat", FileOptions::default())?;
+        zip.write_all(header.as_bytes())?;
+        zip.write_all(timetable.as_bytes())?;
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    const V52: &str = "@100,03072023,04082024,0052,Dienstregeling\r\n";
+    const V53: &str = "@100,04072023,05082024,0053,Dienstregeling\r\n";
+
+    #[test]
+    fn newer_version_is_required() -> TestResult {
+        let report = is_update_required(&delivery_zip(V52, "")?, &delivery_zip(V53, "")?)?;
+
+        assert_eq!(
+            report,
+            UpdateReport::Required(Some((
+                "version 52 valid 2023-07-03 to 2024-08-04".to_owned(),
+                "version 53 valid 2023-07-04 to 2024-08-05".to_owned()
+            )))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn older_version_is_a_downgrade() -> TestResult {
+        let report = is_update_required(&delivery_zip(V53, "")?, &delivery_zip(V52, "")?)?;
+
+        assert!(matches!(report, UpdateReport::Downgrade(Some(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn identical_delivery_is_not_required() -> TestResult {
+        let delivery = delivery_zip(V52, "#00000001\r\n")?;
+
+        assert_eq!(
+            is_update_required(&delivery, &delivery)?,
+            UpdateReport::NotRequired
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn same_version_with_other_content_is_reported() -> TestResult {
+        let report = is_update_required(
+            &delivery_zip(V52, "#00000001\r\n")?,
+            &delivery_zip(V52, "#00000002\r\n")?,
+        )?;
+
+        assert_eq!(
+            report,
+            UpdateReport::ContentChanged(Some(
+                "version 52 valid 2023-07-03 to 2024-08-04".to_owned()
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn version_reset_for_a_new_period_is_required() -> TestResult {
+        let next_period = "@100,09122024,13122025,0001,Dienstregeling 2025\r\n";
+        let report = is_update_required(&delivery_zip(V53, "")?, &delivery_zip(next_period, "")?)?;
+
+        assert!(matches!(report, UpdateReport::Required(_)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_archive_is_an_error() -> TestResult {
+        assert!(is_update_required(&delivery_zip(V52, "")?, b"not a zip").is_err());
+
+        Ok(())
+    }
 }
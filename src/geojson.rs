@@ -0,0 +1,100 @@
+//! Conversion of rides into GeoJSON, for loading train routes into GIS tools like QGIS
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::{api::datarepo::DataRepo, iff::Ride};
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+pub struct FeatureCollection<'a> {
+    features: Vec<Feature<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+struct Feature<'a> {
+    /// Null when the path of the ride couldn't be resolved from the known links
+    geometry: Option<Geometry>,
+    properties: RideProperties<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    LineString { coordinates: Vec<[f64; 2]> },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RideProperties<'a> {
+    id: &'a str,
+    date: NaiveDate,
+    operator: u32,
+    operator_name: Option<&'a str>,
+    transit_mode: &'a str,
+    start_time: String,
+    end_time: String,
+    stops: Vec<StopProperties<'a>>,
+}
+
+#[derive(Serialize)]
+struct StopProperties<'a> {
+    code: &'a str,
+    arrival: Option<String>,
+    departure: Option<String>,
+}
+
+impl<'a> FeatureCollection<'a> {
+    /// Builds a collection with a LineString feature for each ride, `date` is the day the rides are valid on
+    pub fn from_rides(
+        repo: &'a DataRepo,
+        rides: impl IntoIterator<Item = &'a Ride>,
+        date: NaiveDate,
+    ) -> Self {
+        Self {
+            features: rides
+                .into_iter()
+                .map(|ride| Feature::from_ride(repo, ride, date))
+                .collect(),
+        }
+    }
+}
+
+impl<'a> Feature<'a> {
+    fn from_ride(repo: &'a DataRepo, ride: &'a Ride, date: NaiveDate) -> Self {
+        let geometry = repo.ride_path(ride).map(|path| Geometry::LineString {
+            coordinates: path.iter().map(|coords| coords.as_array()).collect(),
+        });
+
+        let stops = ride
+            .timetable
+            .iter()
+            .filter(|entry| !entry.stop_kind.is_waypoint())
+            .map(|entry| StopProperties {
+                code: repo.location_cache().get_str(&entry.code).unwrap(),
+                arrival: entry
+                    .stop_kind
+                    .arrival_time()
+                    .map(|time| time.display_for_timetable().to_string()),
+                departure: entry
+                    .stop_kind
+                    .departure_time()
+                    .map(|time| time.display_for_timetable().to_string()),
+            })
+            .collect();
+
+        Self {
+            geometry,
+            properties: RideProperties {
+                id: &ride.id,
+                date,
+                operator: ride.operator,
+                operator_name: repo.company_by_id(ride.operator).map(|c| c.name()),
+                transit_mode: &ride.transit_mode,
+                start_time: ride.start_time().display_for_timetable().to_string(),
+                end_time: ride.end_time().display_for_timetable().to_string(),
+                stops,
+            },
+        }
+    }
+}
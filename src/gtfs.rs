@@ -0,0 +1,104 @@
+//! Conversion between the IFF based data in `DataRepo` and static GTFS feeds
+//! See <https://gtfs.org/schedule/reference/> for the format
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+mod export;
+mod import;
+pub use export::write_gtfs;
+pub use import::read_gtfs;
+
+const AGENCY_FILE_NAME: &str = "agency.txt";
+const STOPS_FILE_NAME: &str = "stops.txt";
+const ROUTES_FILE_NAME: &str = "routes.txt";
+const TRIPS_FILE_NAME: &str = "trips.txt";
+const STOP_TIMES_FILE_NAME: &str = "stop_times.txt";
+const CALENDAR_FILE_NAME: &str = "calendar.txt";
+const CALENDAR_DATES_FILE_NAME: &str = "calendar_dates.txt";
+const FEED_INFO_FILE_NAME: &str = "feed_info.txt";
+const SHAPES_FILE_NAME: &str = "shapes.txt";
+
+/// Timezone the IFF timetable times are expressed in
+const TIMETABLE_TIMEZONE: &str = "Europe/Amsterdam";
+/// Date format used throughout GTFS
+const DATE_FORMAT: &str = "%Y%m%d";
+
+#[derive(Error, Debug)]
+pub enum GtfsError {
+    #[error("Error handling file io: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Error handling zip archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Error handling csv table: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Invalid data in {file}: {message}")]
+    Invalid { file: &'static str, message: String },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Agency {
+    agency_id: String,
+    agency_name: String,
+    agency_url: String,
+    agency_timezone: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Stop {
+    stop_id: String,
+    stop_name: String,
+    stop_lat: f64,
+    stop_lon: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Route {
+    route_id: String,
+    agency_id: String,
+    route_short_name: String,
+    route_long_name: String,
+    route_type: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Trip {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    trip_short_name: String,
+    shape_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StopTime {
+    trip_id: String,
+    arrival_time: String,
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CalendarDate {
+    service_id: String,
+    date: String,
+    exception_type: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ShapePoint {
+    shape_id: String,
+    shape_pt_lat: f64,
+    shape_pt_lon: f64,
+    shape_pt_sequence: u32,
+}
+
+/// GTFS `route_type` for an IFF transit mode, anything not explicitly known is assumed to be rail
+fn route_type(transit_mode: &str) -> u16 {
+    match transit_mode {
+        "TRM" => 0,
+        "MTR" => 1,
+        "BUS" | "NSB" | "SNB" => 3,
+        _ => 2,
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Seek, Write},
+};
+
+use serde::Serialize;
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{
+    api::datarepo::DataRepo,
+    iff::{LocationCodeHandle, Ride},
+};
+
+use super::{
+    route_type, Agency, CalendarDate, GtfsError, Route, ShapePoint, Stop, StopTime, Trip,
+    AGENCY_FILE_NAME, CALENDAR_DATES_FILE_NAME, DATE_FORMAT, ROUTES_FILE_NAME, SHAPES_FILE_NAME,
+    STOPS_FILE_NAME, STOP_TIMES_FILE_NAME, TIMETABLE_TIMEZONE, TRIPS_FILE_NAME,
+};
+
+/// IFF doesn't carry agency urls, GTFS requires one so point to the source of the data
+const AGENCY_URL: &str = "https://data.ndovloket.nl";
+
+/// Writes the rides in `repo` as a GTFS zip archive
+/// Expects rides with unknown stations to have been filtered out, as GTFS requires coordinates for every stop
+pub fn write_gtfs(repo: &DataRepo, output: impl Write + Seek) -> Result<(), GtfsError> {
+    let mut zip = ZipWriter::new(output);
+
+    write_table(&mut zip, AGENCY_FILE_NAME, agencies(repo))?;
+    write_table(&mut zip, STOPS_FILE_NAME, stops(repo))?;
+    write_table(&mut zip, ROUTES_FILE_NAME, routes(repo))?;
+
+    let (trips, shapes) = trips_and_shapes(repo);
+    write_table(&mut zip, TRIPS_FILE_NAME, trips)?;
+    write_table(&mut zip, SHAPES_FILE_NAME, shapes)?;
+    write_table(&mut zip, STOP_TIMES_FILE_NAME, stop_times(repo))?;
+    write_table(&mut zip, CALENDAR_DATES_FILE_NAME, calendar_dates(repo))?;
+
+    zip.finish()?;
+
+    Ok(())
+}
+
+fn write_table<W: Write + Seek, T: Serialize>(
+    zip: &mut ZipWriter<W>,
+    file_name: &str,
+    records: impl IntoIterator<Item = T>,
+) -> Result<(), GtfsError> {
+    zip.start_file(file_name, FileOptions::default())?;
+
+    let mut writer = csv::Writer::from_writer(zip);
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn route_id(ride: &Ride) -> String {
+    format!("{}_{}", ride.operator, ride.transit_mode)
+}
+
+/// Ride ids are reused across rides with different validities, so the index is added to make them unique
+fn trip_id(index: usize, ride: &Ride) -> String {
+    format!("{}_{}", ride.id, index)
+}
+
+fn agencies(repo: &DataRepo) -> Vec<Agency> {
+    repo.companies()
+        .iter()
+        .map(|company| Agency {
+            agency_id: company.id().to_string(),
+            agency_name: company.name().to_owned(),
+            agency_url: AGENCY_URL.to_owned(),
+            agency_timezone: TIMETABLE_TIMEZONE.to_owned(),
+        })
+        .collect()
+}
+
+fn stops(repo: &DataRepo) -> Vec<Stop> {
+    repo.stations()
+        .iter()
+        .map(|station| Stop {
+            stop_id: station.code.clone(),
+            stop_name: station.name.clone(),
+            stop_lat: station.position.latitude(),
+            stop_lon: station.position.longitude(),
+        })
+        .collect()
+}
+
+fn routes(repo: &DataRepo) -> Vec<Route> {
+    let route_keys: BTreeSet<(u32, &str)> = repo
+        .rides()
+        .iter()
+        .map(|ride| (ride.operator, ride.transit_mode.as_str()))
+        .collect();
+
+    route_keys
+        .into_iter()
+        .map(|(operator, transit_mode)| Route {
+            route_id: format!("{operator}_{transit_mode}"),
+            agency_id: operator.to_string(),
+            route_short_name: transit_mode.to_owned(),
+            route_long_name: repo
+                .company_by_id(operator)
+                .map(|company| format!("{} {transit_mode}", company.name()))
+                .unwrap_or_default(),
+            route_type: route_type(transit_mode),
+        })
+        .collect()
+}
+
+/// Builds trips along with their shapes, rides that share the same series of timetable points share a shape
+fn trips_and_shapes(repo: &DataRepo) -> (Vec<Trip>, Vec<ShapePoint>) {
+    let mut shape_ids: HashMap<Vec<LocationCodeHandle>, Option<String>> = HashMap::new();
+    let mut shapes = vec![];
+
+    let trips =
+        repo.rides()
+            .iter()
+            .enumerate()
+            .map(|(index, ride)| {
+                let key: Vec<_> = ride.timetable.iter().map(|entry| entry.code).collect();
+
+                let shape_id = shape_ids
+                    .entry(key)
+                    .or_insert_with(|| {
+                        let path = repo.ride_path(ride)?;
+                        let shape_id = format!("shape_{index}");
+
+                        shapes.extend(path.iter().enumerate().map(|(sequence, coords)| {
+                            ShapePoint {
+                                shape_id: shape_id.clone(),
+                                shape_pt_lat: coords.latitude(),
+                                shape_pt_lon: coords.longitude(),
+                                shape_pt_sequence: sequence as u32,
+                            }
+                        }));
+
+                        Some(shape_id)
+                    })
+                    .clone();
+
+                Trip {
+                    route_id: route_id(ride),
+                    service_id: ride.day_validity.to_string(),
+                    trip_id: trip_id(index, ride),
+                    trip_short_name: ride.id.clone(),
+                    shape_id,
+                }
+            })
+            .collect();
+
+    (trips, shapes)
+}
+
+fn stop_times(repo: &DataRepo) -> Vec<StopTime> {
+    repo.rides()
+        .iter()
+        .enumerate()
+        .flat_map(|(index, ride)| {
+            ride.timetable
+                .iter()
+                .filter(|entry| !entry.stop_kind.is_waypoint())
+                .enumerate()
+                .map(move |(sequence, entry)| {
+                    // Departures lack an arrival time and arrivals lack a departure time, GTFS wants both
+                    let arrival = entry
+                        .stop_kind
+                        .arrival_time()
+                        .or(entry.stop_kind.departure_time())
+                        .expect("non-waypoint stop to have a time");
+                    let departure = entry.stop_kind.departure_time().unwrap_or(arrival);
+
+                    StopTime {
+                        trip_id: trip_id(index, ride),
+                        arrival_time: arrival.display_for_gtfs().to_string(),
+                        departure_time: departure.display_for_gtfs().to_string(),
+                        stop_id: repo
+                            .location_cache()
+                            .get_str(&entry.code)
+                            .unwrap()
+                            .to_owned(),
+                        stop_sequence: sequence as u32 + 1,
+                    }
+                })
+        })
+        .collect()
+}
+
+fn calendar_dates(repo: &DataRepo) -> Vec<CalendarDate> {
+    let footnotes: BTreeSet<u64> = repo.rides().iter().map(|ride| ride.day_validity).collect();
+
+    footnotes
+        .into_iter()
+        .flat_map(|footnote| {
+            repo.validity()
+                .valid_dates(footnote)
+                .into_iter()
+                .flatten()
+                .map(move |date| CalendarDate {
+                    service_id: footnote.to_string(),
+                    date: date.format(DATE_FORMAT).to_string(),
+                    exception_type: 1,
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,571 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Read, Seek},
+};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::{de::DeserializeOwned, Deserialize};
+use zip::{result::ZipError, ZipArchive};
+
+use crate::{
+    api::datarepo::{
+        links::Coords2D,
+        stations::{Station, StationType},
+    },
+    dayoffset::DayOffset,
+    iff::{
+        Company, Header, LocationCache, Platform, PlatformInfo, Ride, RideValidity, StopKind,
+        TimetableData, TimetableEntry,
+    },
+};
+
+use super::{
+    GtfsError, AGENCY_FILE_NAME, CALENDAR_DATES_FILE_NAME, CALENDAR_FILE_NAME, DATE_FORMAT,
+    FEED_INFO_FILE_NAME, ROUTES_FILE_NAME, STOPS_FILE_NAME, STOP_TIMES_FILE_NAME, TRIPS_FILE_NAME,
+};
+
+#[derive(Deserialize)]
+struct AgencyRecord {
+    agency_id: Option<String>,
+    agency_name: String,
+}
+
+#[derive(Deserialize)]
+struct StopRecord {
+    stop_id: String,
+    stop_name: Option<String>,
+    stop_lat: Option<f64>,
+    stop_lon: Option<f64>,
+    location_type: Option<u8>,
+    parent_station: Option<String>,
+    platform_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RouteRecord {
+    route_id: String,
+    agency_id: Option<String>,
+    route_short_name: Option<String>,
+    route_type: u16,
+}
+
+#[derive(Deserialize)]
+struct TripRecord {
+    route_id: String,
+    service_id: String,
+    trip_id: String,
+    trip_short_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    arrival_time: Option<String>,
+    departure_time: Option<String>,
+    stop_id: String,
+    stop_sequence: u32,
+    pickup_type: Option<u8>,
+    drop_off_type: Option<u8>,
+}
+
+#[derive(Deserialize)]
+struct CalendarRecord {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: String,
+    end_date: String,
+}
+
+impl CalendarRecord {
+    fn runs_on(&self, weekday: Weekday) -> bool {
+        let flag = match weekday {
+            Weekday::Mon => self.monday,
+            Weekday::Tue => self.tuesday,
+            Weekday::Wed => self.wednesday,
+            Weekday::Thu => self.thursday,
+            Weekday::Fri => self.friday,
+            Weekday::Sat => self.saturday,
+            Weekday::Sun => self.sunday,
+        };
+
+        flag == 1
+    }
+}
+
+#[derive(Deserialize)]
+struct CalendarDateRecord {
+    service_id: String,
+    date: String,
+    exception_type: u8,
+}
+
+#[derive(Deserialize)]
+struct FeedInfoRecord {
+    feed_publisher_name: String,
+    feed_version: Option<String>,
+}
+
+/// `pickup_type`/`drop_off_type` value for stops where passengers can't board or alight
+const NO_PICKUP_DROP_OFF: u8 = 1;
+
+/// Reads a static GTFS archive into the same structures an IFF archive produces
+/// Stops that are part of a station are merged into that station, their `platform_code` becomes the platform
+pub fn read_gtfs(archive: impl Read + Seek) -> Result<(TimetableData, Vec<Station>), GtfsError> {
+    let mut archive = ZipArchive::new(archive)?;
+
+    let agencies: Vec<AgencyRecord> = read_table(&mut archive, AGENCY_FILE_NAME)?;
+    let stops: Vec<StopRecord> = read_table(&mut archive, STOPS_FILE_NAME)?;
+    let routes: Vec<RouteRecord> = read_table(&mut archive, ROUTES_FILE_NAME)?;
+    let trips: Vec<TripRecord> = read_table(&mut archive, TRIPS_FILE_NAME)?;
+    let stop_times: Vec<StopTimeRecord> = read_table(&mut archive, STOP_TIMES_FILE_NAME)?;
+    let calendar: Vec<CalendarRecord> = read_optional_table(&mut archive, CALENDAR_FILE_NAME)?;
+    let calendar_dates: Vec<CalendarDateRecord> =
+        read_optional_table(&mut archive, CALENDAR_DATES_FILE_NAME)?;
+    let feed_info: Vec<FeedInfoRecord> = read_optional_table(&mut archive, FEED_INFO_FILE_NAME)?;
+
+    let companies: Vec<Company> = agencies
+        .iter()
+        .enumerate()
+        .map(|(index, agency)| {
+            Company::new(
+                index as u32 + 1,
+                agency.agency_id.as_deref().unwrap_or_default(),
+                &agency.agency_name,
+                // GTFS has no notion of a company day boundary, so days are assumed to start at midnight
+                DayOffset::from_hour_minute(0, 0),
+            )
+        })
+        .collect();
+    let company_ids: HashMap<&str, u32> = agencies
+        .iter()
+        .zip(&companies)
+        .map(|(agency, company)| {
+            (
+                agency.agency_id.as_deref().unwrap_or_default(),
+                company.id(),
+            )
+        })
+        .collect();
+
+    let stops_by_id: HashMap<&str, &StopRecord> = stops
+        .iter()
+        .map(|stop| (stop.stop_id.as_str(), stop))
+        .collect();
+    let stations = stations(&stops);
+
+    let routes_by_id: HashMap<&str, &RouteRecord> = routes
+        .iter()
+        .map(|route| (route.route_id.as_str(), route))
+        .collect();
+
+    let (header, footnotes, validity) = validity(&calendar, &calendar_dates, &feed_info)?;
+
+    let mut stop_times_by_trip: HashMap<&str, Vec<&StopTimeRecord>> = HashMap::new();
+    for stop_time in &stop_times {
+        stop_times_by_trip
+            .entry(stop_time.trip_id.as_str())
+            .or_default()
+            .push(stop_time);
+    }
+
+    let mut locations = LocationCache::with_capacity(stations.len());
+    let mut rides = Vec::with_capacity(trips.len());
+
+    for trip in &trips {
+        let (Some(route), Some(footnote), Some(stop_times)) = (
+            routes_by_id.get(trip.route_id.as_str()),
+            footnotes.get(trip.service_id.as_str()),
+            stop_times_by_trip.get_mut(trip.trip_id.as_str()),
+        ) else {
+            continue;
+        };
+        stop_times.sort_by_key(|stop_time| stop_time.stop_sequence);
+
+        let Some(timetable) = timetable(stop_times, &stops_by_id, *footnote, &mut locations)?
+        else {
+            continue;
+        };
+
+        rides.push(Ride {
+            id: trip
+                .trip_short_name
+                .clone()
+                .unwrap_or_else(|| trip.trip_id.clone()),
+            transit_mode: route
+                .route_short_name
+                .clone()
+                .unwrap_or_else(|| route.route_type.to_string()),
+            timetable,
+            day_validity: *footnote,
+            previous: None,
+            next: None,
+            operator: company_ids
+                .get(route.agency_id.as_deref().unwrap_or_default())
+                .copied()
+                .unwrap_or_default(),
+        });
+    }
+
+    Ok((
+        TimetableData {
+            header,
+            rides,
+            validity,
+            companies,
+            locations,
+            stations: vec![],
+        },
+        stations,
+    ))
+}
+
+fn read_table<R: Read + Seek, T: DeserializeOwned>(
+    archive: &mut ZipArchive<R>,
+    file_name: &str,
+) -> Result<Vec<T>, GtfsError> {
+    let file = archive.by_name(file_name)?;
+
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(file)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(GtfsError::Csv)
+}
+
+/// Like `read_table` but returns an empty table when the file isn't in the archive
+fn read_optional_table<R: Read + Seek, T: DeserializeOwned>(
+    archive: &mut ZipArchive<R>,
+    file_name: &str,
+) -> Result<Vec<T>, GtfsError> {
+    match read_table(archive, file_name) {
+        Err(GtfsError::Zip(ZipError::FileNotFound)) => Ok(vec![]),
+        result => result,
+    }
+}
+
+/// The stop that identifies the location of `stop`, its parent station if it has one
+fn location_code(stop: &StopRecord) -> &str {
+    stop.parent_station
+        .as_deref()
+        .filter(|parent| !parent.is_empty())
+        .unwrap_or(&stop.stop_id)
+}
+
+/// Stations are all stops and stations that aren't part of another station and have a position
+fn stations(stops: &[StopRecord]) -> Vec<Station> {
+    stops
+        .iter()
+        .filter(|stop| matches!(stop.location_type, None | Some(0) | Some(1)))
+        .filter(|stop| location_code(stop) == stop.stop_id)
+        .filter_map(|stop| {
+            Some(Station {
+                code: stop.stop_id.clone(),
+                name: stop.stop_name.clone().unwrap_or_default(),
+                position: Coords2D::new(stop.stop_lon?, stop.stop_lat?),
+                station_type: StationType::Local,
+                resolved: false,
+            })
+        })
+        .collect()
+}
+
+fn parse_date(file: &'static str, date: &str) -> Result<NaiveDate, GtfsError> {
+    NaiveDate::parse_from_str(date, DATE_FORMAT).map_err(|e| GtfsError::Invalid {
+        file,
+        message: format!("date {date}: {e}"),
+    })
+}
+
+/// Builds day validity bitmaps for every service, numbering the services as footnotes
+fn validity<'a>(
+    calendar: &'a [CalendarRecord],
+    calendar_dates: &'a [CalendarDateRecord],
+    feed_info: &[FeedInfoRecord],
+) -> Result<(Header, HashMap<&'a str, u64>, RideValidity), GtfsError> {
+    let mut dates = vec![];
+    for service in calendar {
+        dates.push(parse_date(CALENDAR_FILE_NAME, &service.start_date)?);
+        dates.push(parse_date(CALENDAR_FILE_NAME, &service.end_date)?);
+    }
+    for exception in calendar_dates {
+        dates.push(parse_date(CALENDAR_DATES_FILE_NAME, &exception.date)?);
+    }
+
+    let (Some(first_valid_date), Some(last_valid_date)) =
+        (dates.iter().min().copied(), dates.iter().max().copied())
+    else {
+        return Err(GtfsError::Invalid {
+            file: CALENDAR_FILE_NAME,
+            message: "no service dates in calendar or calendar_dates".to_owned(),
+        });
+    };
+
+    let day_count = last_valid_date
+        .signed_duration_since(first_valid_date)
+        .num_days() as usize
+        + 1;
+    let day_index =
+        |date: NaiveDate| date.signed_duration_since(first_valid_date).num_days() as usize;
+
+    let service_ids: BTreeSet<&str> = calendar
+        .iter()
+        .map(|service| service.service_id.as_str())
+        .chain(
+            calendar_dates
+                .iter()
+                .map(|exception| exception.service_id.as_str()),
+        )
+        .collect();
+    let footnotes: HashMap<&str, u64> = service_ids
+        .into_iter()
+        .enumerate()
+        .map(|(index, service_id)| (service_id, index as u64 + 1))
+        .collect();
+
+    let mut validities: HashMap<u64, Vec<bool>> = footnotes
+        .values()
+        .map(|footnote| (*footnote, vec![false; day_count]))
+        .collect();
+
+    for service in calendar {
+        let days = validities
+            .get_mut(&footnotes[service.service_id.as_str()])
+            .unwrap();
+        let start = parse_date(CALENDAR_FILE_NAME, &service.start_date)?;
+        let end = parse_date(CALENDAR_FILE_NAME, &service.end_date)?;
+
+        for date in start.iter_days().take_while(|date| *date <= end) {
+            if service.runs_on(date.weekday()) {
+                days[day_index(date)] = true;
+            }
+        }
+    }
+
+    for exception in calendar_dates {
+        let days = validities
+            .get_mut(&footnotes[exception.service_id.as_str()])
+            .unwrap();
+        let date = parse_date(CALENDAR_DATES_FILE_NAME, &exception.date)?;
+
+        // 1 adds the date to the service, 2 removes it
+        days[day_index(date)] = exception.exception_type == 1;
+    }
+
+    let feed_info = feed_info.first();
+    let header = || Header {
+        company_id: 0,
+        first_valid_date,
+        last_valid_date,
+        version: feed_info
+            .and_then(|info| info.feed_version.as_deref())
+            .and_then(|version| version.parse().ok())
+            .unwrap_or_default(),
+        description: feed_info
+            .map(|info| info.feed_publisher_name.clone())
+            .unwrap_or_else(|| "GTFS".to_owned()),
+    };
+
+    Ok((header(), footnotes, RideValidity::new(header(), validities)))
+}
+
+fn parse_time(time: Option<&str>) -> Result<Option<DayOffset>, GtfsError> {
+    time.map(|time| {
+        DayOffset::from_gtfs_str(time).map_err(|e| GtfsError::Invalid {
+            file: STOP_TIMES_FILE_NAME,
+            message: format!("time {time}: {e}"),
+        })
+    })
+    .transpose()
+}
+
+/// Converts the stop times of a trip into timetable entries, returns None for trips with less than two timed stops
+/// Stops without times or where passengers can't board or alight become waypoints
+fn timetable(
+    stop_times: &[&StopTimeRecord],
+    stops_by_id: &HashMap<&str, &StopRecord>,
+    footnote: u64,
+    locations: &mut LocationCache,
+) -> Result<Option<Vec<TimetableEntry>>, GtfsError> {
+    let mut timed_stops = vec![];
+    for stop_time in stop_times {
+        let arrival = parse_time(stop_time.arrival_time.as_deref())?;
+        let departure = parse_time(stop_time.departure_time.as_deref())?;
+        let passing = stop_time.pickup_type == Some(NO_PICKUP_DROP_OFF)
+            && stop_time.drop_off_type == Some(NO_PICKUP_DROP_OFF);
+
+        let times = match (arrival, departure) {
+            _ if passing => None,
+            (Some(arrival), Some(departure)) => Some((arrival, departure)),
+            (Some(time), None) | (None, Some(time)) => Some((time, time)),
+            (None, None) => None,
+        };
+
+        timed_stops.push((stop_time, times));
+    }
+
+    let first = timed_stops.iter().position(|(_, times)| times.is_some());
+    let last = timed_stops.iter().rposition(|(_, times)| times.is_some());
+    let (Some(first), Some(last)) = (first, last) else {
+        return Ok(None);
+    };
+    if first == last {
+        return Ok(None);
+    }
+
+    let entries = timed_stops[first..=last]
+        .iter()
+        .enumerate()
+        .map(|(index, (stop_time, times))| {
+            let stop = stops_by_id.get(stop_time.stop_id.as_str());
+            let code = stop.map_or(stop_time.stop_id.as_str(), |stop| location_code(stop));
+            let platform = stop
+                .and_then(|stop| stop.platform_code.as_deref())
+                .and_then(|platform| platform.parse::<Platform>().ok())
+                .map(|platform| PlatformInfo::new(Some(platform.clone()), Some(platform), footnote))
+                .into_iter()
+                .collect();
+
+            let stop_kind = match times {
+                None => StopKind::Waypoint,
+                Some((_, departure)) if index == 0 => StopKind::Departure(platform, *departure),
+                Some((arrival, _)) if index == last - first => {
+                    StopKind::Arrival(platform, *arrival)
+                }
+                Some((arrival, departure)) if arrival == departure => {
+                    StopKind::StopShort(platform, *arrival)
+                }
+                Some((arrival, departure)) => StopKind::StopLong(platform, *arrival, *departure),
+            };
+
+            TimetableEntry {
+                code: locations.get_handle(code),
+                stop_kind,
+            }
+        })
+        .collect();
+
+    Ok(Some(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn archive(files: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+
+        let mut cursor = zip.finish().unwrap();
+        cursor.set_position(0);
+        cursor
+    }
+
+    #[test]
+    fn read_minimal_feed() -> TestResult {
+        let archive = archive(&[
+            (
+                AGENCY_FILE_NAME,
+                "agency_id,agency_name,agency_url,agency_timezone\r\nns,NS,https://ns.nl,Europe/Amsterdam\r\n",
+            ),
+            (
+                STOPS_FILE_NAME,
+                "stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station,platform_code\n\
+                 ut,Utrecht Centraal,52.089,5.110,1,,\n\
+                 ut_11,Utrecht Centraal,52.089,5.110,0,ut,11\n\
+                 gd,Gouda,52.017,4.704,1,,\n\
+                 wd,Woerden,52.085,4.891,1,,\n",
+            ),
+            (
+                ROUTES_FILE_NAME,
+                "route_id,agency_id,route_short_name,route_type\nr1,ns,IC,2\n",
+            ),
+            (
+                TRIPS_FILE_NAME,
+                "route_id,service_id,trip_id,trip_short_name\nr1,weekdays,t1,2871\n",
+            ),
+            (
+                STOP_TIMES_FILE_NAME,
+                "trip_id,arrival_time,departure_time,stop_id,stop_sequence,pickup_type,drop_off_type\n\
+                 t1,24:10:00,24:10:00,ut_11,1,0,0\n\
+                 t1,24:20:00,24:20:00,wd,2,1,1\n\
+                 t1,24:29:00,24:30:00,gd,3,0,0\n",
+            ),
+            (
+                CALENDAR_FILE_NAME,
+                "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+                 weekdays,1,1,1,1,1,0,0,20240101,20240107\n",
+            ),
+            (
+                CALENDAR_DATES_FILE_NAME,
+                "service_id,date,exception_type\nweekdays,20240101,2\n",
+            ),
+        ]);
+
+        let (timetable, stations) = read_gtfs(archive)?;
+
+        assert_eq!(stations.len(), 3);
+        assert_eq!(timetable.companies.first().map(Company::name), Some("NS"));
+
+        let code = |a: &str| timetable.locations.lookup_handle(a).unwrap();
+        let ride = timetable.rides.first().unwrap();
+        assert_eq!(
+            ride,
+            &Ride {
+                id: "2871".to_owned(),
+                transit_mode: "IC".to_owned(),
+                timetable: vec![
+                    TimetableEntry {
+                        code: code("ut"),
+                        stop_kind: StopKind::Departure(
+                            vec![PlatformInfo::plain(11, ride.day_validity)],
+                            DayOffset::from_hour_minute(24, 10)
+                        )
+                    },
+                    TimetableEntry {
+                        code: code("wd"),
+                        stop_kind: StopKind::Waypoint
+                    },
+                    TimetableEntry {
+                        code: code("gd"),
+                        stop_kind: StopKind::Arrival(vec![], DayOffset::from_hour_minute(24, 29))
+                    },
+                ],
+                day_validity: ride.day_validity,
+                previous: None,
+                next: None,
+                operator: 1,
+            }
+        );
+
+        let valid = |day: u32| {
+            timetable
+                .validity
+                .is_valid_on_day(
+                    ride.day_validity,
+                    NaiveDate::from_ymd_opt(2024, 1, day).unwrap(),
+                )
+                .unwrap()
+        };
+        assert!(!valid(1), "removed by calendar_dates");
+        assert!(valid(2));
+        assert!(!valid(6), "saturday");
+
+        Ok(())
+    }
+}
@@ -2,41 +2,171 @@ use std::{
     collections::HashMap,
     fmt::{Display, Write},
     fs::File,
-    io::{self, Cursor, Read},
+    io::{self, Read},
+    num::NonZeroUsize,
     str::FromStr,
 };
 
 use chrono::NaiveDate;
 use parsing::{
-    parse_company_file, parse_delivery_file, parse_footnote_file, parse_timetable_file, CompanyFile,
+    parse_company, parse_delivery_file, parse_file, parse_file_header, parse_footnote_record,
+    parse_records, parse_station, split_chunks, RecordError, RecordParser,
 };
-use serde::Serialize;
-use winnow::{BStr, Parser};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use winnow::BStr;
 
 use crate::dayoffset::DayOffset;
 
 use self::parsing::TransitMode;
 
+mod diff;
 mod parsing;
 
 const FOOTNOTE_FILE_NAME: &str = "footnote.dat";
 const TIMETABLE_FILE_NAME: &str = "timetbls.dat";
 const COMPANY_FILE_NAME: &str = "company.dat";
+const STATION_FILE_NAME: &str = "stations.dat";
 const HEADER_FILENAME: &str = "delivery.dat";
 
+#[derive(Error, Debug)]
+pub enum IffError {
+    #[error("Error reading archive: {0}")]
+    Archive(#[from] zip::result::ZipError),
+    #[error("{file} is missing from the archive")]
+    Missing { file: &'static str },
+    #[error("Error reading {file}: {source}")]
+    Io {
+        file: &'static str,
+        source: io::Error,
+    },
+    #[error("{file} line {line}{}: {message}, found `{found}`", record.map(|id| format!(" (record {id})")).unwrap_or_default())]
+    Parse {
+        file: &'static str,
+        line: usize,
+        record: Option<u64>,
+        message: String,
+        /// Start of the line parsing stopped at
+        found: String,
+    },
+}
+
+impl IffError {
+    /// Adds the line number and content to `error`, counting lines from `position`, which is on `line`
+    fn at_line(
+        file: &'static str,
+        content: &[u8],
+        error: RecordError,
+        position: &mut usize,
+        line: &mut usize,
+    ) -> Self {
+        let offset = error.offset.clamp(*position, content.len());
+        *line += content[*position..offset]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count();
+        *position = offset;
+
+        let line_start = content[..offset]
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |newline| newline + 1);
+        let line_end = content[line_start..]
+            .iter()
+            .take_while(|b| **b != b'\r' && **b != b'\n')
+            .take(60)
+            .count();
+        let found =
+            String::from_utf8_lossy(&content[line_start..line_start + line_end]).into_owned();
+
+        Self::Parse {
+            file,
+            line: *line,
+            record: error.record,
+            message: error.message,
+            found,
+        }
+    }
+
+    /// Adds line numbers and content to `errors`, which have to be sorted by offset
+    fn locate(file: &'static str, content: &[u8], errors: Vec<RecordError>) -> Vec<Self> {
+        let (mut position, mut line) = (0, 1);
+
+        errors
+            .into_iter()
+            .map(|error| Self::at_line(file, content, error, &mut position, &mut line))
+            .collect()
+    }
+
+    fn parse(file: &'static str, content: &[u8], error: RecordError) -> Self {
+        Self::at_line(file, content, error, &mut 0, &mut 1)
+    }
+}
+
+/// How records that fail to parse are handled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseMode {
+    /// Loading fails on the first invalid record
+    #[default]
+    Strict,
+    /// Invalid records are skipped and reported, see [`Iff::skipped`]
+    Lenient,
+}
+
+/// How an archive is parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    /// Worker threads parsing the timetable file, the result doesn't depend on it
+    pub threads: NonZeroUsize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            mode: ParseMode::Strict,
+            threads: NonZeroUsize::MIN,
+        }
+    }
+}
+
+/// Timetable data in the form `DataRepo` consumes it, independent of the format it was loaded from
+pub struct TimetableData {
+    pub header: Header,
+    pub rides: Vec<Ride>,
+    pub validity: RideValidity,
+    pub companies: Vec<Company>,
+    pub locations: LocationCache,
+    /// Stations as listed by the timetable itself, only IFF archives list them
+    pub stations: Vec<IffStation>,
+}
+
 pub struct Iff {
     timetable: TimeTable,
     validity: RideValidity,
     companies: Vec<Company>,
+    stations: Vec<IffStation>,
     header: Header,
     pub locations: LocationCache,
+    skipped: Vec<IffError>,
 }
 
 impl Iff {
-    pub fn new_from_archive(archive: &File) -> Result<Self, String> {
-        let (timetable, locations) = Self::parse_timetable(archive)?;
-        let validity = Self::parse_validity(archive)?;
-        let companies = Self::parse_companies(archive).map(|c| c.companies)?;
+    pub fn new_from_archive(archive: &File) -> Result<Self, IffError> {
+        Self::new_from_archive_with_options(archive, ParseOptions::default())
+    }
+
+    pub fn new_from_archive_with_options(
+        archive: &File,
+        options: ParseOptions,
+    ) -> Result<Self, IffError> {
+        let mut skipped = vec![];
+
+        let (timetable, locations) = Self::parse_timetable(archive, options, &mut skipped)?;
+        let validity = Self::parse_validity(archive, options.mode, &mut skipped)?;
+        let companies = Self::parse_companies(archive, options.mode, &mut skipped)?;
+        let stations = Self::parse_stations(archive, options.mode, &mut skipped)?;
         let delivery = Self::parse_delivery(archive)?;
 
         Ok(Self {
@@ -44,140 +174,276 @@ impl Iff {
             timetable,
             validity,
             companies,
+            stations,
             header: delivery,
+            skipped,
         })
     }
 
-    pub fn timetable(&self) -> &TimeTable {
-        &self.timetable
+    /// Records skipped in lenient mode
+    pub fn skipped(&self) -> &[IffError] {
+        &self.skipped
     }
 
-    pub fn timetable_mut(&mut self) -> &mut TimeTable {
-        &mut self.timetable
+    /// Splits all records into rides, dropping the IFF specific record structure
+    pub fn into_timetable_data(self) -> TimetableData {
+        let rides = self
+            .timetable
+            .rides
+            .iter()
+            .flat_map(|r| r.split_on_ride_id())
+            .collect();
+
+        TimetableData {
+            header: self.header,
+            rides,
+            validity: self.validity,
+            companies: self.companies,
+            locations: self.locations,
+            stations: self.stations,
+        }
     }
 
-    pub fn rides_mut(&mut self) -> &mut Vec<Record> {
-        &mut self.timetable.rides
+    #[allow(dead_code)]
+    pub fn timetable_mut(&mut self) -> &mut TimeTable {
+        &mut self.timetable
     }
 
-    pub fn validity(&self) -> &RideValidity {
-        &self.validity
-    }
+    /// Parses the records of `file`, adding the ones skipped in lenient mode to `skipped`
+    fn parse_records<'s, T>(
+        file: &'static str,
+        content: &'s [u8],
+        starts_record: fn(u8) -> bool,
+        parse_record: impl winnow::Parser<parsing::Stream<'s>, T, winnow::error::ContextError>,
+        mode: ParseMode,
+        skipped: &mut Vec<IffError>,
+    ) -> Result<(Header, Vec<T>), IffError> {
+        let mut errors = vec![];
+
+        let parsed = parse_file(
+            content,
+            starts_record,
+            parse_record,
+            mode == ParseMode::Lenient,
+            &mut errors,
+        )
+        .map_err(|e| IffError::parse(file, content, e))?;
 
-    pub fn companies(&self) -> &[Company] {
-        &self.companies
-    }
+        skipped.extend(IffError::locate(file, content, errors));
 
-    pub fn header(&self) -> &Header {
-        &self.header
+        Ok(parsed)
     }
 
+    /// Parses the timetable file in chunks of records, each on its own thread with its own location cache
+    /// The caches are merged in file order, so the handles match those of parsing it in one go
     fn parse_timetable(
         archive: impl Read + io::Seek,
-    ) -> Result<(TimeTable, LocationCache), String> {
+        options: ParseOptions,
+        skipped: &mut Vec<IffError>,
+    ) -> Result<(TimeTable, LocationCache), IffError> {
         let content = read_bytes_from_archive(archive, TIMETABLE_FILE_NAME)?;
-        let content = BStr::new(&content);
-        if !content.is_ascii() {
-            return Err("Expected timetable file to be valid ASCII".to_owned());
+
+        const ESTIMATE_UNIQUE_LOCATION_CODES: usize = 1000;
+        let starts_record = |b| b == b'#';
+
+        let (header, start) = parse_file_header(&content)
+            .map_err(|e| IffError::parse(TIMETABLE_FILE_NAME, &content, e))?;
+        let chunks = split_chunks(
+            &content,
+            start..content.len(),
+            starts_record,
+            options.threads.get(),
+        );
+
+        let parse_chunk = |range| {
+            let mut locations = LocationCache::with_capacity(ESTIMATE_UNIQUE_LOCATION_CODES);
+            let mut errors = vec![];
+            let records = parse_records(
+                &content,
+                range,
+                starts_record,
+                RecordParser::new(&mut locations),
+                options.mode == ParseMode::Lenient,
+                &mut errors,
+            );
+
+            (records, locations, errors)
+        };
+
+        let parsed: Vec<_> = if chunks.len() <= 1 {
+            chunks.into_iter().map(parse_chunk).collect()
+        } else {
+            std::thread::scope(|scope| {
+                let workers: Vec<_> = chunks
+                    .into_iter()
+                    .map(|range| scope.spawn(|| parse_chunk(range)))
+                    .collect();
+
+                workers
+                    .into_iter()
+                    .map(|worker| {
+                        worker
+                            .join()
+                            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                    })
+                    .collect()
+            })
+        };
+
+        let mut locations = LocationCache::with_capacity(ESTIMATE_UNIQUE_LOCATION_CODES);
+        let mut rides = vec![];
+        let mut errors = vec![];
+
+        for (records, chunk_locations, chunk_errors) in parsed {
+            let mut records =
+                records.map_err(|e| IffError::parse(TIMETABLE_FILE_NAME, &content, e))?;
+
+            let handles = locations.merge(&chunk_locations);
+            for record in &mut records {
+                record.remap(&handles);
+            }
+
+            rides.extend(records);
+            errors.extend(chunk_errors);
         }
 
-        parse_timetable_file
-            .parse(content)
-            .map_err(|o| o.to_string())
+        skipped.extend(IffError::locate(TIMETABLE_FILE_NAME, &content, errors));
+
+        Ok((TimeTable { header, rides }, locations))
     }
 
-    fn parse_validity(archive: impl Read + io::Seek) -> Result<RideValidity, String> {
+    fn parse_validity(
+        archive: impl Read + io::Seek,
+        mode: ParseMode,
+        skipped: &mut Vec<IffError>,
+    ) -> Result<RideValidity, IffError> {
         let content = read_string_from_archive(archive, FOOTNOTE_FILE_NAME)?;
-        let content = BStr::new(&content);
-        if !content.is_ascii() {
-            return Err("Expected timetable file to be valid ASCII".to_owned());
-        }
-
-        parse_footnote_file
-            .parse(content)
-            .map_err(|o| o.to_string())
+        let (header, _) = parse_file_header(content.as_bytes())
+            .map_err(|e| IffError::parse(FOOTNOTE_FILE_NAME, content.as_bytes(), e))?;
+
+        let (_, footnotes) = Self::parse_records(
+            FOOTNOTE_FILE_NAME,
+            content.as_bytes(),
+            |b| b == b'#',
+            parse_footnote_record(header.day_count()),
+            mode,
+            skipped,
+        )?;
+
+        Ok(RideValidity::new(
+            header,
+            footnotes
+                .into_iter()
+                .map(|footnote| (footnote.id, footnote.validity))
+                .collect(),
+        ))
     }
 
-    fn parse_companies(archive: impl Read + io::Seek) -> Result<CompanyFile, String> {
+    fn parse_companies(
+        archive: impl Read + io::Seek,
+        mode: ParseMode,
+        skipped: &mut Vec<IffError>,
+    ) -> Result<Vec<Company>, IffError> {
         let content = read_string_from_archive(archive, COMPANY_FILE_NAME)?;
-        let content = BStr::new(&content);
-        if !content.is_ascii() {
-            return Err("Expected timetable file to be valid ASCII".to_owned());
-        }
 
-        parse_company_file(content).map_err(|o| o.to_string())
+        Self::parse_records(
+            COMPANY_FILE_NAME,
+            content.as_bytes(),
+            |_| true,
+            parse_company,
+            mode,
+            skipped,
+        )
+        .map(|(_, companies)| companies)
     }
 
-    pub fn parse_delivery(archive: impl Read + io::Seek) -> Result<Header, String> {
-        let content = read_string_from_archive(archive, HEADER_FILENAME)?;
-        let content = BStr::new(&content);
-        if !content.is_ascii() {
-            return Err("Expected timetable file to be valid ASCII".to_owned());
-        }
-
-        parse_delivery_file(content).map_err(|o| o.to_string())
+    /// Parses the stations file, which archives don't need to include
+    fn parse_stations(
+        archive: impl Read + io::Seek,
+        mode: ParseMode,
+        skipped: &mut Vec<IffError>,
+    ) -> Result<Vec<IffStation>, IffError> {
+        let content = match read_string_from_archive(archive, STATION_FILE_NAME) {
+            Ok(content) => content,
+            Err(IffError::Missing { .. }) => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        Self::parse_records(
+            STATION_FILE_NAME,
+            content.as_bytes(),
+            |_| true,
+            parse_station,
+            mode,
+            skipped,
+        )
+        .map(|(_, stations)| stations)
     }
 
-    pub fn parse_version_only(data: &[u8]) -> Result<u64, String> {
-        let cursor = Cursor::new(data);
-        let content = read_bytes_from_archive(cursor, HEADER_FILENAME)?;
-        let content = BStr::new(&content);
-        if !content.is_ascii() {
-            return Err("Expected timetable file to be valid ASCII".to_owned());
-        }
+    pub fn parse_delivery(archive: impl Read + io::Seek) -> Result<Header, IffError> {
+        let content = read_string_from_archive(archive, HEADER_FILENAME)?;
 
-        parse_delivery_file(content)
-            .map_err(|e| e.to_string())
-            .map(|h| h.version)
+        parse_delivery_file(BStr::new(&content))
+            .map_err(|e| IffError::parse(HEADER_FILENAME, content.as_bytes(), e))
     }
 }
 
-fn read_string_from_archive(
+fn read_file_from_archive(
     archive: impl Read + io::Seek,
-    filename: &str,
-) -> Result<String, String> {
-    let mut archive = zip::ZipArchive::new(archive).expect("valid new archive");
-    let mut file = archive
-        .by_name(filename)
-        .map_err(|_| "Error getting file from archive")?;
+    filename: &'static str,
+) -> Result<Vec<u8>, IffError> {
+    let mut archive = zip::ZipArchive::new(archive)?;
+    let mut file = archive.by_name(filename).map_err(|e| match e {
+        zip::result::ZipError::FileNotFound => IffError::Missing { file: filename },
+        e => IffError::Archive(e),
+    })?;
 
     let mut buf = vec![];
+    file.read_to_end(&mut buf).map_err(|source| IffError::Io {
+        file: filename,
+        source,
+    })?;
 
-    file.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    Ok(buf)
+}
 
-    // File should be ISO 8859-1 / Latin1, this should work fine
-    let str_content = String::from_utf8(buf).map_err(|_| "file contained invalid utf-8")?;
+/// Decodes IFF text, which is ISO 8859-1 / Latin1
+///
+/// Content that is valid UTF-8 is taken as is, Latin1 text with accented characters practically never is
+fn decode_text(buf: Vec<u8>) -> String {
+    String::from_utf8(buf).unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect())
+}
 
-    Ok(str_content.to_owned())
+fn read_string_from_archive(
+    archive: impl Read + io::Seek,
+    filename: &'static str,
+) -> Result<String, IffError> {
+    Ok(decode_text(read_file_from_archive(archive, filename)?))
 }
 
+/// Reads a file as UTF-8 bytes, passing ASCII content on without decoding or copying it
 fn read_bytes_from_archive(
     archive: impl Read + io::Seek,
-    filename: &str,
-) -> Result<Vec<u8>, String> {
-    let mut archive = zip::ZipArchive::new(archive).expect("valid new archive");
-    let mut file = archive
-        .by_name(filename)
-        .map_err(|_| "Error getting file from archive")?;
-
-    let mut buf = vec![];
-    file.read_to_end(&mut buf).map_err(|e| e.to_string())?;
-
-    if !buf.is_ascii() {
-        return Err("File is not valid ASCII".into());
+    filename: &'static str,
+) -> Result<Vec<u8>, IffError> {
+    let buf = read_file_from_archive(archive, filename)?;
+
+    if buf.is_ascii() {
+        Ok(buf)
+    } else {
+        Ok(decode_text(buf).into_bytes())
     }
-
-    Ok(buf)
 }
 
-#[derive(PartialEq, Debug, Eq, Clone, Serialize)]
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
 pub struct TimetableEntry {
     pub code: LocationCodeHandle,
     pub stop_kind: StopKind,
 }
 
 impl TimetableEntry {
-    fn serializable<'a, 'b>(&'a self, cache: &'b LocationCache) -> TimetableEntryContext
+    #[allow(dead_code)]
+    fn serializable<'a, 'b>(&'a self, cache: &'b LocationCache) -> TimetableEntryContext<'a, 'b>
     where
         'b: 'a,
     {
@@ -188,6 +454,7 @@ impl TimetableEntry {
     }
 }
 
+#[allow(dead_code)]
 pub struct TimetableEntryContext<'e, 'c> {
     pub entry: &'e TimetableEntry,
     pub context: &'c LocationCache,
@@ -215,100 +482,192 @@ struct TimetableEntryRaw<'a> {
 }
 
 impl<'a> TimetableEntryRaw<'a> {
-    pub fn to_proper(&self, cache: &mut LocationCache) -> TimetableEntry {
-        TimetableEntry {
-            code: cache.get_handle(self.code),
-            stop_kind: self.stop_kind.clone(),
-        }
+    /// Splits off the location code, leaving an entry at [`LocationCodeHandle::UNINTERNED`]
+    pub fn into_uninterned(self) -> (&'a str, TimetableEntry) {
+        let entry = TimetableEntry {
+            code: LocationCodeHandle::UNINTERNED,
+            stop_kind: self.stop_kind,
+        };
+
+        (self.code, entry)
     }
 }
 
+/// A platform as signposted, a single track or a train stopping along several of them
 #[derive(Debug, PartialEq, Clone, Eq)]
 pub enum Platform {
-    Regular(u8),
-    Split(u8, char),
-    Range(u8, u8),
+    /// `5`, `14ab` or `A`
+    Single(Track),
+    /// `1-2` or `1a-1b`
+    Range(Track, Track),
+}
+
+/// A platform track, a number followed by letters for a section of it, or only letters
+/// At least one of the number and letters is present
+#[derive(Debug, PartialEq, Clone, Eq, Serialize, Deserialize)]
+pub struct Track {
+    number: Option<u16>,
+    letters: Box<str>,
+}
+
+impl Track {
+    pub fn numbered(number: u16) -> Self {
+        Self {
+            number: Some(number),
+            letters: "".into(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn number(&self) -> Option<u16> {
+        self.number
+    }
+
+    #[allow(dead_code)]
+    pub fn letters(&self) -> &str {
+        &self.letters
+    }
+}
+
+impl Display for Track {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(number) = self.number {
+            write!(f, "{number}")?;
+        }
+
+        f.write_str(&self.letters)
+    }
 }
 
 impl Display for Platform {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Platform::Regular(n) => f.write_fmt(format_args!("{n}")),
-            Platform::Split(n, suffix) => f.write_fmt(format_args!("{n}{suffix}")),
-            Platform::Range(from, to) => f.write_fmt(format_args!("{from} - {to}")),
+            Platform::Single(track) => write!(f, "{track}"),
+            Platform::Range(from, to) => write!(f, "{from} - {to}"),
         }
     }
 }
 
+/// Binary layout of a [`Platform`], human readable formats get the platform as displayed instead
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Platform")]
+enum PlatformDef {
+    Single(Track),
+    Range(Track, Track),
+}
+
 impl Serialize for Platform {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            PlatformDef::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Platform {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(serde::de::Error::custom)
+        } else {
+            PlatformDef::deserialize(deserializer)
+        }
     }
 }
 
 impl Platform {
-    pub fn plain(n: u8) -> Self {
-        Platform::Regular(n)
+    #[allow(dead_code)]
+    pub fn plain(number: u16) -> Self {
+        Platform::Single(Track::numbered(number))
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Eq, Serialize)]
-pub struct PlatformParseError;
+#[derive(Error, Debug, PartialEq, Clone, Eq)]
+#[error("Invalid platform `{platform}`: {kind}")]
+pub struct PlatformParseError {
+    platform: String,
+    kind: PlatformParseErrorKind,
+}
 
-impl FromStr for Platform {
-    type Err = PlatformParseError;
+#[derive(Error, Debug, PartialEq, Clone, Eq)]
+pub enum PlatformParseErrorKind {
+    #[error("no platform given")]
+    Empty,
+    #[error("platform numbers go up to {}", u16::MAX)]
+    NumberTooLarge,
+    #[error("unexpected `{0}`")]
+    UnexpectedCharacter(char),
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.trim();
-        // Common case
-        let num_parse_result = u8::from_str(s);
-        if let Ok(number) = num_parse_result {
-            return Ok(Platform::Regular(number));
-        }
+impl FromStr for Track {
+    type Err = PlatformParseErrorKind;
 
-        if s.ends_with(|c: char| c.is_alphabetic()) {
-            let a = &s[0..s.len() - 1];
+    /// Parses a track without surrounding whitespace
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (number, letters) = s.split_at(digits);
 
-            return Ok(Platform::Split(
-                a.parse().unwrap(),
-                s.chars().last().unwrap(),
-            ));
+        if let Some(c) = letters.chars().find(|c| !c.is_alphabetic()) {
+            return Err(PlatformParseErrorKind::UnexpectedCharacter(c));
         }
 
-        if s.is_empty() {
-            return Err(PlatformParseError {});
-        }
+        let number = match number {
+            "" if letters.is_empty() => return Err(PlatformParseErrorKind::Empty),
+            "" => None,
+            number => Some(
+                number
+                    .parse()
+                    .map_err(|_| PlatformParseErrorKind::NumberTooLarge)?,
+            ),
+        };
 
-        // Special case
-        if s.chars().nth(1).expect("Expected to find a -") == '-' {
-            let split = s.split_once('-').expect("clean platform split");
+        Ok(Self {
+            number,
+            letters: letters.into(),
+        })
+    }
+}
 
-            let left: u8 = split
-                .0
-                .parse()
-                .expect("expected first number parse to succeed");
-            let right: u8 = split
-                .1
-                .parse()
-                .expect("expected first number parse to succeed");
+impl FromStr for Platform {
+    type Err = PlatformParseError;
 
-            return Ok(Platform::Range(left, right));
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = |kind| PlatformParseError {
+            platform: s.to_owned(),
+            kind,
+        };
+
+        match s.trim().split_once('-') {
+            None => s.trim().parse().map(Platform::Single).map_err(error),
+            Some((from, to)) => Ok(Platform::Range(
+                from.trim_end().parse().map_err(error)?,
+                to.trim_start().parse().map_err(error)?,
+            )),
         }
-
-        Err(PlatformParseError)
-        // common case
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct LocationCodeHandle {
     inner: u16,
 }
 
+impl LocationCodeHandle {
+    /// Placeholder for the location of an entry whose record is still being checked,
+    /// so rejected records don't leave their codes in the cache
+    const UNINTERNED: Self = Self { inner: u16::MAX };
+}
+
 #[derive(Clone, Debug, Serialize)]
 #[serde(transparent)]
 pub struct LocationCache {
@@ -317,7 +676,30 @@ pub struct LocationCache {
     lookup: HashMap<Box<str>, u16>,
 }
 
+impl From<Vec<Box<str>>> for LocationCache {
+    /// Rebuilds the lookup of a deserialized cache, handles keep their index into `storage`
+    fn from(storage: Vec<Box<str>>) -> Self {
+        let lookup = storage
+            .iter()
+            .enumerate()
+            .map(|(index, code)| (code.clone(), index as u16))
+            .collect();
+
+        Self { storage, lookup }
+    }
+}
+
+impl<'de> Deserialize<'de> for LocationCache {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Vec::<Box<str>>::deserialize(deserializer).map(Self::from)
+    }
+}
+
 impl LocationCache {
+    #[allow(dead_code)]
     pub fn new() -> Self {
         Self {
             lookup: HashMap::new(),
@@ -361,9 +743,19 @@ impl LocationCache {
     pub fn codes(&self) -> &[Box<str>] {
         &self.storage
     }
+
+    /// Adds the codes of `other`, returning the handle in this cache for every handle of `other`
+    /// Codes new to this cache get handles in the order `other` interned them
+    pub fn merge(&mut self, other: &LocationCache) -> Vec<LocationCodeHandle> {
+        other
+            .storage
+            .iter()
+            .map(|code| self.get_handle(code))
+            .collect()
+    }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct PlatformInfo {
     pub arrival_platform: Option<Platform>,
     pub departure_platform: Option<Platform>,
@@ -371,22 +763,47 @@ pub struct PlatformInfo {
 }
 
 impl PlatformInfo {
-    pub fn plain(number: u8, footnote: u64) -> Self {
+    pub fn footnote(&self) -> u64 {
+        self.footnote
+    }
+
+    /// This platform info when its footnote lists `date`, stops only use the platforms on the days of the footnote
+    pub fn on_date(&self, validity: &RideValidity, date: NaiveDate) -> Option<&Self> {
+        validity
+            .is_valid_on_day(self.footnote, date)
+            .unwrap_or(false)
+            .then_some(self)
+    }
+
+    pub fn new(
+        arrival_platform: Option<Platform>,
+        departure_platform: Option<Platform>,
+        footnote: u64,
+    ) -> Self {
         PlatformInfo {
-            arrival_platform: Some(Platform::Regular(number)),
-            departure_platform: Some(Platform::Regular(number)),
+            arrival_platform,
+            departure_platform,
+            footnote,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn plain(number: u16, footnote: u64) -> Self {
+        PlatformInfo {
+            arrival_platform: Some(Platform::plain(number)),
+            departure_platform: Some(Platform::plain(number)),
             footnote,
         }
     }
 }
 
-#[derive(PartialEq, Debug, Eq, Clone, Serialize)]
+#[derive(PartialEq, Debug, Eq, Clone, Serialize, Deserialize)]
 pub enum StopKind {
-    Departure(Option<PlatformInfo>, DayOffset),
-    Arrival(Option<PlatformInfo>, DayOffset),
+    Departure(Vec<PlatformInfo>, DayOffset),
+    Arrival(Vec<PlatformInfo>, DayOffset),
     Waypoint,
-    StopShort(Option<PlatformInfo>, DayOffset),
-    StopLong(Option<PlatformInfo>, DayOffset, DayOffset),
+    StopShort(Vec<PlatformInfo>, DayOffset),
+    StopLong(Vec<PlatformInfo>, DayOffset, DayOffset),
 }
 
 impl StopKind {
@@ -425,18 +842,19 @@ impl StopKind {
         }
     }
 
-    pub fn platform_info(&self) -> Option<&PlatformInfo> {
+    /// Platforms of the stop, one per footnote when they differ between days
+    pub fn platforms(&self) -> &[PlatformInfo] {
         match self {
-            StopKind::Departure(pl, _) => pl.as_ref(),
-            StopKind::Arrival(pl, _) => pl.as_ref(),
-            StopKind::Waypoint => None,
-            StopKind::StopShort(pl, _) => pl.as_ref(),
-            StopKind::StopLong(pl, _, _) => pl.as_ref(),
+            StopKind::Departure(pl, _) => pl,
+            StopKind::Arrival(pl, _) => pl,
+            StopKind::Waypoint => &[],
+            StopKind::StopShort(pl, _) => pl,
+            StopKind::StopLong(pl, _, _) => pl,
         }
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub struct Header {
     pub company_id: u64,
     pub first_valid_date: chrono::NaiveDate,
@@ -445,23 +863,63 @@ pub struct Header {
     pub description: String,
 }
 
+impl Header {
+    /// Orders deliveries by the start of their validity, then by version,
+    /// so a delivery for a new timetable period counts as newer even if its version number was reset
+    pub fn cmp_recency(&self, other: &Header) -> std::cmp::Ordering {
+        self.first_valid_date
+            .cmp(&other.first_valid_date)
+            .then(self.version.cmp(&other.version))
+    }
+
+    /// If `date` falls within the validity period of the delivery
+    pub fn covers_date(&self, date: NaiveDate) -> bool {
+        self.first_valid_date <= date && date <= self.last_valid_date
+    }
+
+    /// Number of days in the validity period, counting both the first and last day
+    pub fn day_count(&self) -> usize {
+        self.last_valid_date
+            .signed_duration_since(self.first_valid_date)
+            .num_days() as usize
+            + 1
+    }
+}
+
+impl Display for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "version {} valid {} to {}",
+            self.version, self.first_valid_date, self.last_valid_date
+        )
+    }
+}
+
 struct DayValidityFootnote {
     id: u64,
     validity: Vec<bool>,
 }
 
 pub struct TimeTable {
+    #[allow(dead_code)]
     pub header: Header,
     pub rides: Vec<Record>,
     // pub locations: LocationCache,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct RideValidity {
     header: Header,
     validities: HashMap<u64, Vec<bool>>,
 }
 
 impl RideValidity {
+    /// `validities` maps footnote ids to a flag for every day from `header.first_valid_date` up to and including `header.last_valid_date`
+    pub fn new(header: Header, validities: HashMap<u64, Vec<bool>>) -> Self {
+        Self { header, validities }
+    }
+
     pub fn is_valid_on_day(&self, footnote_id: u64, date: NaiveDate) -> Result<bool, ()> {
         if date < self.header.first_valid_date || date > self.header.last_valid_date {
             return Err(()); // Out of validity range
@@ -472,9 +930,32 @@ impl RideValidity {
             .signed_duration_since(self.header.first_valid_date)
             .num_days() as u64;
 
-        self.validities.get(&footnote_id).ok_or(()).map(|v| {
-            *v.get(day_id as usize)
-                .expect("to find footnote in validity lookup")
+        // Parsing rejects footnotes without a flag for every day, other sources may still be short
+        self.validities
+            .get(&footnote_id)
+            .ok_or(())
+            .map(|v| v.get(day_id as usize).copied().unwrap_or(false))
+    }
+
+    /// If the footnote file defines `footnote_id`
+    pub fn has_footnote(&self, footnote_id: u64) -> bool {
+        self.validities.contains_key(&footnote_id)
+    }
+
+    /// First and last date of the validity period
+    pub fn date_range(&self) -> (NaiveDate, NaiveDate) {
+        (self.header.first_valid_date, self.header.last_valid_date)
+    }
+
+    /// All dates on which the given footnote is valid, None if the footnote is unknown
+    pub fn valid_dates(&self, footnote_id: u64) -> Option<impl Iterator<Item = NaiveDate> + '_> {
+        let first_valid_date = self.header.first_valid_date;
+
+        self.validities.get(&footnote_id).map(move |days| {
+            days.iter()
+                .zip(first_valid_date.iter_days())
+                .filter(|(valid, _)| **valid)
+                .map(|(_, date)| date)
         })
     }
 }
@@ -505,7 +986,7 @@ pub struct Footnote {
     pub last_stop: u64,
 }
 
-#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Ride {
     pub id: String,
     pub transit_mode: String,
@@ -516,6 +997,7 @@ pub struct Ride {
     pub operator: u32,
 }
 
+#[allow(dead_code)]
 pub struct RidePrettyPrint<'a>(&'a Ride, &'a LocationCache);
 
 impl<'a> Display for RidePrettyPrint<'a> {
@@ -532,18 +1014,31 @@ impl<'a> Display for RidePrettyPrint<'a> {
 }
 
 impl Ride {
+    /// Moves the location handles of this ride from `from` over to `to`, interning codes `to` doesn't know yet
+    pub fn relocate(&mut self, from: &LocationCache, to: &mut LocationCache) {
+        for entry in &mut self.timetable {
+            let code = from
+                .get_str(&entry.code)
+                .expect("ride locations to be in their own cache");
+            entry.code = to.get_handle(code);
+        }
+    }
+
+    #[allow(dead_code)]
     pub fn stop_at_code(&self, code: &LocationCodeHandle) -> Option<&TimetableEntry> {
         self.timetable
             .iter()
             .find(|entry| entry.code == *code && !entry.stop_kind.is_waypoint())
     }
     // TODO This needs to take footnotes into account for special trains eg international
+    #[allow(dead_code)]
     pub fn boardable_at_code(&self, code: &LocationCodeHandle) -> bool {
         self.timetable
             .iter()
             .any(|entry| entry.code == *code && entry.stop_kind.is_boardable())
     }
 
+    #[allow(dead_code)]
     pub fn pretty_print<'a>(&'a self, codes: &'a LocationCache) -> RidePrettyPrint<'a> {
         RidePrettyPrint(self, codes)
     }
@@ -615,21 +1110,14 @@ impl LegKind {
         }
     }
 
-    pub fn platform_info(&self) -> Option<&PlatformInfo> {
-        #[allow(clippy::match_same_arms)]
+    pub fn platforms(&self) -> &[PlatformInfo] {
         match self {
-            Self::Stationary(_, kind) => match kind {
-                StopKind::Departure(plat, _) => plat.as_ref(),
-                StopKind::Arrival(plat, _) => plat.as_ref(),
-                StopKind::Waypoint => None,
-                StopKind::StopShort(plat, _) => plat.as_ref(),
-                StopKind::StopLong(plat, _, _) => plat.as_ref(),
-            },
+            Self::Stationary(_, kind) => kind.platforms(),
             Self::Moving {
                 from: _,
                 to: _,
                 waypoints: _,
-            } => None,
+            } => &[],
         }
     }
 }
@@ -638,14 +1126,595 @@ impl LegKind {
 pub struct Leg {
     pub start: DayOffset,
     pub end: DayOffset,
+    /// Index in the timetable of the ride of the stop the leg starts at
+    pub from_stop: usize,
+    /// Index in the timetable of the ride of the stop the leg ends at, the same stop for stationary legs
+    pub to_stop: usize,
     // #[serde(flatten)]
     pub kind: LegKind,
 }
 
-#[derive(Serialize, Debug)]
+/// A station as listed in the stations file of an IFF archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct IffStation {
+    pub code: Box<str>,
+    pub name: Box<str>,
+    pub country: Box<str>,
+    /// Rijksdriehoek x and y in metres, None for the stations listed at the origin, like most foreign ones
+    pub position: Option<(u32, u32)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Company {
     id: u32,
     code: Box<str>,
     name: Box<str>,
     end_of_timetable: DayOffset,
 }
+
+impl Company {
+    pub fn new(id: u32, code: &str, name: &str, end_of_timetable: DayOffset) -> Self {
+        Self {
+            id,
+            code: code.into(),
+            name: name.into(),
+            end_of_timetable,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+pub mod test_fixtures {
+    use std::{
+        fs::{self, File},
+        io::{Cursor, Read, Write},
+        ops::Deref,
+        path::{Path, PathBuf},
+    };
+
+    use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+    use crate::fetch::{ROUTE_FILEPATH, STATION_FILEPATH, TIMETABLE_PATH};
+
+    pub const STANDIN_FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/standin");
+
+    /// Directory in the temp dir unique to this test run, removed with its content when dropped,
+    /// so failing tests don't leave it behind
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> std::io::Result<Self> {
+            let path =
+                std::env::temp_dir().join(format!("rustyrails-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path)?;
+
+            Ok(Self(path))
+        }
+    }
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A cache dir holding the stand-in timetable, stations and routes where fetching puts them
+    pub fn standin_cache_dir(name: &str) -> std::io::Result<TempDir> {
+        let cache_dir = TempDir::new(name)?;
+        fs::create_dir_all(cache_dir.join("remote"))?;
+
+        for (fixture, path) in [
+            ("ns-latest.zip", TIMETABLE_PATH),
+            ("stations.json", STATION_FILEPATH),
+            ("spoorkaart.json", ROUTE_FILEPATH),
+        ] {
+            fs::copy(
+                Path::new(STANDIN_FIXTURES).join(fixture),
+                cache_dir.join(path),
+            )?;
+        }
+
+        Ok(cache_dir)
+    }
+
+    /// The stand-in timetable archive with the content of every file passed through `edit`
+    pub fn edited_standin_timetable(
+        edit: impl Fn(String) -> String,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        encoded_standin_timetable(|_, content| edit(content).into_bytes())
+    }
+
+    /// The stand-in timetable archive with every file replaced by what `encode` returns for its name and content
+    pub fn encoded_standin_timetable(
+        encode: impl Fn(&str, String) -> Vec<u8>,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let archive = File::open(Path::new(STANDIN_FIXTURES).join("ns-latest.zip"))?;
+        let mut archive = ZipArchive::new(archive)?;
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            let mut content = String::new();
+            file.read_to_string(&mut content)?;
+
+            zip.start_file(file.name(), FileOptions::default())?;
+            zip.write_all(&encode(file.name(), content))?;
+        }
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// `archive` with a file named `name` added to it
+    pub fn with_file(
+        archive: Vec<u8>,
+        name: &str,
+        content: &[u8],
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut zip = ZipWriter::new_append(Cursor::new(archive))?;
+        zip.start_file(name, FileOptions::default())?;
+        zip.write_all(content)?;
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// Writes `content` to a file named `name` in its own [TempDir], which has to be kept alive
+    /// for as long as the file is used
+    pub fn write_temp(name: &str, content: &[u8]) -> std::io::Result<(TempDir, PathBuf)> {
+        let stem = Path::new(name).file_stem().and_then(|stem| stem.to_str());
+        let dir = TempDir::new(stem.unwrap_or(name))?;
+        let path = dir.join(name);
+        fs::write(&path, content)?;
+
+        Ok((dir, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use super::{
+        test_fixtures::{edited_standin_timetable, encoded_standin_timetable, write_temp},
+        *,
+    };
+
+    #[test]
+    fn invalid_record_is_located() -> TestResult {
+        let (_dir, path) = write_temp(
+            "invalid-record.zip",
+            &edited_standin_timetable(|content| content.replace(".wd ,1011", ".wd ,10x1"))?,
+        )?;
+
+        let error = Iff::new_from_archive(&File::open(&path)?)
+            .err()
+            .ok_or("invalid record to fail strict parsing")?;
+
+        assert!(matches!(
+            &error,
+            IffError::Parse {
+                file: TIMETABLE_FILE_NAME,
+                line: 18,
+                record: Some(2),
+                ..
+            }
+        ));
+        assert!(error.to_string().contains("found `.wd ,10x1`"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_mode_skips_invalid_records() -> TestResult {
+        let (_dir, path) = write_temp(
+            "lenient.zip",
+            &edited_standin_timetable(|content| {
+                content
+                    .replace(".wd ,1011", ".wd ,10x1")
+                    .replace("#00001\r\n1111", "#00002\r\n11x1")
+            })?,
+        )?;
+
+        let iff = Iff::new_from_archive_with_options(
+            &File::open(&path)?,
+            ParseOptions {
+                mode: ParseMode::Lenient,
+                ..Default::default()
+            },
+        )?;
+
+        let skipped: Vec<_> = iff
+            .skipped()
+            .iter()
+            .map(|error| match error {
+                IffError::Parse {
+                    file, line, record, ..
+                } => Some((*file, *line, *record)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                Some((TIMETABLE_FILE_NAME, 18, Some(2))),
+                Some((FOOTNOTE_FILE_NAME, 3, Some(2))),
+            ]
+        );
+
+        let timetable = iff.into_timetable_data();
+        assert_eq!(timetable.rides.len(), 1);
+        assert_eq!(timetable.rides[0].id, "2871");
+
+        Ok(())
+    }
+
+    #[test]
+    fn ride_stop_range_outside_timetable_is_rejected() -> TestResult {
+        for (name, range) in [("past-end", "001,009"), ("zero", "000,003")] {
+            let (_dir, path) = write_temp(
+                &format!("stop-range-{name}.zip"),
+                &edited_standin_timetable(|content| {
+                    // Also stopping at Den Haag, which no other ride does
+                    content
+                        .replace("%100,02871, ,001,003,", &format!("%100,02871, ,{range},"))
+                        .replace(".wd ,1018", ".gvc ,1018")
+                })?,
+            )?;
+
+            let error = Iff::new_from_archive(&File::open(&path)?)
+                .err()
+                .ok_or("invalid stop range to fail strict parsing")?;
+            assert!(matches!(
+                &error,
+                IffError::Parse {
+                    file: TIMETABLE_FILE_NAME,
+                    record: Some(1),
+                    ..
+                }
+            ));
+            assert!(error.to_string().contains("ride stop range"), "{error}");
+
+            let iff = Iff::new_from_archive_with_options(
+                &File::open(&path)?,
+                ParseOptions {
+                    mode: ParseMode::Lenient,
+                    ..Default::default()
+                },
+            )?;
+            assert_eq!(iff.skipped().len(), 1);
+            assert_eq!(iff.locations.lookup_handle("gvc"), None);
+
+            let timetable = iff.into_timetable_data();
+            assert_eq!(timetable.rides.len(), 1);
+            assert_eq!(timetable.rides[0].id, "2870");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn footnote_shorter_than_validity_period_is_rejected() -> TestResult {
+        // Footnote 2 misses the last day of January
+        let (_dir, path) = write_temp(
+            "short-footnote.zip",
+            &edited_standin_timetable(|content| {
+                if content.contains("#00001\r\n") {
+                    content + "#00002\r\n" + &"1".repeat(30) + "\r\n"
+                } else {
+                    content
+                }
+            })?,
+        )?;
+
+        let error = Iff::new_from_archive(&File::open(&path)?)
+            .err()
+            .ok_or("short footnote to fail strict parsing")?;
+        assert!(matches!(
+            &error,
+            IffError::Parse {
+                file: FOOTNOTE_FILE_NAME,
+                line: 4,
+                record: Some(2),
+                ..
+            }
+        ));
+        assert!(error.to_string().contains("footnote day count"), "{error}");
+
+        let iff = Iff::new_from_archive_with_options(
+            &File::open(&path)?,
+            ParseOptions {
+                mode: ParseMode::Lenient,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(iff.skipped().len(), 1);
+
+        let timetable = iff.into_timetable_data();
+        assert!(timetable.validity.has_footnote(1));
+        assert!(!timetable.validity.has_footnote(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn days_missing_from_a_footnote_are_not_valid() {
+        let header = Header {
+            company_id: 100,
+            first_valid_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            last_valid_date: NaiveDate::from_ymd_opt(2024, 1, 31).unwrap(),
+            version: 1,
+            description: String::new(),
+        };
+        let validity = RideValidity::new(header, HashMap::from([(1, vec![true; 2])]));
+
+        let date = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        assert_eq!(validity.is_valid_on_day(1, date(2)), Ok(true));
+        assert_eq!(validity.is_valid_on_day(1, date(3)), Ok(false));
+    }
+
+    #[test]
+    fn invalid_platform_fails_strict_parsing() -> TestResult {
+        let (_dir, path) = write_temp(
+            "invalid-platform.zip",
+            &edited_standin_timetable(|content| content.replace("?2 ,2 ,", "?2-3-4 ,2 ,"))?,
+        )?;
+
+        let error = Iff::new_from_archive(&File::open(&path)?)
+            .err()
+            .ok_or("invalid platform to fail strict parsing")?;
+        assert!(matches!(
+            &error,
+            IffError::Parse {
+                file: TIMETABLE_FILE_NAME,
+                line: 9,
+                record: Some(1),
+                ..
+            }
+        ));
+        assert!(error.to_string().contains("found `?2-3-4 ,2 ,00001`"));
+
+        let iff = Iff::new_from_archive_with_options(
+            &File::open(&path)?,
+            ParseOptions {
+                mode: ParseMode::Lenient,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(iff.skipped().len(), 1);
+
+        let timetable = iff.into_timetable_data();
+        assert_eq!(timetable.rides.len(), 1);
+        assert_eq!(timetable.rides[0].id, "2870");
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_file_is_named() -> TestResult {
+        let archive = edited_standin_timetable(|content| content)?;
+        let mut zip = zip::ZipArchive::new(io::Cursor::new(archive))?;
+        let mut without_companies = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for index in 0..zip.len() {
+            let file = zip.by_index(index)?;
+            if file.name() != COMPANY_FILE_NAME {
+                without_companies.raw_copy_file(file)?;
+            }
+        }
+        let (_dir, path) = write_temp(
+            "missing-file.zip",
+            &without_companies.finish()?.into_inner(),
+        )?;
+
+        let error = Iff::new_from_archive(&File::open(&path)?).err();
+
+        assert!(matches!(
+            error,
+            Some(IffError::Missing {
+                file: COMPANY_FILE_NAME
+            })
+        ));
+
+        Ok(())
+    }
+
+    fn latin1(text: &str) -> Vec<u8> {
+        text.chars()
+            .map(|c| u8::try_from(c).expect("text to be representable in Latin1"))
+            .collect()
+    }
+
+    #[test]
+    fn accented_names_are_decoded_as_latin1() -> TestResult {
+        let (_dir, path) = write_temp(
+            "latin1.zip",
+            &encoded_standin_timetable(|name, content| match name {
+                COMPANY_FILE_NAME => latin1(&content.replace(
+                    "NS                            ",
+                    "Société Nationale Française   ",
+                )),
+                TIMETABLE_FILE_NAME | HEADER_FILENAME => {
+                    latin1(&content.replace("Stand-in dienstregeling", "Dienstregeling één"))
+                }
+                _ => content.into_bytes(),
+            })?,
+        )?;
+
+        let timetable = Iff::new_from_archive(&File::open(&path)?)?.into_timetable_data();
+
+        assert_eq!(timetable.companies[0].name(), "Société Nationale Française");
+        assert_eq!(
+            timetable.header.description.trim_end(),
+            "Dienstregeling één"
+        );
+        assert_eq!(timetable.rides.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn utf8_content_is_kept() -> TestResult {
+        let (_dir, path) = write_temp(
+            "utf8.zip",
+            &edited_standin_timetable(|content| {
+                content.replace(
+                    "NS                            ",
+                    "Société Nationale Française   ",
+                )
+            })?,
+        )?;
+
+        let timetable = Iff::new_from_archive(&File::open(&path)?)?.into_timetable_data();
+
+        assert_eq!(timetable.companies[0].name(), "Société Nationale Française");
+
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_parsing_matches_sequential() -> TestResult {
+        // Enough records for every chunk to intern its own codes, in an order differing per chunk
+        let mut timetable = "@100,01012024,31012024,0001,Stand-in dienstregeling\r\n".to_owned();
+        for i in 0..200 {
+            let time = if i == 150 { "1x18" } else { "1018" };
+            timetable += &format!(
+                "#{:08}\r\n%100,{:05}, ,001,003,\r\n-00001,000,999\r\n&IC ,001,003\r\n\
+                 >s{} ,1008\r\n.wd ,{time}\r\n<s{} ,1027\r\n",
+                i + 1,
+                i + 1000,
+                (i * 7) % 37,
+                200 - i,
+            );
+        }
+
+        let (_dir, path) = write_temp(
+            "parallel.zip",
+            &encoded_standin_timetable(|name, content| match name {
+                TIMETABLE_FILE_NAME => timetable.clone().into_bytes(),
+                _ => content.into_bytes(),
+            })?,
+        )?;
+
+        let parse = |threads| -> Result<_, Box<dyn std::error::Error>> {
+            let iff = Iff::new_from_archive_with_options(
+                &File::open(&path)?,
+                ParseOptions {
+                    mode: ParseMode::Lenient,
+                    threads: NonZeroUsize::new(threads).expect("threads to be non-zero"),
+                },
+            )?;
+            let skipped: Vec<_> = iff.skipped().iter().map(ToString::to_string).collect();
+            let timetable = iff.into_timetable_data();
+
+            Ok((
+                timetable.rides,
+                timetable.locations.codes().to_vec(),
+                skipped,
+            ))
+        };
+
+        let sequential = parse(1)?;
+        assert_eq!(sequential.0.len(), 199);
+        assert_eq!(sequential.2.len(), 1);
+
+        for threads in [2, 3, 8, 500] {
+            assert_eq!(parse(threads)?, sequential);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn platforms_are_parsed() {
+        let track = |number, letters: &str| Track {
+            number,
+            letters: letters.into(),
+        };
+
+        for (platform, expected) in [
+            ("5", Platform::plain(5)),
+            (" 11 ", Platform::plain(11)),
+            ("300", Platform::plain(300)),
+            ("14ab", Platform::Single(track(Some(14), "ab"))),
+            ("A", Platform::Single(track(None, "A"))),
+            (
+                "1-2",
+                Platform::Range(track(Some(1), ""), track(Some(2), "")),
+            ),
+            (
+                "1a - 1b",
+                Platform::Range(track(Some(1), "a"), track(Some(1), "b")),
+            ),
+        ] {
+            assert_eq!(platform.parse(), Ok(expected));
+        }
+
+        for (platform, kind) in [
+            ("", PlatformParseErrorKind::Empty),
+            ("  ", PlatformParseErrorKind::Empty),
+            ("1-", PlatformParseErrorKind::Empty),
+            ("70000", PlatformParseErrorKind::NumberTooLarge),
+            ("1a2", PlatformParseErrorKind::UnexpectedCharacter('2')),
+            ("1-2-3", PlatformParseErrorKind::UnexpectedCharacter('-')),
+        ] {
+            assert_eq!(
+                platform.parse::<Platform>(),
+                Err(PlatformParseError {
+                    platform: platform.to_owned(),
+                    kind,
+                })
+            );
+        }
+    }
+
+    fn track_strategy() -> impl proptest::strategy::Strategy<Value = Track> {
+        use proptest::prelude::*;
+
+        (proptest::option::of(any::<u16>()), "[a-zA-Zé]{0,3}")
+            .prop_filter("tracks have a number or letters", |(number, letters)| {
+                number.is_some() || !letters.is_empty()
+            })
+            .prop_map(|(number, letters)| Track {
+                number,
+                letters: letters.into(),
+            })
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn platform_parsing_never_panics(platform in "\\PC{0,12}") {
+            let _ = platform.parse::<Platform>();
+        }
+
+        #[test]
+        fn platforms_round_trip(
+            from in track_strategy(),
+            to in proptest::option::of(track_strategy()),
+        ) {
+            let platform = match to {
+                Some(to) => Platform::Range(from, to),
+                None => Platform::Single(from),
+            };
+
+            proptest::prop_assert_eq!(platform.to_string().parse::<Platform>(), Ok(platform));
+        }
+    }
+}
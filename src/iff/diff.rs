@@ -0,0 +1,463 @@
+//! Differences between two IFF deliveries
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use super::{Iff, LocationCache, Ride, TimetableEntry};
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TimetableDiff {
+    pub old_version: String,
+    pub new_version: String,
+    pub added_rides: Vec<String>,
+    pub removed_rides: Vec<String>,
+    pub changed_rides: Vec<RideChanges>,
+    pub added_stations: Vec<String>,
+    pub removed_stations: Vec<String>,
+    pub added_companies: Vec<String>,
+    pub removed_companies: Vec<String>,
+    pub changed_companies: Vec<CompanyChange>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RideChanges {
+    pub ride: String,
+    pub changes: Vec<RideChange>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RideChange {
+    /// The ride calls at other stations, stop level changes aren't compared
+    #[serde(rename_all = "camelCase")]
+    Route { old: Vec<String>, new: Vec<String> },
+    #[serde(rename_all = "camelCase")]
+    Times {
+        station: String,
+        old_arrival: Option<String>,
+        old_departure: Option<String>,
+        new_arrival: Option<String>,
+        new_departure: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Platform {
+        station: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// Changed running dates, limited to the dates both deliveries are valid on
+    #[serde(rename_all = "camelCase")]
+    Validity {
+        added_dates: Vec<NaiveDate>,
+        removed_dates: Vec<NaiveDate>,
+    },
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompanyChange {
+    pub id: u32,
+    pub old: String,
+    pub new: String,
+}
+
+/// A stop of a ride, resolved to comparable values
+#[derive(PartialEq)]
+struct Stop {
+    station: String,
+    arrival: Option<String>,
+    departure: Option<String>,
+    /// Platform variants of the stop joined by `/`
+    platform: Option<String>,
+}
+
+impl Stop {
+    fn new(entry: &TimetableEntry, locations: &LocationCache) -> Self {
+        let platforms: Vec<_> = entry
+            .stop_kind
+            .platforms()
+            .iter()
+            .filter_map(|info| {
+                info.departure_platform
+                    .as_ref()
+                    .or(info.arrival_platform.as_ref())
+            })
+            .map(ToString::to_string)
+            .collect();
+
+        Self {
+            station: locations
+                .get_str(&entry.code)
+                .unwrap_or_default()
+                .to_owned(),
+            arrival: entry
+                .stop_kind
+                .arrival_time()
+                .map(|time| time.display_for_gtfs().to_string()),
+            departure: entry
+                .stop_kind
+                .departure_time()
+                .map(|time| time.display_for_gtfs().to_string()),
+            platform: (!platforms.is_empty()).then(|| platforms.join("/")),
+        }
+    }
+}
+
+/// Rides grouped by id, rides sharing an id are ordered by start time
+fn rides_by_id(iff: &Iff) -> BTreeMap<String, Vec<Ride>> {
+    let mut rides: BTreeMap<String, Vec<Ride>> = BTreeMap::new();
+
+    for ride in iff
+        .timetable
+        .rides
+        .iter()
+        .flat_map(|record| record.split_on_ride_id())
+    {
+        rides.entry(ride.id.clone()).or_default().push(ride);
+    }
+
+    for group in rides.values_mut() {
+        group.sort_by_key(Ride::start_time);
+    }
+
+    rides
+}
+
+/// Labels rides sharing an id by their position, e.g. `2871` and `2871/2`
+fn ride_label(id: &str, index: usize) -> String {
+    match index {
+        0 => id.to_owned(),
+        _ => format!("{id}/{}", index + 1),
+    }
+}
+
+fn valid_dates(iff: &Iff, ride: &Ride, window: (NaiveDate, NaiveDate)) -> BTreeSet<NaiveDate> {
+    iff.validity
+        .valid_dates(ride.day_validity)
+        .into_iter()
+        .flatten()
+        .filter(|date| window.0 <= *date && *date <= window.1)
+        .collect()
+}
+
+fn compare_rides(
+    old: (&Iff, &Ride),
+    new: (&Iff, &Ride),
+    window: (NaiveDate, NaiveDate),
+) -> Vec<RideChange> {
+    let stops = |(iff, ride): (&Iff, &Ride)| -> Vec<Stop> {
+        ride.timetable
+            .iter()
+            .filter(|entry| !entry.stop_kind.is_waypoint())
+            .map(|entry| Stop::new(entry, &iff.locations))
+            .collect()
+    };
+    let (old_stops, new_stops) = (stops(old), stops(new));
+
+    let mut changes = vec![];
+
+    let stations =
+        |stops: &[Stop]| -> Vec<String> { stops.iter().map(|stop| stop.station.clone()).collect() };
+    if stations(&old_stops) != stations(&new_stops) {
+        changes.push(RideChange::Route {
+            old: stations(&old_stops),
+            new: stations(&new_stops),
+        });
+    } else {
+        for (old_stop, new_stop) in old_stops.iter().zip(&new_stops) {
+            if old_stop.arrival != new_stop.arrival || old_stop.departure != new_stop.departure {
+                changes.push(RideChange::Times {
+                    station: new_stop.station.clone(),
+                    old_arrival: old_stop.arrival.clone(),
+                    old_departure: old_stop.departure.clone(),
+                    new_arrival: new_stop.arrival.clone(),
+                    new_departure: new_stop.departure.clone(),
+                });
+            }
+            if old_stop.platform != new_stop.platform {
+                changes.push(RideChange::Platform {
+                    station: new_stop.station.clone(),
+                    old: old_stop.platform.clone(),
+                    new: new_stop.platform.clone(),
+                });
+            }
+        }
+    }
+
+    let old_dates = valid_dates(old.0, old.1, window);
+    let new_dates = valid_dates(new.0, new.1, window);
+    if old_dates != new_dates {
+        changes.push(RideChange::Validity {
+            added_dates: new_dates.difference(&old_dates).copied().collect(),
+            removed_dates: old_dates.difference(&new_dates).copied().collect(),
+        });
+    }
+
+    changes
+}
+
+impl Iff {
+    /// Lists what changed from the `old` delivery to this one
+    pub fn diff(&self, old: &Iff) -> TimetableDiff {
+        let mut diff = TimetableDiff {
+            old_version: old.header.to_string(),
+            new_version: self.header.to_string(),
+            ..TimetableDiff::default()
+        };
+
+        // Running dates are only comparable on the dates both deliveries cover
+        let window = (
+            old.header
+                .first_valid_date
+                .max(self.header.first_valid_date),
+            old.header.last_valid_date.min(self.header.last_valid_date),
+        );
+
+        let old_rides = rides_by_id(old);
+        let new_rides = rides_by_id(self);
+        let ids: BTreeSet<&String> = old_rides.keys().chain(new_rides.keys()).collect();
+
+        for id in ids {
+            let old_group = old_rides.get(id).map(Vec::as_slice).unwrap_or_default();
+            let new_group = new_rides.get(id).map(Vec::as_slice).unwrap_or_default();
+
+            for index in 0..old_group.len().max(new_group.len()) {
+                match (old_group.get(index), new_group.get(index)) {
+                    (Some(old_ride), Some(new_ride)) => {
+                        let changes = compare_rides((old, old_ride), (self, new_ride), window);
+
+                        if !changes.is_empty() {
+                            diff.changed_rides.push(RideChanges {
+                                ride: ride_label(id, index),
+                                changes,
+                            });
+                        }
+                    }
+                    (Some(_), None) => diff.removed_rides.push(ride_label(id, index)),
+                    (None, Some(_)) => diff.added_rides.push(ride_label(id, index)),
+                    (None, None) => unreachable!("index within the longest group"),
+                }
+            }
+        }
+
+        let codes = |iff: &Iff| -> BTreeSet<String> {
+            iff.locations
+                .codes()
+                .iter()
+                .map(|code| code.to_string())
+                .collect()
+        };
+        let (old_codes, new_codes) = (codes(old), codes(self));
+        diff.added_stations = new_codes.difference(&old_codes).cloned().collect();
+        diff.removed_stations = old_codes.difference(&new_codes).cloned().collect();
+
+        for company in &self.companies {
+            match old.companies.iter().find(|c| c.id == company.id) {
+                None => diff.added_companies.push(company.name.to_string()),
+                Some(previous)
+                    if previous.name != company.name || previous.code != company.code =>
+                {
+                    diff.changed_companies.push(CompanyChange {
+                        id: company.id,
+                        old: previous.name.to_string(),
+                        new: company.name.to_string(),
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed_companies = old
+            .companies
+            .iter()
+            .filter(|company| !self.companies.iter().any(|c| c.id == company.id))
+            .map(|company| company.name.to_string())
+            .collect();
+
+        diff
+    }
+}
+
+impl TimetableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_rides.is_empty()
+            && self.removed_rides.is_empty()
+            && self.changed_rides.is_empty()
+            && self.added_stations.is_empty()
+            && self.removed_stations.is_empty()
+            && self.added_companies.is_empty()
+            && self.removed_companies.is_empty()
+            && self.changed_companies.is_empty()
+    }
+}
+
+fn write_list(f: &mut std::fmt::Formatter<'_>, title: &str, items: &[String]) -> std::fmt::Result {
+    if !items.is_empty() {
+        writeln!(f, "{title} ({}): {}", items.len(), items.join(", "))?;
+    }
+
+    Ok(())
+}
+
+fn or_none(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("-")
+}
+
+impl Display for RideChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RideChange::Route { old, new } => {
+                write!(f, "route {} -> {}", old.join(" "), new.join(" "))
+            }
+            RideChange::Times {
+                station,
+                old_arrival,
+                old_departure,
+                new_arrival,
+                new_departure,
+            } => write!(
+                f,
+                "{station}: times {}/{} -> {}/{}",
+                or_none(old_arrival),
+                or_none(old_departure),
+                or_none(new_arrival),
+                or_none(new_departure)
+            ),
+            RideChange::Platform { station, old, new } => {
+                write!(
+                    f,
+                    "{station}: platform {} -> {}",
+                    or_none(old),
+                    or_none(new)
+                )
+            }
+            RideChange::Validity {
+                added_dates,
+                removed_dates,
+            } => write!(
+                f,
+                "running dates +{} -{}",
+                added_dates.len(),
+                removed_dates.len()
+            ),
+        }
+    }
+}
+
+impl Display for TimetableDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} -> {}", self.old_version, self.new_version)?;
+
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        write_list(f, "Added rides", &self.added_rides)?;
+        write_list(f, "Removed rides", &self.removed_rides)?;
+
+        if !self.changed_rides.is_empty() {
+            writeln!(f, "Changed rides ({}):", self.changed_rides.len())?;
+            for ride in &self.changed_rides {
+                writeln!(f, "  {}", ride.ride)?;
+                for change in &ride.changes {
+                    writeln!(f, "    {change}")?;
+                }
+            }
+        }
+
+        write_list(f, "Added stations", &self.added_stations)?;
+        write_list(f, "Removed stations", &self.removed_stations)?;
+        write_list(f, "Added companies", &self.added_companies)?;
+        write_list(f, "Removed companies", &self.removed_companies)?;
+
+        for change in &self.changed_companies {
+            writeln!(f, "Company {}: {} -> {}", change.id, change.old, change.new)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use crate::iff::test_fixtures::{edited_standin_timetable, write_temp};
+
+    use super::*;
+
+    #[test]
+    fn reports_changes_between_deliveries() -> TestResult {
+        let (_old_dir, old) = write_temp(
+            "diff-old.zip",
+            &edited_standin_timetable(|content| content)?,
+        )?;
+        let (_new_dir, new) = write_temp(
+            "diff-new.zip",
+            &edited_standin_timetable(|content| {
+                content
+                    .replace("0001,Stand-in", "0002,Stand-in")
+                    .replace(".wd ,1018", ".wd ,1019")
+                    .replace("?11 ,11 ,00001", "?12 ,12 ,00001")
+                    .replace("%100,02870,", "%100,02872,")
+                    .replace("<ut ,1021", "<utg ,1021")
+                    .replace(&"1".repeat(31), &format!("0{}", "1".repeat(30)))
+                    .replace(
+                        "NS                            ",
+                        "Nederlandse Spoorwegen        ",
+                    )
+            })?,
+        )?;
+
+        let old = Iff::new_from_archive(&std::fs::File::open(old)?)?;
+        let new = Iff::new_from_archive(&std::fs::File::open(new)?)?;
+        let diff = new.diff(&old);
+
+        assert_eq!(diff.added_rides, vec!["2872"]);
+        assert_eq!(diff.removed_rides, vec!["2870"]);
+        assert_eq!(diff.added_stations, vec!["utg"]);
+        assert_eq!(diff.removed_stations, Vec::<String>::new());
+        assert_eq!(
+            diff.changed_companies,
+            vec![CompanyChange {
+                id: 100,
+                old: "NS".to_owned(),
+                new: "Nederlandse Spoorwegen".to_owned(),
+            }]
+        );
+        assert_eq!(
+            diff.changed_rides,
+            vec![RideChanges {
+                ride: "2871".to_owned(),
+                changes: vec![
+                    RideChange::Platform {
+                        station: "ut".to_owned(),
+                        old: Some("11".to_owned()),
+                        new: Some("12".to_owned()),
+                    },
+                    RideChange::Times {
+                        station: "wd".to_owned(),
+                        old_arrival: Some("10:18:00".to_owned()),
+                        old_departure: Some("10:18:00".to_owned()),
+                        new_arrival: Some("10:19:00".to_owned()),
+                        new_departure: Some("10:19:00".to_owned()),
+                    },
+                    RideChange::Validity {
+                        added_dates: vec![],
+                        removed_dates: vec![NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()],
+                    },
+                ],
+            }]
+        );
+
+        assert!(new.diff(&new).is_empty());
+
+        Ok(())
+    }
+}
@@ -2,8 +2,9 @@ use chrono::NaiveDate;
 
 use serde::Serialize;
 use std::fmt::Display;
+use std::ops::{Range, RangeInclusive};
 
-use winnow::error::ParseError;
+use winnow::error::{ContextError, ParseError};
 
 use winnow::ascii::{dec_uint, line_ending, multispace0, Uint};
 use winnow::combinator::trace;
@@ -16,22 +17,173 @@ use winnow::{BStr, PResult, Parser};
 use crate::dayoffset::DayOffset;
 
 mod timetable;
-pub use timetable::parse_footnote_file;
-pub use timetable::parse_timetable_file;
+pub use timetable::parse_footnote_record;
+pub use timetable::RecordParser;
 
 mod company;
-pub use company::parse_company_file;
-pub use company::CompanyFile;
+pub use company::parse_company;
+
+mod station;
+pub use station::parse_station;
 
 pub type Stream<'s> = &'s BStr;
 
-pub fn parse_delivery_file(
-    input: Stream,
-) -> Result<Header, ParseError<Stream, winnow::error::ContextError>> {
-    parse_header.parse(input)
+pub fn parse_delivery_file(input: Stream) -> Result<Header, RecordError> {
+    parse_header
+        .parse(input)
+        .map_err(|e| RecordError::new(&e, 0, None))
+}
+
+/// A record that failed to parse, `offset` is the position in the file where parsing stopped
+#[derive(Debug)]
+pub struct RecordError {
+    pub offset: usize,
+    pub record: Option<u64>,
+    pub message: String,
+}
+
+impl RecordError {
+    /// `e` occured parsing a record starting at `offset`
+    fn new(e: &ParseError<Stream, ContextError>, offset: usize, record: Option<u64>) -> Self {
+        let message = e.inner().to_string();
+
+        Self {
+            offset: offset + e.offset(),
+            record,
+            message: if message.is_empty() {
+                "invalid record".to_owned()
+            } else {
+                message
+            },
+        }
+    }
+}
+
+/// Parses the header of `input` followed by records starting at every line for which `starts_record` holds on its first byte
+/// In lenient mode records that fail to parse are collected in `skipped`, otherwise the first failure is returned
+pub fn parse_file<'s, T>(
+    input: &'s [u8],
+    starts_record: fn(u8) -> bool,
+    parse_record: impl Parser<Stream<'s>, T, ContextError>,
+    lenient: bool,
+    skipped: &mut Vec<RecordError>,
+) -> Result<(Header, Vec<T>), RecordError> {
+    let (header, start) = parse_file_header(input)?;
+    let records = parse_records(
+        input,
+        start..input.len(),
+        starts_record,
+        parse_record,
+        lenient,
+        skipped,
+    )?;
+
+    Ok((header, records))
+}
+
+/// Parses the header of `input`, along with the offset of the first record after it
+pub fn parse_file_header(input: &[u8]) -> Result<(Header, usize), RecordError> {
+    let mut rest = BStr::new(input);
+    let header = parse_header
+        .parse_next(&mut rest)
+        .map_err(|_| RecordError {
+            offset: 0,
+            record: None,
+            message: "invalid header".to_owned(),
+        })?;
+
+    Ok((header, input.len() - rest.len()))
+}
+
+/// Parses the records in `range` of `input` like [`parse_file`], `range` has to start at a record
+pub fn parse_records<'s, T>(
+    input: &'s [u8],
+    range: Range<usize>,
+    starts_record: fn(u8) -> bool,
+    mut parse_record: impl Parser<Stream<'s>, T, ContextError>,
+    lenient: bool,
+    skipped: &mut Vec<RecordError>,
+) -> Result<Vec<T>, RecordError> {
+    let mut records = vec![];
+
+    for (offset, record) in split_records(&input[..range.end], range.start, starts_record) {
+        match parse_record.parse(BStr::new(record)) {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                let error = RecordError::new(&e, offset, record_id(record));
+                if !lenient {
+                    return Err(error);
+                }
+
+                skipped.push(error);
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+/// Divides `range` of `input` into at most `count` ranges of about equal length, which all start at a record
+pub fn split_chunks(
+    input: &[u8],
+    range: Range<usize>,
+    starts_record: fn(u8) -> bool,
+    count: usize,
+) -> Vec<Range<usize>> {
+    let chunk_len = range.len().div_ceil(count.max(1)).max(1);
+    let mut chunks = vec![];
+    let mut start = range.start;
+
+    while start < range.end {
+        // Records start after a newline, so the search begins at the byte before the earliest end
+        let search = (start + chunk_len).min(range.end) - 1;
+        let end = input[search..range.end]
+            .windows(2)
+            .position(|pair| pair[0] == b'\n' && starts_record(pair[1]))
+            .map_or(range.end, |newline| search + newline + 1);
+
+        chunks.push(start..end);
+        start = end;
+    }
+
+    chunks
+}
+
+/// Splits `input` from `start` on into records, along with their offset
+/// A record runs up to the next line for which `starts_record` holds on its first byte
+fn split_records(
+    input: &[u8],
+    mut start: usize,
+    starts_record: fn(u8) -> bool,
+) -> impl Iterator<Item = (usize, &[u8])> {
+    std::iter::from_fn(move || {
+        if start >= input.len() {
+            return None;
+        }
+
+        let end = input[start..]
+            .windows(2)
+            .position(|pair| pair[0] == b'\n' && starts_record(pair[1]))
+            .map_or(input.len(), |newline| start + newline + 1);
+
+        let record = (start, &input[start..end]);
+        start = end;
+
+        Some(record)
+    })
+}
+
+/// The leading number of a record, after the `#` of timetable and footnote records
+fn record_id(record: &[u8]) -> Option<u64> {
+    let digits = record.strip_prefix(b"#").unwrap_or(record);
+    let len = digits.iter().take_while(|b| b.is_ascii_digit()).count();
+
+    std::str::from_utf8(&digits[..len]).ok()?.parse().ok()
 }
 
-use super::{Header, Leg, LegKind, Record, Ride, StopKind, TimetableEntry};
+use super::{
+    Header, Leg, LegKind, LocationCodeHandle, Record, Ride, RideId, StopKind, TimetableEntry,
+};
 
 /// Length of dates as they appear in the iff file
 const DATE_FORMAT_LEN: usize = "DDMMYYYY".len();
@@ -40,6 +192,7 @@ const DATE_FORMAT: &str = "%d%m%Y";
 
 const IFF_NEWLINE: &str = "\r\n";
 
+#[allow(dead_code)]
 pub struct InvalidEncodingError {}
 
 impl Display for InvalidEncodingError {
@@ -52,6 +205,7 @@ impl Display for InvalidEncodingError {
 //     digit1.recognize().parse_next(input)
 // }
 
+#[allow(dead_code)]
 fn seperator(input: &mut Stream) -> PResult<()> {
     (multispace0, ',').void().parse_next(input)
 }
@@ -113,10 +267,10 @@ fn parse_header(input: &mut Stream) -> PResult<Header> {
 }
 
 fn parse_date(input: &mut Stream) -> PResult<NaiveDate> {
+    // Fixed width fields can split multibyte characters in decoded Latin1 input, so they're checked
     take(DATE_FORMAT_LEN)
-        .try_map(|s| {
-            NaiveDate::parse_from_str(unsafe { std::str::from_utf8_unchecked(s) }, DATE_FORMAT)
-        })
+        .try_map(std::str::from_utf8)
+        .try_map(|s| NaiveDate::parse_from_str(s, DATE_FORMAT))
         .parse_next(input)
 }
 
@@ -136,7 +290,7 @@ mod test_parse_date {
 fn parse_time(input: &mut Stream) -> PResult<DayOffset> {
     trace(
         "time",
-        take(4u8).try_map(|s: &[u8]| unsafe { std::str::from_utf8_unchecked(s).parse() }),
+        take(4u8).try_map(std::str::from_utf8).try_map(str::parse),
     )
     .parse_next(input)
 }
@@ -164,7 +318,7 @@ mod header_tests {
     }
 }
 
-fn leg_for_stop(entry: &TimetableEntry) -> Leg {
+fn leg_for_stop(index: usize, entry: &TimetableEntry) -> Leg {
     let (arrival, departure) = match entry.stop_kind {
         StopKind::Departure(_, scheduled_departure) => {
             (scheduled_departure.offset_by(-1), scheduled_departure)
@@ -184,6 +338,8 @@ fn leg_for_stop(entry: &TimetableEntry) -> Leg {
     Leg {
         start: arrival,
         end: departure,
+        from_stop: index,
+        to_stop: index,
         kind: LegKind::Stationary(entry.code, entry.stop_kind.clone()),
     }
 }
@@ -195,39 +351,47 @@ pub fn generate_legs(entries: &[TimetableEntry]) -> Vec<Leg> {
     let mut waypoints = vec![];
     let first_stop = entries.first().expect("timetable to have an entry");
     let mut previous_stop = first_stop;
+    let mut previous_index = 0;
 
-    out.push(leg_for_stop(first_stop));
+    out.push(leg_for_stop(0, first_stop));
 
-    entries.iter().skip(1).for_each(|entry| {
-        // Collect non-stopping points into waypoints. These are needed later on to find the right Links between Stations
-        if entry.stop_kind.is_waypoint() {
-            waypoints.push(entry);
-            return;
-        }
+    entries
+        .iter()
+        .enumerate()
+        .skip(1)
+        .for_each(|(index, entry)| {
+            // Collect non-stopping points into waypoints. These are needed later on to find the right Links between Stations
+            if entry.stop_kind.is_waypoint() {
+                waypoints.push(entry);
+                return;
+            }
 
-        out.push(Leg {
-            start: *previous_stop
-                .stop_kind
-                .departure_time()
-                .expect("leg start to have a departure time"),
-            end: *entry
-                .stop_kind
-                .arrival_time()
-                .expect("leg end to have an arrival time"),
-            kind: LegKind::Moving {
-                from: previous_stop.code,
-                to: entry.code,
-                waypoints: waypoints.iter().map(|c| c.code).collect(),
-            },
+            out.push(Leg {
+                start: *previous_stop
+                    .stop_kind
+                    .departure_time()
+                    .expect("leg start to have a departure time"),
+                end: *entry
+                    .stop_kind
+                    .arrival_time()
+                    .expect("leg end to have an arrival time"),
+                from_stop: previous_index,
+                to_stop: index,
+                kind: LegKind::Moving {
+                    from: previous_stop.code,
+                    to: entry.code,
+                    waypoints: waypoints.iter().map(|c| c.code).collect(),
+                },
+            });
+
+            previous_stop = entry;
+            previous_index = index;
+
+            waypoints.clear();
+
+            out.push(leg_for_stop(index, entry));
         });
 
-        previous_stop = entry;
-
-        waypoints.clear();
-
-        out.push(leg_for_stop(entry));
-    });
-
     out
 }
 
@@ -269,7 +433,7 @@ fn timetable_normalize_ends(entries: &mut [TimetableEntry]) {
         .stop_kind
         .departure_time()
         .expect("stop have departure time");
-    let departure_platform = entries.first().unwrap().stop_kind.platform_info().cloned();
+    let departure_platform = entries.first().unwrap().stop_kind.platforms().to_vec();
 
     entries.first_mut().unwrap().stop_kind =
         StopKind::Departure(departure_platform, *departure_time);
@@ -281,7 +445,7 @@ fn timetable_normalize_ends(entries: &mut [TimetableEntry]) {
         .stop_kind
         .arrival_time()
         .expect("stop to have arrival time");
-    let arrival_platform = entries.last().unwrap().stop_kind.platform_info().cloned();
+    let arrival_platform = entries.last().unwrap().stop_kind.platforms().to_vec();
 
     entries.last_mut().unwrap().stop_kind = StopKind::Arrival(arrival_platform, *arrival_time);
 }
@@ -298,6 +462,17 @@ impl Ride {
     pub fn generate_legs(&self) -> Vec<Leg> {
         generate_legs(&self.timetable)
     }
+
+    /// This ride between the stops at `first` and `last`, which become its departure and arrival
+    pub fn section(&self, first: usize, last: usize) -> Ride {
+        let mut timetable = self.timetable[first..=last].to_owned();
+        timetable_normalize_ends(&mut timetable);
+
+        Ride {
+            timetable,
+            ..self.clone()
+        }
+    }
 }
 
 impl Record {
@@ -309,32 +484,47 @@ impl Record {
         timetable_end(self.timetable.as_slice())
     }
 
-    pub fn split_on_ride_id(&self) -> impl Iterator<Item = Ride> + '_ {
-        let is_sole_transit_type = self.transit_types.len() == 1;
-
-        if !is_sole_transit_type && self.ride_id.len() > 1 {
-            println!("{:#?}", self);
-            todo!("Support mixed transit_types and rideids")
+    /// Replaces every location handle with its entry in `handles`, as returned by [`LocationCache::merge`]
+    pub fn remap(&mut self, handles: &[LocationCodeHandle]) {
+        for entry in &mut self.timetable {
+            entry.code = handles[entry.code.inner as usize];
         }
+    }
+
+    /// The transit mode and timetable entries of every ride id, `None` if a ride's stops aren't in the timetable
+    fn ride_sections(&self) -> Option<Vec<(&RideId, &TransitMode, RangeInclusive<usize>)>> {
+        let stop_index = |number: u32| {
+            let nth = number.checked_sub(1)?;
+            timetable_stop_index(&self.timetable, nth as usize)
+        };
 
         self.ride_id
             .iter()
-            .enumerate()
-            .map(move |(index, ride_id)| {
-                let transit_type = if is_sole_transit_type {
-                    self.transit_types.first().unwrap()
-                } else {
-                    self.transit_types.get(index).unwrap()
+            .map(|ride_id| {
+                let transit_type = match self.transit_types.as_slice() {
+                    [sole] => sole,
+                    types => types.iter().find(|mode| {
+                        (mode.first_stop..=mode.last_stop).contains(&ride_id.first_stop)
+                    })?,
                 };
 
-                let first_stop_idx =
-                    timetable_stop_index(&self.timetable, ride_id.first_stop as usize - 1)
-                        .expect("to find first stop");
-                let last_stop_idx =
-                    timetable_stop_index(&self.timetable, ride_id.last_stop as usize - 1)
-                        .expect("to find last stop");
+                let first = stop_index(ride_id.first_stop)?;
+                let last = stop_index(ride_id.last_stop)?;
+
+                (first < last).then_some((ride_id, transit_type, first..=last))
+            })
+            .collect()
+    }
 
-                let mut timetable = self.timetable[first_stop_idx..=last_stop_idx].to_owned();
+    /// The rides of this record, records rejected by [`Record::ride_sections`] have none
+    pub fn split_on_ride_id(&self) -> impl Iterator<Item = Ride> + '_ {
+        let sections = self.ride_sections().unwrap_or_default();
+
+        sections
+            .into_iter()
+            .enumerate()
+            .map(move |(index, (ride_id, transit_type, stops))| {
+                let mut timetable = self.timetable[stops].to_owned();
 
                 timetable_normalize_ends(&mut timetable);
 
@@ -371,7 +561,7 @@ pub struct TransitMode {
     last_stop: u32,
 }
 
-fn till_comma<'b, 's>(input: &mut Stream<'s>) -> PResult<Stream<'s>> {
+fn till_comma<'s>(input: &mut Stream<'s>) -> PResult<Stream<'s>> {
     take_till(0.., |c| c == b',')
         .parse_next(input)
         .map(|s| s.into())
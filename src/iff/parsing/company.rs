@@ -1,13 +1,8 @@
-use winnow::{combinator::repeat, error::ParseError, PResult, Parser};
+use winnow::{PResult, Parser};
 
-use crate::iff::{Company, Header};
+use crate::iff::Company;
 
-use super::{dec_uint_leading, parse_header, parse_time, till_comma, Stream, IFF_NEWLINE};
-
-pub struct CompanyFile {
-    pub header: Header,
-    pub companies: Vec<Company>,
-}
+use super::{dec_uint_leading, parse_time, till_comma, Stream, IFF_NEWLINE};
 
 // 100,ns        ,NS                            ,0400
 pub fn parse_company(input: &mut Stream<'_>) -> PResult<Company> {
@@ -29,14 +24,3 @@ pub fn parse_company(input: &mut Stream<'_>) -> PResult<Company> {
         })
         .parse_next(input)
 }
-
-pub fn parse_company_file(
-    input: Stream,
-) -> Result<CompanyFile, ParseError<Stream, winnow::error::ContextError>> {
-    (parse_header, repeat(0.., parse_company))
-        .map(|seq| CompanyFile {
-            header: seq.0,
-            companies: seq.1,
-        })
-        .parse(input)
-}
@@ -0,0 +1,76 @@
+use winnow::{PResult, Parser};
+
+use crate::iff::IffStation;
+
+use super::{till_comma, untill_newline, Stream, IFF_NEWLINE};
+
+fn trimmed(input: Stream<'_>) -> Box<str> {
+    String::from_utf8_lossy(input).trim().into()
+}
+
+fn coordinate(input: &mut Stream<'_>) -> PResult<u32> {
+    till_comma
+        .try_map(|s: Stream<'_>| std::str::from_utf8(s))
+        .try_map(|s| s.trim().parse())
+        .parse_next(input)
+}
+
+// 1,ut     ,04,05,NL  ,0000,00,136553,455884,Utrecht Centraal
+pub fn parse_station(input: &mut Stream<'_>) -> PResult<IffStation> {
+    (
+        till_comma,
+        ',',
+        till_comma.map(trimmed),
+        ',',
+        till_comma,
+        ',',
+        till_comma,
+        ',',
+        till_comma.map(trimmed),
+        ',',
+        till_comma,
+        ',',
+        till_comma,
+        ',',
+        coordinate,
+        ',',
+        coordinate,
+        ',',
+        untill_newline.map(|name| trimmed(name.into())),
+        IFF_NEWLINE,
+    )
+        .map(|seq| IffStation {
+            code: seq.2,
+            name: seq.18,
+            country: seq.8,
+            position: (seq.14 != 0 || seq.16 != 0).then_some((seq.14, seq.16)),
+        })
+        .parse_next(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn stations_are_parsed() {
+        let mut input: Stream =
+            "1,ut     ,04,05,NL  ,0000,00,136553,455884,Utrecht Centraal              \r\n".into();
+        assert_eq!(
+            parse_station(&mut input),
+            Ok(IffStation {
+                code: "ut".into(),
+                name: "Utrecht Centraal".into(),
+                country: "NL".into(),
+                position: Some((136553, 455884)),
+            })
+        );
+
+        // Foreign stations are mostly listed without coordinates
+        let mut input: Stream =
+            "0,bru    ,00,00,B   ,0000,00,000000,000000,Brussel-Zuid\r\n".into();
+        assert_eq!(parse_station(&mut input).map(|s| s.position), Ok(None));
+    }
+}
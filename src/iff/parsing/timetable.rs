@@ -1,20 +1,19 @@
-use std::collections::HashMap;
-
 use winnow::{
     ascii::{alphanumeric1, line_ending, multispace0, space0},
-    combinator::{dispatch, fail, opt, preceded, repeat, terminated, trace},
+    combinator::{dispatch, eof, fail, opt, preceded, repeat, terminated, trace},
+    error::{ContextError, StrContext},
     token::{one_of, take_till},
     PResult, Parser,
 };
 
 use crate::iff::{
-    DayValidityFootnote, Footnote, Header, LocationCache, Platform, PlatformInfo, Record, RideId,
-    RideValidity, StopKind, TimeTable, TimetableEntryRaw,
+    DayValidityFootnote, Footnote, LocationCache, Platform, PlatformInfo, Record, RideId, StopKind,
+    TimetableEntryRaw,
 };
 
 use super::{
-    dec_uint_leading, empty_str_to_none, parse_header, parse_time, parse_transit_mode,
-    untill_newline, Stream, TransitMode, IFF_NEWLINE,
+    dec_uint_leading, empty_str_to_none, parse_time, parse_transit_mode, untill_newline, Stream,
+    TransitMode, IFF_NEWLINE,
 };
 
 fn parse_single_day(input: &mut Stream) -> PResult<bool> {
@@ -23,41 +22,73 @@ fn parse_single_day(input: &mut Stream) -> PResult<bool> {
         .parse_next(input)
 }
 
-struct RecordParser<'a> {
+/// Parses a single timetable record, interning its location codes into `locations`
+pub struct RecordParser<'a> {
     locations: &'a mut LocationCache,
 }
 
+impl<'a> RecordParser<'a> {
+    pub fn new(locations: &'a mut LocationCache) -> Self {
+        Self { locations }
+    }
+}
+
 impl<'a, 'b> Parser<Stream<'a>, Record, winnow::error::ContextError> for RecordParser<'b> {
     fn parse_next(&mut self, input: &mut &'a winnow::BStr) -> PResult<Record> {
-        preceded(
-            '#',
-            (
-                dec_uint_leading,
-                line_ending,
-                repeat(0.., parse_ride_id),
-                parse_day_footnote,
-                take_till(0.., '&').void(),
-                parse_transit_mode,
-                take_till(0.., '>').void(),
-                repeat(1.., any_entry),
+        // The whole record is parsed before the ride stop ranges are checked against its stops
+        terminated(
+            preceded(
+                '#',
+                (
+                    dec_uint_leading,
+                    line_ending,
+                    repeat(0.., parse_ride_id),
+                    parse_day_footnote,
+                    take_till(0.., '&').void(),
+                    parse_transit_mode,
+                    take_till(0.., '>').void(),
+                    repeat(1.., any_entry),
+                ),
             ),
+            eof,
         )
         .map(
             |seq: (_, _, _, _, _, TransitMode, _, Vec<TimetableEntryRaw>)| {
-                Record {
+                let (codes, timetable): (Vec<_>, _) = seq
+                    .7
+                    .into_iter()
+                    .map(TimetableEntryRaw::into_uninterned)
+                    .unzip();
+
+                let record = Record {
                     id: seq.0,
-                    timetable: seq.7.iter().map(|s| s.to_proper(self.locations)).collect(),
+                    timetable,
                     ride_id: seq.2,
                     day_validity_footnote: seq.3.footnote, // NONSTANDARD assuming date footnotes span the entire length of a record
                     transit_types: vec![seq.5],
-                }
+                };
+
+                (record, codes)
             },
         )
+        .verify(|(record, _): &(Record, _)| record.ride_sections().is_some())
+        .context(StrContext::Label("ride stop range"))
+        // Only records that passed every check get their location codes interned
+        .map(|(mut record, codes)| {
+            for (entry, code) in record.timetable.iter_mut().zip(codes) {
+                entry.code = self.locations.get_handle(code);
+            }
+
+            record
+        })
         .parse_next(input)
     }
 }
 
-fn parse_footnote_record(input: &mut Stream) -> PResult<DayValidityFootnote> {
+/// Parses a footnote record, which has to have a flag for each of the `days` its file is valid
+pub fn parse_footnote_record<'s>(
+    days: usize,
+) -> impl Parser<Stream<'s>, DayValidityFootnote, ContextError> {
     (
         '#',
         dec_uint_leading,
@@ -69,59 +100,20 @@ fn parse_footnote_record(input: &mut Stream) -> PResult<DayValidityFootnote> {
             id: seq.1,
             validity: seq.3,
         })
-        .parse_next(input)
-}
-
-pub fn parse_footnote_file(input: &mut Stream) -> PResult<RideValidity> {
-    (parse_header, repeat(0.., parse_footnote_record))
-        .map(|seq: (Header, Vec<DayValidityFootnote>)| RideValidity {
-            header: seq.0,
-            validities: seq
-                .1
-                .into_iter()
-                .map(|footnote| (footnote.id, footnote.validity))
-                .collect::<HashMap<_, _>>(),
-        })
-        .parse_next(input)
-}
-
-pub fn parse_timetable_file(input: &mut Stream<'_>) -> PResult<(TimeTable, LocationCache)> {
-    (parse_header, parse_records).parse_next(input).map(|seq| {
-        (
-            TimeTable {
-                header: seq.0,
-                rides: seq.1 .0,
-            },
-            seq.1 .1,
-        )
-    })
-}
-
-fn parse_records(input: &mut Stream) -> PResult<(Vec<Record>, LocationCache)> {
-    let estimate_record_count = input.iter().filter(|b| b == &&b'#').count();
-    let mut accumulator = Vec::with_capacity(estimate_record_count);
-
-    const ESTIMATE_UNIQUE_LOCATION_CODES: usize = 1000;
-    let mut location_codes = LocationCache::with_capacity(ESTIMATE_UNIQUE_LOCATION_CODES);
-
-    let mut record_parser = RecordParser {
-        locations: &mut location_codes,
-    };
-
-    while !input.is_empty() {
-        let record = record_parser.parse_next(input)?;
-        accumulator.push(record)
-    }
-
-    Ok((accumulator, location_codes))
+        .verify(move |footnote: &DayValidityFootnote| footnote.validity.len() == days)
+        .context(StrContext::Label("footnote day count"))
 }
 
+/// Blank platforms are `None`, platforms that don't parse fail the whole record
 fn parse_platform_opt(input: &mut Stream) -> PResult<Option<Platform>> {
     // trace(
     // "platform",
     take_till(1.., ',')
         .map(|s| unsafe { std::str::from_utf8_unchecked(s) })
-        .map(|s| s.parse::<Platform>().ok())
+        .try_map(|s| match s.trim() {
+            "" => Ok(None),
+            platform => platform.parse::<Platform>().map(Some),
+        })
         .parse_next(input)
 }
 
@@ -156,7 +148,7 @@ fn parse_departure<'s>(input: &mut Stream<'s>) -> PResult<TimetableEntryRaw<'s>>
         ',',
         parse_time,
         line_ending,
-        opt(parse_platform_info),
+        repeat(0.., parse_platform_info),
     )
         .parse_next(input)
         .map(|seq| TimetableEntryRaw {
@@ -196,7 +188,7 @@ fn parse_stop_short<'s>(input: &mut Stream<'s>) -> PResult<TimetableEntryRaw<'s>
         ',',
         parse_time,
         line_ending,
-        opt(parse_platform_info),
+        repeat(0.., parse_platform_info),
     )
         .parse_next(input)
         .map(|seq| TimetableEntryRaw {
@@ -213,7 +205,7 @@ fn parse_stop_long<'s>(input: &mut Stream<'s>) -> PResult<TimetableEntryRaw<'s>>
         ',',
         parse_time,
         line_ending,
-        opt(parse_platform_info),
+        repeat(0.., parse_platform_info),
     )
         .parse_next(input)
         .map(|seq| TimetableEntryRaw {
@@ -228,7 +220,7 @@ fn parse_arrival<'s>(input: &mut Stream<'s>) -> PResult<TimetableEntryRaw<'s>> {
         ',',
         parse_time,
         opt(line_ending),
-        opt(parse_platform_info),
+        repeat(0.., parse_platform_info),
     )
         .parse_next(input)
         .map(|seq| TimetableEntryRaw {
@@ -246,8 +238,8 @@ mod test_platform_parse {
     fn test_platform_parse() {
         let input = "?11 ,15 ,00003".into();
         let expected = PlatformInfo {
-            arrival_platform: Some(Platform::Regular(11)),
-            departure_platform: Some(Platform::Regular(15)),
+            arrival_platform: Some(Platform::plain(11)),
+            departure_platform: Some(Platform::plain(15)),
             footnote: 3,
         };
 
@@ -327,6 +319,8 @@ fn parse_day_footnote(input: &mut Stream) -> PResult<Footnote> {
 }
 
 #[cfg(test)]
+// testresult panics on conversion into its error, so the early returns count as unreachable
+#[allow(unreachable_code)]
 mod test_record {
     use pretty_assertions::assert_eq;
 
@@ -338,6 +332,7 @@ mod test_record {
         iff::{
             parsing::{dec_uint_leading, timetable::RecordParser, TransitMode},
             LocationCache, Platform, PlatformInfo, Record, Ride, RideId, StopKind, TimetableEntry,
+            Track,
         },
     };
 
@@ -394,7 +389,7 @@ mod test_record {
                     entry!(
                         code("rtd"),
                         StopKind::Departure(
-                            Some(platform!(13, 3)),
+                            vec![platform!(13, 3)],
                             DayOffset::from_hour_minute(18, 50)
                         )
                     ),
@@ -402,11 +397,11 @@ mod test_record {
                     TimetableEntry {
                         code: code("rta"),
                         stop_kind: StopKind::StopShort(
-                            Some(PlatformInfo {
-                                arrival_platform: Some(Platform::Regular(1)),
-                                departure_platform: Some(Platform::Regular(1)),
+                            vec![PlatformInfo {
+                                arrival_platform: Some(Platform::plain(1)),
+                                departure_platform: Some(Platform::plain(1)),
                                 footnote: 3
-                            }),
+                            }],
                             DayOffset::from_hour_minute(18, 58)
                         )
                     },
@@ -415,7 +410,7 @@ mod test_record {
                     entry!(
                         code("gd"),
                         StopKind::StopLong(
-                            Some(platform!(3, 3)),
+                            vec![platform!(3, 3)],
                             DayOffset::from_hour_minute(19, 8),
                             DayOffset::from_hour_minute(19, 9)
                         )
@@ -428,7 +423,7 @@ mod test_record {
                     entry!(
                         code("ut"),
                         StopKind::Arrival(
-                            Some(platform!(11, 3)),
+                            vec![platform!(11, 3)],
                             DayOffset::from_hour_minute(19, 28)
                         )
                     ),
@@ -449,7 +444,7 @@ mod test_record {
                     entry!(
                         code("ut"),
                         StopKind::Departure(
-                            Some(platform!(11, 3)),
+                            vec![platform!(11, 3)],
                             DayOffset::from_hour_minute(19, 36)
                         )
                     ),
@@ -459,7 +454,7 @@ mod test_record {
                     entry!(
                         code("amf"),
                         StopKind::Arrival(
-                            Some(platform!(2, 3)),
+                            vec![platform!(2, 3)],
                             DayOffset::from_hour_minute(19, 50)
                         )
                     ),
@@ -528,11 +523,11 @@ mod test_record {
             &TimetableEntry {
                 code: code("rtd"),
                 stop_kind: StopKind::Departure(
-                    Some(PlatformInfo {
-                        departure_platform: Some(Platform::Regular(13)),
-                        arrival_platform: Some(Platform::Regular(13)),
+                    vec![PlatformInfo {
+                        departure_platform: Some(Platform::plain(13)),
+                        arrival_platform: Some(Platform::plain(13)),
                         footnote: 3
-                    }),
+                    }],
                     DayOffset::from_hour_minute(18, 50)
                 )
             }
@@ -585,11 +580,11 @@ mod test_record {
                 entry!(
                     code("asd"),
                     StopKind::Departure(
-                        Some(PlatformInfo {
+                        vec![PlatformInfo {
                             arrival_platform: Some(Platform::plain(14)),
                             departure_platform: Some(Platform::plain(14)),
                             footnote: 81
-                        }),
+                        }],
                         DayOffset::from_hour_minute(7, 15)
                     )
                 ),
@@ -598,11 +593,17 @@ mod test_record {
                 entry!(
                     code("shl"),
                     StopKind::StopLong(
-                        Some(PlatformInfo {
-                            arrival_platform: Some(Platform::Range(1, 2)),
-                            departure_platform: Some(Platform::Range(1, 2)),
+                        vec![PlatformInfo {
+                            arrival_platform: Some(Platform::Range(
+                                Track::numbered(1),
+                                Track::numbered(2)
+                            )),
+                            departure_platform: Some(Platform::Range(
+                                Track::numbered(1),
+                                Track::numbered(2)
+                            )),
                             footnote: 81
-                        }),
+                        }],
                         DayOffset::from_hour_minute(7, 30),
                         DayOffset::from_hour_minute(7, 32)
                     )
@@ -611,7 +612,7 @@ mod test_record {
                 entry!(
                     code("rtd"),
                     StopKind::StopLong(
-                        Some(PlatformInfo::plain(2, 81)),
+                        vec![PlatformInfo::plain(2, 81)],
                         DayOffset::from_hour_minute(7, 54),
                         DayOffset::from_hour_minute(7, 58)
                     )
@@ -625,7 +626,7 @@ mod test_record {
                 entry!(
                     code("atw"),
                     StopKind::StopLong(
-                        None,
+                        vec![],
                         DayOffset::from_hour_minute(8, 30),
                         DayOffset::from_hour_minute(8, 33)
                     )
@@ -645,7 +646,7 @@ mod test_record {
                 entry!(
                     code("brusz"),
                     StopKind::StopLong(
-                        None,
+                        vec![],
                         DayOffset::from_hour_minute(9, 8),
                         DayOffset::from_hour_minute(9, 20)
                     )
@@ -653,14 +654,14 @@ mod test_record {
                 entry!(
                     code("acdg"),
                     StopKind::StopLong(
-                        Some(PlatformInfo::plain(1, 81)),
+                        vec![PlatformInfo::plain(1, 81)],
                         DayOffset::from_hour_minute(10, 33),
                         DayOffset::from_hour_minute(10, 38)
                     )
                 ),
                 entry!(
                     code("marne"),
-                    StopKind::Arrival(None, DayOffset::from_hour_minute(10, 48))
+                    StopKind::Arrival(vec![], DayOffset::from_hour_minute(10, 48))
                 ),
             ],
             transit_types: vec![TransitMode {
@@ -1,25 +1,39 @@
 mod access;
 mod api;
 mod cache;
+mod cache_command;
 mod cli;
 mod contextual_serializer;
 mod dayoffset;
 // mod experiment;
+mod export;
 mod fetch;
+mod geojson;
+mod gtfs;
 mod iff;
 mod ndovloket_api;
 mod print;
+mod realtime;
+mod standin;
 mod time;
+mod verify;
 
-use std::{path::PathBuf, time::Instant};
+use std::{
+    fs::File,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
-use anyhow::{Context, Ok};
+use anyhow::{anyhow, Context, Ok};
 
-use api::datarepo::{self, DataRepo};
+use api::datarepo::{self, TimetableSource};
+use cache::RetentionPolicy;
 use figment::{
     providers::{Env, Format, Toml},
     Figment,
 };
+use realtime::RealtimeSource;
 use serde::Deserialize;
 
 #[derive(Deserialize)]
@@ -29,8 +43,70 @@ pub struct AppConfig {
     pub allow_cache_overwrite: bool,
     pub cors_domain: String,
     pub bind_addr: String,
+    #[serde(default)]
+    pub timetable: TimetableSource,
+    pub realtime: Option<RealtimeSource>,
+    #[serde(default)]
+    pub route_finding: RouteFindingConfig,
+    #[serde(default)]
+    pub upstream: UpstreamConfig,
+    #[serde(default)]
+    pub cache_retention: RetentionPolicy,
+    #[serde(default)]
+    pub verify: verify::VerifyConfig,
+}
+
+/// Where remote data is fetched from, point these at a `standin` server to work offline
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct UpstreamConfig {
+    /// Base URL of the NS API, paths are appended to it
+    pub ns_api_host: String,
+    /// URL of the zipped IFF timetable
+    pub timetable_url: String,
+    /// Seconds after which the cached NS stations and route files are downloaded again
+    pub ns_data_max_age: u64,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            ns_api_host: ns_api::API_HOST.to_owned(),
+            timetable_url: ndovloket_api::TIMETABLE_URL.to_owned(),
+            ns_data_max_age: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RouteFindingConfig {
+    /// Seconds an NS route planner response is reused for identical requests
+    pub cache_ttl: u64,
+    pub cache_capacity: usize,
+    /// Route finding requests a single client may make per minute
+    pub requests_per_minute: u32,
+    /// Seconds after which a request to NS is abandoned
+    pub upstream_timeout: u64,
+    pub upstream_retries: u32,
+    /// Reverse proxies whose `X-Forwarded-For` and `X-Real-IP` headers identify the client
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
+impl Default for RouteFindingConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl: 120,
+            cache_capacity: 1000,
+            requests_per_minute: 30,
+            upstream_timeout: 10,
+            upstream_retries: 2,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+#[allow(dead_code)]
 fn wait_user_input() {
     println!("Waiting for user input");
     let mut dummy = String::new();
@@ -38,7 +114,7 @@ fn wait_user_input() {
 }
 
 fn main() -> Result<(), anyhow::Error> {
-    println!("Main");
+    eprintln!("Main");
     let config: Figment = Figment::new()
         .merge(Toml::file("./config/project.toml"))
         .merge(Toml::file("./config/project.secret.toml"))
@@ -48,7 +124,7 @@ fn main() -> Result<(), anyhow::Error> {
     let config: AppConfig = config.extract().context("Parsing config files")?;
     let cli_options = cli::get_cli_args();
 
-    println!("Config was read");
+    eprintln!("Config was read");
 
     // wait_user_input();
 
@@ -56,17 +132,30 @@ fn main() -> Result<(), anyhow::Error> {
     // config.cache_dir = cli_options.cache_dir.into();
 
     match cli_options.command {
-        cli::SubCommand::Fetch => fetch::fetch(&config.cache_dir, config.ns_api_key.as_deref()),
+        cli::SubCommand::Fetch => fetch::fetch(
+            &config.cache_dir,
+            config.ns_api_key.as_deref(),
+            &config.upstream,
+            &config.cache_retention,
+            &config.timetable,
+        ),
         cli::SubCommand::Serve { autofetch } => api::serve(&config, autofetch),
-        cli::SubCommand::Verify => verify(&config),
+        cli::SubCommand::Verify(args) => verify::verify(&config, args),
         cli::SubCommand::Print(args) => print::print(&config, args),
+        cli::SubCommand::Export(args) => export::export(&config, args),
+        cli::SubCommand::Cache(args) => cache_command::cache(&config, args),
+        cli::SubCommand::Diff { old, new, json } => diff(&old, &new, json),
         cli::SubCommand::Bench => benchparser(&config),
+        cli::SubCommand::Standin {
+            fixtures,
+            bind_addr,
+        } => standin::serve(&fixtures, &bind_addr),
     }
 }
 
 fn benchparser(config: &AppConfig) -> Result<(), anyhow::Error> {
     let start = Instant::now();
-    let _ = datarepo::DataRepo::new(&config.cache_dir);
+    let _ = datarepo::DataRepo::new(&config.cache_dir, &config.timetable)?;
     let end = Instant::now();
 
     println!(
@@ -74,11 +163,31 @@ fn benchparser(config: &AppConfig) -> Result<(), anyhow::Error> {
         end.duration_since(start).as_millis()
     );
 
+    let start = Instant::now();
+    let _ = datarepo::DataRepo::load(&config.cache_dir, &config.timetable)?;
+    let end = Instant::now();
+
+    println!(
+        "Loading datarepo took {}ms",
+        end.duration_since(start).as_millis()
+    );
+
     Ok(())
 }
 
-fn verify(config: &AppConfig) -> Result<(), anyhow::Error> {
-    DataRepo::new(&config.cache_dir).report_unkown_legs();
+fn diff(old: &Path, new: &Path, json: bool) -> Result<(), anyhow::Error> {
+    let read = |path: &Path| {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        iff::Iff::new_from_archive(&file).map_err(|e| anyhow!("parsing {}: {e}", path.display()))
+    };
+
+    let diff = read(new)?.diff(&read(old)?);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        print!("{diff}");
+    }
 
     Ok(())
 }
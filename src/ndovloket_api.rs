@@ -1,9 +1,15 @@
 //! This module gets the timetable file from <http://data.ndovloket.nl>
 use std::time::Duration;
 
+use reqwest::{
+    header::{HeaderMap, HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
 use thiserror::Error;
 
-const TIMETABLE_URL: &str = "http://data.ndovloket.nl/ns/ns-latest.zip";
+use crate::cache::{Fetched, Validators};
+
+pub const TIMETABLE_URL: &str = "http://data.ndovloket.nl/ns/ns-latest.zip";
 pub struct NDovLoket {}
 
 #[derive(Error, Debug)]
@@ -13,24 +19,53 @@ pub enum NdovLoketError {
 }
 
 impl NDovLoket {
-    pub async fn fetch_timetable() -> Result<Vec<u8>, NdovLoketError> {
+    /// Downloads the timetable, unless it's unchanged since the download `validators` belong to
+    pub async fn fetch_timetable(
+        url: &str,
+        validators: Validators,
+    ) -> Result<Fetched, NdovLoketError> {
         let client = reqwest::Client::builder()
             .connect_timeout(Duration::from_secs(30))
             .build()
             .map_err(NdovLoketError::Network)?;
 
-        let request = client
-            .get(TIMETABLE_URL)
-            .build()
-            .map_err(NdovLoketError::Network)?;
+        let mut request = client.get(url);
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let request = request.build().map_err(NdovLoketError::Network)?;
 
-        client
+        let response = client
             .execute(request)
             .await
-            .map_err(NdovLoketError::Network)?
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(NdovLoketError::Network)?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+
+        let validators = Validators {
+            etag: header_string(response.headers(), ETAG),
+            last_modified: header_string(response.headers(), LAST_MODIFIED),
+        };
+
+        let data = response
             .bytes()
             .await
-            .map_err(NdovLoketError::Network)
-            .map(std::convert::Into::into)
+            .map_err(NdovLoketError::Network)?
+            .into();
+
+        Ok(Fetched::Content { data, validators })
     }
 }
+
+fn header_string(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
@@ -1,28 +1,30 @@
 use anyhow::anyhow;
-use chrono::Duration;
 
 use crate::{
-    api::datarepo::{self, DataRepo},
+    api::datarepo::{self, stations::Station, DataRepo},
     cli,
     dayoffset::DayOffset,
+    iff::{LocationCodeHandle, TimetableEntry},
     time, AppConfig,
 };
 
 pub fn print(config: &AppConfig, args: cli::PrintStruct) -> Result<(), anyhow::Error> {
-    let data = datarepo::DataRepo::new(&config.cache_dir);
+    let data = datarepo::DataRepo::new(&config.cache_dir, &config.timetable)?;
 
     match args.command {
         cli::PrintSubCommand::Departures { station } => {
             print_departures(&data, station.as_str()).map_err(|a| anyhow!(a))
         }
+        cli::PrintSubCommand::Arrivals { station } => {
+            print_arrivals(&data, station.as_str()).map_err(|a| anyhow!(a))
+        }
     }
 }
 
-// fn select_station_by_name<'a,'b,'c>(stations: &'a [&'c Station], name_or_code: &'b str) -> Option<&'a Station> {
-
-// }
-
-fn print_departures(data: &DataRepo, name_or_code: &str) -> Result<(), String> {
+fn find_station<'a>(
+    data: &'a DataRepo,
+    name_or_code: &str,
+) -> Result<(&'a Station, LocationCodeHandle), String> {
     let station = data
         .stations()
         .iter()
@@ -30,54 +32,71 @@ fn print_departures(data: &DataRepo, name_or_code: &str) -> Result<(), String> {
         .or_else(|| datarepo::select_station_by_name(data.stations(), name_or_code))
         .ok_or("failed to find station")?;
 
-    let code = &station.code;
-    let handle = data.location_cache().lookup_handle(code).unwrap();
+    let handle = data
+        .location_cache()
+        .lookup_handle(&station.code)
+        .ok_or("station is not in the timetable")?;
+
+    Ok((station, handle))
+}
+
+/// Name of the station at `entry`, falling back to its code
+fn station_name<'a>(data: &'a DataRepo, entry: Option<&TimetableEntry>) -> &'a str {
+    entry
+        .and_then(|entry| data.location_cache().get_str(&entry.code))
+        .map(|code| {
+            data.station_by_code(code)
+                .map_or(code, |station| station.name.as_str())
+        })
+        .unwrap_or_default()
+}
+
+fn print_departures(data: &DataRepo, name_or_code: &str) -> Result<(), String> {
+    let (station, handle) = find_station(data, name_or_code)?;
 
     println!("{}", station.name);
 
+    // Only show departures from now up to two hours ahead
     let now = time::timetable_now();
-    let future = now + Duration::hours(2); // TODO max ride time instead
-    let mut active_rides =
-        data.rides_active_in_timespan(&now.time(), &future.time(), &now.date_naive());
-
-    active_rides.retain(|ride| ride.boardable_at_code(&handle));
-
-    // Timestamp before which are hide departures, since they're too far in the past to be relevant
-    let cutoff_time_start = DayOffset::from_naivetime(&now.time());
-    let cutoff_time_end = cutoff_time_start.offset_by(2 * 60);
-
-    // Match ride with their stop at the given station code
-    // And filter these to trains that depart between `cutoff_time_start` and `cutoff_time_end`
-    let mut ride_and_stop: Vec<_> = active_rides
-        .into_iter()
-        .map(|ride| (ride, ride.stop_at_code(&handle).unwrap()))
-        .filter(|(_, stop)| {
-            stop.stop_kind.departure_time().unwrap() > &cutoff_time_start
-                && stop.stop_kind.departure_time().unwrap() < &cutoff_time_end
-        })
-        .collect();
+    let from = DayOffset::from_naivetime(&now.time());
+    let to = from.offset_by(2 * 60);
 
-    ride_and_stop.sort_by_key(|a| a.1.stop_kind.departure_time().unwrap());
+    for call in data.departures(&handle, now.date_naive(), from, to) {
+        println!(
+            "{:5} {:5} {:3} {}",
+            call.ride.id,
+            call.time.display_for_timetable(),
+            data.platforms_on(call.stop.stop_kind.platforms(), now.date_naive())
+                .and_then(|p| p.departure_platform.as_ref())
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            station_name(data, call.ride.timetable.last()),
+        )
+    }
+
+    Ok(())
+}
+
+fn print_arrivals(data: &DataRepo, name_or_code: &str) -> Result<(), String> {
+    let (station, handle) = find_station(data, name_or_code)?;
+
+    println!("{}", station.name);
+
+    // Only show arrivals from now up to two hours ahead
+    let now = time::timetable_now();
+    let from = DayOffset::from_naivetime(&now.time());
+    let to = from.offset_by(2 * 60);
 
-    for (ride, stop) in ride_and_stop {
+    for call in data.arrivals(&handle, now.date_naive(), from, to) {
         println!(
             "{:5} {:5} {:3} {}",
-            ride.id,
-            stop.stop_kind
-                .departure_time()
-                .unwrap()
-                .display_for_timetable(),
-            stop.stop_kind
-                .platform_info()
-                .map(|p| p.departure_platform.as_ref().unwrap().to_string())
+            call.ride.id,
+            call.time.display_for_timetable(),
+            data.platforms_on(call.stop.stop_kind.platforms(), now.date_naive())
+                .and_then(|p| p.arrival_platform.as_ref())
+                .map(|p| p.to_string())
                 .unwrap_or_default(),
-            data.station_by_code(
-                data.location_cache()
-                    .get_str(&ride.timetable.last().unwrap().code)
-                    .unwrap()
-            )
-            .unwrap()
-            .name,
+            station_name(data, call.ride.timetable.first()),
         )
     }
 
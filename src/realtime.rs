@@ -0,0 +1,576 @@
+//! Realtime delay and cancellation state, overlaid on the static timetable
+//!
+//! Messages are read from KV6 XML feeds, see [`kv6`], or as JSON lines, one message per line:
+//! ```json
+//! {"type":"delay","rideId":"2871","date":"2024-01-02","station":"ut","arrivalDelay":120,"departureDelay":180}
+//! {"type":"cancellation","rideId":"2871","date":"2024-01-02"}
+//! {"type":"cancellation","rideId":"2871","date":"2024-01-02","station":"wd","passage":1}
+//! ```
+//! Delays are in seconds, `date` is the timetable date the ride runs on.
+//! `passage` counts earlier calls of the ride at the same station, for rides calling at a station more than once.
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use chrono::{Days, NaiveDate};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, BufReader},
+    net::TcpListener,
+};
+
+use crate::{
+    api::datarepo::DataRepo,
+    iff::{LocationCache, Ride},
+    time::timetable_now,
+};
+
+pub mod kv6;
+
+/// Where realtime messages are read from
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum RealtimeSource {
+    /// A file of recorded messages, read once on startup
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        format: RealtimeFormat,
+    },
+    /// A local TCP socket, every connection can stream messages
+    Socket {
+        bind_addr: String,
+        #[serde(default)]
+        format: RealtimeFormat,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RealtimeFormat {
+    /// JSON lines as described in the module documentation
+    #[default]
+    Json,
+    /// KV6 XML documents, as recorded from the NDOV loket
+    Kv6,
+}
+
+#[derive(Error, Debug)]
+pub enum RealtimeError {
+    #[error("Error reading realtime messages: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Invalid realtime message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid KV6 XML: {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("Invalid KV6 message: {0}")]
+    Kv6(String),
+    #[error("Unknown station {0}")]
+    UnknownStation(String),
+    #[error("No ride {0} on {1}")]
+    UnknownRide(String, NaiveDate),
+    #[error("Ride {ride_id} doesn't call at {station} {} times", passage + 1)]
+    UnknownCall {
+        ride_id: String,
+        station: String,
+        passage: usize,
+    },
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RealtimeMessage {
+    /// Delays at a stop, later stops without an update of their own inherit the departure delay
+    /// Delays left out keep their earlier value
+    #[serde(rename_all = "camelCase")]
+    Delay {
+        ride_id: String,
+        date: NaiveDate,
+        station: String,
+        #[serde(default)]
+        passage: usize,
+        arrival_delay: Option<i32>,
+        departure_delay: Option<i32>,
+    },
+    /// Cancels the call at `station`, or the whole ride when no station is given
+    #[serde(rename_all = "camelCase")]
+    Cancellation {
+        ride_id: String,
+        date: NaiveDate,
+        station: Option<String>,
+        #[serde(default)]
+        passage: usize,
+    },
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StopRealtime {
+    /// Arrival delay in seconds
+    pub arrival_delay: Option<i32>,
+    /// Departure delay in seconds
+    pub departure_delay: Option<i32>,
+    pub cancelled: bool,
+}
+
+impl StopRealtime {
+    /// Delay on arrival, falling back to the departure delay
+    pub fn delay_on_arrival(&self) -> Option<i32> {
+        self.arrival_delay.or(self.departure_delay)
+    }
+
+    /// Delay on departure, falling back to the arrival delay
+    pub fn delay_on_departure(&self) -> Option<i32> {
+        self.departure_delay.or(self.arrival_delay)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RideRealtime {
+    pub cancelled: bool,
+    /// Updates by the index of the stop in the timetable of the ride
+    stops: HashMap<usize, StopRealtime>,
+}
+
+impl RideRealtime {
+    /// Realtime state of the stop at `index` in the timetable of the ride
+    pub fn stop(&self, index: usize) -> Option<&StopRealtime> {
+        self.stops.get(&index)
+    }
+
+    /// Fills in every stop of `ride` that has no update of its own with the last known delay before it
+    pub fn propagated(&self, ride: &Ride) -> Self {
+        let mut stops = HashMap::with_capacity(ride.timetable.len());
+        let mut last_delay = None;
+
+        for (index, _) in ride
+            .timetable
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.stop_kind.is_waypoint())
+        {
+            let stop = match self.stops.get(&index) {
+                Some(stop) => stop.clone(),
+                None => StopRealtime {
+                    arrival_delay: last_delay,
+                    departure_delay: last_delay,
+                    cancelled: false,
+                },
+            };
+
+            last_delay = stop.delay_on_departure().or(last_delay);
+            stops.insert(index, stop);
+        }
+
+        Self {
+            cancelled: self.cancelled,
+            stops,
+        }
+    }
+}
+
+/// Realtime state for all rides, keyed by timetable date and ride id
+#[derive(Default)]
+pub struct RealtimeState {
+    rides: RwLock<HashMap<NaiveDate, HashMap<String, RideRealtime>>>,
+}
+
+impl RealtimeState {
+    pub fn ride(&self, id: &str, date: NaiveDate) -> Option<RideRealtime> {
+        self.rides
+            .read()
+            .unwrap()
+            .get(&date)
+            .and_then(|rides| rides.get(id))
+            .cloned()
+    }
+
+    /// Smallest and largest delay in seconds of any stop on `date`, both zero when nothing runs early or late
+    pub fn delay_range(&self, date: NaiveDate) -> (i32, i32) {
+        self.rides
+            .read()
+            .unwrap()
+            .get(&date)
+            .into_iter()
+            .flat_map(|rides| rides.values())
+            .flat_map(|ride| ride.stops.values())
+            .flat_map(|stop| [stop.arrival_delay, stop.departure_delay])
+            .flatten()
+            .fold((0, 0), |(min, max), delay| (min.min(delay), max.max(delay)))
+    }
+
+    /// Applies `message` to the ride `find_ride` returns for its id and date
+    pub fn apply<'r>(
+        &self,
+        message: RealtimeMessage,
+        locations: &LocationCache,
+        find_ride: impl FnOnce(&str, NaiveDate) -> Option<&'r Ride>,
+    ) -> Result<(), RealtimeError> {
+        let (ride_id, date, station, passage) = match &message {
+            RealtimeMessage::Delay {
+                ride_id,
+                date,
+                station,
+                passage,
+                ..
+            } => (ride_id, *date, Some(station), *passage),
+            RealtimeMessage::Cancellation {
+                ride_id,
+                date,
+                station,
+                passage,
+            } => (ride_id, *date, station.as_ref(), *passage),
+        };
+
+        // Every message has to match a scheduled ride, so the state only grows with the timetable
+        let ride = find_ride(ride_id, date)
+            .ok_or_else(|| RealtimeError::UnknownRide(ride_id.clone(), date))?;
+
+        let stop = station
+            .map(|station| {
+                let code = locations
+                    .lookup_handle(station)
+                    .ok_or_else(|| RealtimeError::UnknownStation(station.to_owned()))?;
+
+                ride.timetable
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| !entry.stop_kind.is_waypoint() && entry.code == code)
+                    .nth(passage)
+                    .map(|(index, _)| index)
+                    .ok_or_else(|| RealtimeError::UnknownCall {
+                        ride_id: ride_id.clone(),
+                        station: station.clone(),
+                        passage,
+                    })
+            })
+            .transpose()?;
+
+        let mut rides = self.rides.write().unwrap();
+        let ride = rides
+            .entry(date)
+            .or_default()
+            .entry(ride_id.clone())
+            .or_default();
+
+        match (message, stop) {
+            (
+                RealtimeMessage::Delay {
+                    arrival_delay,
+                    departure_delay,
+                    ..
+                },
+                Some(index),
+            ) => {
+                let stop = ride.stops.entry(index).or_default();
+                stop.arrival_delay = arrival_delay.or(stop.arrival_delay);
+                stop.departure_delay = departure_delay.or(stop.departure_delay);
+            }
+            (RealtimeMessage::Cancellation { .. }, Some(index)) => {
+                ride.stops.entry(index).or_default().cancelled = true;
+            }
+            (_, None) => ride.cancelled = true,
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the state of all dates before `date`
+    pub fn purge_before(&self, date: NaiveDate) {
+        self.rides.write().unwrap().retain(|day, _| *day >= date);
+    }
+}
+
+/// Reads messages from `source` into the realtime state of `repo` until the source is exhausted
+/// Invalid messages are reported and skipped, streamed state is kept for the current and previous day only
+pub async fn ingest(source: RealtimeSource, repo: Arc<DataRepo>) -> Result<(), RealtimeError> {
+    match source {
+        RealtimeSource::File { path, format } => {
+            let file = tokio::fs::File::open(&path).await?;
+            let count = apply_stream(BufReader::new(file), format, &repo, false).await?;
+
+            println!("Applied {count} realtime messages from {}", path.display());
+        }
+        RealtimeSource::Socket { bind_addr, format } => {
+            let listener = TcpListener::bind(&bind_addr).await?;
+            println!("Realtime listening on {bind_addr}");
+
+            loop {
+                let (stream, peer) = listener.accept().await?;
+                let repo = repo.clone();
+
+                tokio::spawn(async move {
+                    match apply_stream(BufReader::new(stream), format, &repo, true).await {
+                        Ok(count) => println!("Applied {count} realtime messages from {peer}"),
+                        Err(e) => eprintln!("Realtime connection {peer}: {e}"),
+                    }
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies a message to the realtime state of `repo`, looking its ride up by id and date
+fn apply(repo: &DataRepo, message: RealtimeMessage) -> Result<(), RealtimeError> {
+    repo.realtime()
+        .apply(message, repo.location_cache(), |id, date| {
+            repo.rides_with_id_on_date(id, &date).first().copied()
+        })
+}
+
+/// Applies the messages read from `reader`, purging state from before yesterday by the wall clock when `purge` is set
+async fn apply_stream(
+    reader: impl AsyncBufRead + Unpin,
+    format: RealtimeFormat,
+    repo: &DataRepo,
+    purge: bool,
+) -> Result<usize, RealtimeError> {
+    let mut lines = reader.lines();
+    let mut count = 0;
+    // KV6 documents span several lines, they are decoded once complete
+    let mut document = String::new();
+
+    while let Some(line) = lines.next_line().await? {
+        let messages = match format {
+            RealtimeFormat::Json if line.trim().is_empty() => continue,
+            RealtimeFormat::Json => vec![serde_json::from_str(&line).map_err(RealtimeError::from)],
+            RealtimeFormat::Kv6 => {
+                document.push_str(&line);
+                document.push('\n');
+
+                let Some(end) = kv6::document_end(&document) else {
+                    continue;
+                };
+                let decoded = kv6::decode(&document[..end]);
+                document.drain(..end);
+
+                match decoded {
+                    Ok(messages) => messages.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                }
+            }
+        };
+
+        for message in messages {
+            match message.and_then(|message| apply(repo, message)) {
+                Ok(()) => count += 1,
+                Err(e) => eprintln!("Skipping realtime message: {e}"),
+            }
+        }
+
+        if purge {
+            if let Some(yesterday) = timetable_now().date_naive().checked_sub_days(Days::new(1)) {
+                repo.realtime().purge_before(yesterday);
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use crate::{
+        dayoffset::DayOffset,
+        iff::{StopKind, TimetableEntry},
+    };
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    /// Ride 2871 calling at `codes`, ten minutes apart
+    fn ride(locations: &mut LocationCache, codes: &[&str]) -> Ride {
+        let time = |index: usize| DayOffset::from_hour_minute(10, index as u32 * 10);
+        let last = codes.len() - 1;
+
+        Ride {
+            id: "2871".to_owned(),
+            transit_mode: "IC".to_owned(),
+            timetable: codes
+                .iter()
+                .enumerate()
+                .map(|(index, code)| TimetableEntry {
+                    code: locations.get_handle(code),
+                    stop_kind: match index {
+                        0 => StopKind::Departure(vec![], time(index)),
+                        index if index == last => StopKind::Arrival(vec![], time(index)),
+                        _ => StopKind::StopShort(vec![], time(index)),
+                    },
+                })
+                .collect(),
+            day_validity: 1,
+            previous: None,
+            next: None,
+            operator: 1,
+        }
+    }
+
+    fn delay(day: u32, station: &str, passage: usize, delay: i32) -> RealtimeMessage {
+        RealtimeMessage::Delay {
+            ride_id: "2871".to_owned(),
+            date: date(day),
+            station: station.to_owned(),
+            passage,
+            arrival_delay: Some(delay),
+            departure_delay: Some(delay),
+        }
+    }
+
+    #[test]
+    fn parse_messages() -> TestResult {
+        let delay: RealtimeMessage = serde_json::from_str(
+            r#"{"type":"delay","rideId":"2871","date":"2024-01-02","station":"ut","departureDelay":180}"#,
+        )?;
+        let cancellation: RealtimeMessage =
+            serde_json::from_str(r#"{"type":"cancellation","rideId":"2871","date":"2024-01-02"}"#)?;
+
+        assert_eq!(
+            delay,
+            RealtimeMessage::Delay {
+                ride_id: "2871".to_owned(),
+                date: date(2),
+                station: "ut".to_owned(),
+                passage: 0,
+                arrival_delay: None,
+                departure_delay: Some(180),
+            }
+        );
+        assert_eq!(
+            cancellation,
+            RealtimeMessage::Cancellation {
+                ride_id: "2871".to_owned(),
+                date: date(2),
+                station: None,
+                passage: 0,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn delays_propagate_to_later_stops() -> TestResult {
+        let mut locations = LocationCache::new();
+        let ride = ride(&mut locations, &["ut", "wd", "gd"]);
+
+        let state = RealtimeState::default();
+        state.apply(
+            RealtimeMessage::Delay {
+                ride_id: "2871".to_owned(),
+                date: date(2),
+                station: "wd".to_owned(),
+                passage: 0,
+                arrival_delay: Some(60),
+                departure_delay: Some(120),
+            },
+            &locations,
+            |_, _| Some(&ride),
+        )?;
+
+        assert_eq!(state.ride("2871", date(3)), None);
+
+        let realtime = state.ride("2871", date(2)).unwrap().propagated(&ride);
+        assert_eq!(realtime.stop(0), Some(&StopRealtime::default()));
+        assert_eq!(realtime.stop(1).unwrap().departure_delay, Some(120));
+        assert_eq!(realtime.stop(2).unwrap().arrival_delay, Some(120));
+
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_calls_are_kept_apart() -> TestResult {
+        let mut locations = LocationCache::new();
+        // Out and back to Utrecht, like a shuttle turning at Woerden
+        let ride = ride(&mut locations, &["ut", "wd", "ut"]);
+
+        let state = RealtimeState::default();
+        state.apply(delay(2, "ut", 1, 300), &locations, |_, _| Some(&ride))?;
+
+        let realtime = state.ride("2871", date(2)).unwrap();
+        assert_eq!(realtime.stop(0), None);
+        assert_eq!(realtime.stop(2).unwrap().arrival_delay, Some(300));
+
+        let result = state.apply(delay(2, "ut", 2, 300), &locations, |_, _| Some(&ride));
+        assert!(matches!(result, Err(RealtimeError::UnknownCall { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn state_is_purged_by_date_not_by_messages() -> TestResult {
+        let mut locations = LocationCache::new();
+        let ride = ride(&mut locations, &["ut", "wd", "gd"]);
+
+        let state = RealtimeState::default();
+        state.apply(delay(2, "wd", 0, 60), &locations, |_, _| Some(&ride))?;
+        // A message for a later date leaves the state of other dates alone
+        let cancel = |day| RealtimeMessage::Cancellation {
+            ride_id: "2871".to_owned(),
+            date: date(day),
+            station: None,
+            passage: 0,
+        };
+        state.apply(cancel(4), &locations, |_, _| Some(&ride))?;
+        assert!(state.ride("2871", date(2)).is_some());
+
+        state.purge_before(date(3));
+        assert_eq!(state.ride("2871", date(2)), None);
+        assert!(state
+            .ride("2871", date(4))
+            .is_some_and(|ride| ride.cancelled));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_calls_are_rejected() {
+        let mut locations = LocationCache::new();
+        let ride = ride(&mut locations, &["ut", "wd", "gd"]);
+        let state = RealtimeState::default();
+
+        let unknown_station = state.apply(
+            RealtimeMessage::Cancellation {
+                ride_id: "2871".to_owned(),
+                date: date(2),
+                station: Some("xyz".to_owned()),
+                passage: 0,
+            },
+            &locations,
+            |_, _| Some(&ride),
+        );
+        assert!(matches!(
+            unknown_station,
+            Err(RealtimeError::UnknownStation(_))
+        ));
+
+        let unknown_ride = state.apply(delay(2, "wd", 0, 60), &locations, |_, _| None);
+        assert!(matches!(unknown_ride, Err(RealtimeError::UnknownRide(..))));
+
+        // Cancelling a whole ride that isn't scheduled stores nothing either
+        let unknown_cancelled_ride = state.apply(
+            RealtimeMessage::Cancellation {
+                ride_id: "9999".to_owned(),
+                date: date(2),
+                station: None,
+                passage: 0,
+            },
+            &locations,
+            |_, _| None,
+        );
+        assert!(matches!(
+            unknown_cancelled_ride,
+            Err(RealtimeError::UnknownRide(..))
+        ));
+        assert_eq!(state.ride("9999", date(2)), None);
+    }
+}
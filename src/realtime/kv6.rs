@@ -0,0 +1,199 @@
+//! Decoder for KV6 punctuality messages, the `KV6posinfo` dossier of the BISON TMI8 standard as published by the NDOV loket
+//!
+//! `ARRIVAL`, `ONSTOP` and `DEPARTURE` messages become delays at the stop they name, the other messages don't describe a call and are skipped.
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use quick_xml::{events::Event, Reader};
+
+use super::{RealtimeError, RealtimeMessage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Arrival,
+    OnStop,
+    Departure,
+}
+
+impl Kind {
+    fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"ARRIVAL" => Some(Self::Arrival),
+            b"ONSTOP" => Some(Self::OnStop),
+            b"DEPARTURE" => Some(Self::Departure),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the punctuality messages in `xml`, which holds any number of `VV_TM_PUSH` documents
+pub fn decode(xml: &str) -> Result<Vec<RealtimeMessage>, RealtimeError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut messages = vec![];
+    // The message being read along with its fields so far, and the field being read
+    let mut current: Option<(Kind, HashMap<String, String>)> = None;
+    let mut field: Option<String> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => match current {
+                Some(_) => {
+                    field = Some(String::from_utf8_lossy(start.local_name().as_ref()).into_owned())
+                }
+                None => {
+                    current = Kind::from_name(start.local_name().as_ref())
+                        .map(|kind| (kind, HashMap::new()))
+                }
+            },
+            Event::Text(text) => {
+                if let (Some((_, fields)), Some(field)) = (&mut current, &field) {
+                    fields.insert(field.clone(), text.unescape()?.into_owned());
+                }
+            }
+            // Closing the message itself rather than one of its fields
+            Event::End(_) if field.take().is_none() => {
+                if let Some((kind, fields)) = current.take() {
+                    messages.push(message(kind, &fields)?);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(messages)
+}
+
+fn message(kind: Kind, fields: &HashMap<String, String>) -> Result<RealtimeMessage, RealtimeError> {
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .map(|value| value.trim())
+            .ok_or_else(|| RealtimeError::Kv6(format!("{kind:?} without {name}")))
+    };
+    let invalid = |name: &str| RealtimeError::Kv6(format!("{kind:?} with invalid {name}"));
+
+    let ride_id: u32 = field("journeynumber")?
+        .parse()
+        .map_err(|_| invalid("journeynumber"))?;
+    let date = NaiveDate::parse_from_str(field("operatingday")?, "%Y-%m-%d")
+        .map_err(|_| invalid("operatingday"))?;
+    let passage = match fields.get("passagesequencenumber") {
+        Some(passage) => passage
+            .trim()
+            .parse()
+            .map_err(|_| invalid("passagesequencenumber"))?,
+        None => 0,
+    };
+    let punctuality: i32 = field("punctuality")?
+        .parse()
+        .map_err(|_| invalid("punctuality"))?;
+
+    Ok(RealtimeMessage::Delay {
+        ride_id: ride_id.to_string(),
+        date,
+        station: field("userstopcode")?.to_lowercase(),
+        passage,
+        arrival_delay: (kind != Kind::Departure).then_some(punctuality),
+        departure_delay: (kind == Kind::Departure).then_some(punctuality),
+    })
+}
+
+/// Byte offset just past the first complete `VV_TM_PUSH` document in `buffer`, if there is one
+pub fn document_end(buffer: &str) -> Option<usize> {
+    buffer.match_indices("VV_TM_PUSH>").find_map(|(index, _)| {
+        let tag = &buffer[buffer[..index].rfind('<')?..index];
+        tag.starts_with("</").then_some(index + "VV_TM_PUSH>".len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use super::*;
+
+    const KV6: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<VV_TM_PUSH xmlns="http://bison.connekt.nl/tmi8/kv6/msg">
+  <SubscriberID>rustyrails</SubscriberID>
+  <Version>BISON 8.1.1.0</Version>
+  <DossierName>KV6posinfo</DossierName>
+  <Timestamp>2024-01-02T10:19:12+01:00</Timestamp>
+  <KV6posinfo>
+    <ARRIVAL>
+      <dataownercode>NS</dataownercode>
+      <lineplanningnumber>IC</lineplanningnumber>
+      <operatingday>2024-01-02</operatingday>
+      <journeynumber>2871</journeynumber>
+      <reinforcementnumber>0</reinforcementnumber>
+      <userstopcode>WD</userstopcode>
+      <passagesequencenumber>0</passagesequencenumber>
+      <timestamp>2024-01-02T10:19:00+01:00</timestamp>
+      <source>VEHICLE</source>
+      <vehiclenumber>4011</vehiclenumber>
+      <punctuality>60</punctuality>
+    </ARRIVAL>
+    <ONROUTE>
+      <dataownercode>NS</dataownercode>
+      <operatingday>2024-01-02</operatingday>
+      <journeynumber>2871</journeynumber>
+      <punctuality>90</punctuality>
+    </ONROUTE>
+    <tmi8:DEPARTURE xmlns:tmi8="http://bison.connekt.nl/tmi8/kv6/msg">
+      <tmi8:dataownercode>NS</tmi8:dataownercode>
+      <tmi8:operatingday>2024-01-02</tmi8:operatingday>
+      <tmi8:journeynumber>02871</tmi8:journeynumber>
+      <tmi8:userstopcode>WD</tmi8:userstopcode>
+      <tmi8:passagesequencenumber>0</tmi8:passagesequencenumber>
+      <tmi8:punctuality>120</tmi8:punctuality>
+    </tmi8:DEPARTURE>
+  </KV6posinfo>
+</VV_TM_PUSH>
+"#;
+
+    #[test]
+    fn punctuality_messages_are_decoded() -> TestResult {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let delay = |arrival_delay, departure_delay| RealtimeMessage::Delay {
+            ride_id: "2871".to_owned(),
+            date,
+            station: "wd".to_owned(),
+            passage: 0,
+            arrival_delay,
+            departure_delay,
+        };
+
+        // Two recorded documents back to back
+        let messages = decode(&KV6.repeat(2))?;
+
+        assert_eq!(
+            messages,
+            [
+                delay(Some(60), None),
+                delay(None, Some(120)),
+                delay(Some(60), None),
+                delay(None, Some(120)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_messages_are_rejected() {
+        let xml = KV6.replace("<punctuality>60</punctuality>", "");
+
+        assert!(matches!(decode(&xml), Err(RealtimeError::Kv6(_))));
+    }
+
+    #[test]
+    fn documents_are_split() {
+        let first = KV6.find("</VV_TM_PUSH>").unwrap() + "</VV_TM_PUSH>".len();
+
+        assert_eq!(document_end(&KV6.repeat(2)), Some(first));
+        assert_eq!(document_end(&KV6[..first - 1]), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Local stand-in for the NS API and NDOV Loket, serving recorded responses from a fixtures directory
+//!
+//! The directory is expected to contain:
+//! - `ns-latest.zip`, the IFF timetable, served at [`TIMETABLE_PATH`]
+//! - `stations.json`, `spoorkaart.json` and `trips.json`, served at their NS API paths
+//!
+//! Requests are answered with the same recording regardless of their query.
+use std::path::Path;
+
+use anyhow::Context;
+use poem::{
+    endpoint::StaticFileEndpoint,
+    get,
+    listener::{Listener, TcpAcceptor, TcpListener},
+    Route, Server,
+};
+
+/// Path the timetable zip is served at, mirroring the NDOV Loket URL
+pub const TIMETABLE_PATH: &str = "/ns/ns-latest.zip";
+
+pub fn app(fixtures_dir: &Path) -> Route {
+    let file = |name: &str| get(StaticFileEndpoint::new(fixtures_dir.join(name)));
+
+    Route::new()
+        .at(TIMETABLE_PATH, file("ns-latest.zip"))
+        .at(format!("/{}", ns_api::STATION_PATH), file("stations.json"))
+        .at(format!("/{}", ns_api::ROUTE_PATH), file("spoorkaart.json"))
+        .at(format!("/{}", ns_api::TRIP_PATH), file("trips.json"))
+}
+
+async fn bind(bind_addr: &str) -> Result<TcpAcceptor, anyhow::Error> {
+    TcpListener::bind(bind_addr.to_owned())
+        .into_acceptor()
+        .await
+        .with_context(|| format!("Binding stand-in to {bind_addr}"))
+}
+
+#[tokio::main]
+pub async fn serve(fixtures_dir: &Path, bind_addr: &str) -> Result<(), anyhow::Error> {
+    if !fixtures_dir.is_dir() {
+        anyhow::bail!("Fixtures directory {} not found", fixtures_dir.display());
+    }
+
+    let acceptor = bind(bind_addr).await?;
+
+    println!(
+        "Serving stand-in responses from {} on {bind_addr}",
+        fixtures_dir.display()
+    );
+    println!("Set upstream.ns_api_host = \"http://{bind_addr}/\"");
+    println!("Set upstream.timetable_url = \"http://{bind_addr}{TIMETABLE_PATH}\"");
+
+    Server::new_with_acceptor(acceptor)
+        .run(app(fixtures_dir))
+        .await
+        .context("Running stand-in server")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::DateTime;
+    use poem::listener::Acceptor;
+    use pretty_assertions::assert_eq;
+    use testresult::TestResult;
+
+    use crate::{
+        api::datarepo::{snapshot, DataRepo, TimetableSource},
+        cache::RetentionPolicy,
+        fetch,
+        iff::test_fixtures::TempDir,
+        UpstreamConfig,
+    };
+
+    use super::*;
+
+    #[test]
+    fn fetch_and_serve_offline() -> TestResult {
+        let fixtures_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/standin");
+        let cache_dir = TempDir::new("standin")?;
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        let acceptor = runtime.block_on(bind("127.0.0.1:0"))?;
+        let addr = acceptor.local_addr()[0]
+            .as_socket_addr()
+            .copied()
+            .ok_or("stand-in to listen on a socket")?;
+        runtime.spawn(Server::new_with_acceptor(acceptor).run(app(&fixtures_dir)));
+
+        let upstream = UpstreamConfig {
+            ns_api_host: format!("http://{addr}/"),
+            timetable_url: format!("http://{addr}{TIMETABLE_PATH}"),
+            ..UpstreamConfig::default()
+        };
+
+        fetch::fetch(
+            &cache_dir,
+            Some("standin"),
+            &upstream,
+            &RetentionPolicy::default(),
+            &TimetableSource::default(),
+        )?;
+
+        assert!(cache_dir.join(snapshot::SNAPSHOT_PATH).is_file());
+
+        let mut repo = DataRepo::load(&cache_dir, &TimetableSource::default())?;
+        repo.filter_unknown_legs();
+
+        assert_eq!(repo.stations().len(), 3);
+        assert_eq!(repo.rides().len(), 2);
+
+        let ns = ns_api::NsApi::new("standin".to_owned()).host(&upstream.ns_api_host);
+        let departure = DateTime::parse_from_rfc3339("2024-01-02T10:08:00+01:00")?;
+        let response = runtime.block_on(
+            ns.find_path(&ns_api::TripAdviceArguments::new("ut", "gd").date_time(departure)),
+        )?;
+
+        let leg = &response.trips[0].legs[0];
+        let departure = departure.naive_local();
+        let (ride, _) = repo
+            .find_ride(leg.product.get_number().unwrap(), "ut", departure)
+            .ok_or("planned ride to be in the timetable")?;
+        assert_eq!(ride.id, "2871");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::HashMap, fmt::Display, fs, io::Write};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::datarepo::{
+        quality::{Check, Finding, QualityReport},
+        DataRepo,
+    },
+    cli::{self, ReportFormat},
+    AppConfig,
+};
+
+/// Limits on the issues `verify` accepts before failing
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct VerifyConfig {
+    /// Issues a check may find, checks without a threshold never fail
+    pub thresholds: HashMap<Check, usize>,
+    /// Most frequent subjects listed per check
+    pub examples: usize,
+}
+
+impl Default for VerifyConfig {
+    fn default() -> Self {
+        Self {
+            thresholds: HashMap::new(),
+            examples: 10,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    passed: bool,
+    checks: Vec<CheckOutcome<'a>>,
+}
+
+#[derive(Serialize)]
+struct CheckOutcome<'a> {
+    check: Check,
+    count: usize,
+    threshold: Option<usize>,
+    exceeded: bool,
+    findings: &'a [Finding],
+}
+
+impl<'a> Report<'a> {
+    fn new(report: &'a QualityReport, config: &VerifyConfig) -> Self {
+        let checks: Vec<_> = report
+            .checks
+            .iter()
+            .map(|result| {
+                let threshold = config.thresholds.get(&result.check).copied();
+
+                CheckOutcome {
+                    check: result.check,
+                    count: result.count,
+                    threshold,
+                    exceeded: threshold.is_some_and(|threshold| result.count > threshold),
+                    findings: &result.findings[..result.findings.len().min(config.examples)],
+                }
+            })
+            .collect();
+
+        Self {
+            passed: !checks.iter().any(|check| check.exceeded),
+            checks,
+        }
+    }
+}
+
+/// Markdown rendering of the report
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# Timetable data quality")?;
+        writeln!(f)?;
+        writeln!(f, "| Check | Issues | Threshold | Status |")?;
+        writeln!(f, "| --- | ---: | ---: | --- |")?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "| {} | {} | {} | {} |",
+                check.check,
+                check.count,
+                check
+                    .threshold
+                    .map(|threshold| threshold.to_string())
+                    .unwrap_or_default(),
+                if check.exceeded { "failed" } else { "ok" },
+            )?;
+        }
+
+        for check in self.checks.iter().filter(|check| check.count > 0) {
+            writeln!(f)?;
+            writeln!(f, "## {} ({})", check.check, check.count)?;
+            writeln!(f)?;
+            for finding in check.findings {
+                writeln!(f, "- {} ({})", finding.subject, finding.count)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn verify(config: &AppConfig, args: cli::VerifyStruct) -> Result<(), anyhow::Error> {
+    let repo = DataRepo::new(&config.cache_dir, &config.timetable)?;
+    let quality = repo.quality_report();
+    let report = Report::new(&quality, &config.verify);
+
+    let content = match args.format {
+        ReportFormat::Markdown => report.to_string(),
+        ReportFormat::Json => serde_json::to_string_pretty(&report)? + "\n",
+    };
+
+    match &args.output {
+        Some(path) => fs::write(path, content)
+            .with_context(|| format!("writing report to {}", path.display()))?,
+        None => std::io::stdout().write_all(content.as_bytes())?,
+    }
+
+    if report.passed {
+        return Ok(());
+    }
+
+    let exceeded: Vec<_> = report
+        .checks
+        .iter()
+        .filter(|check| check.exceeded)
+        .map(|check| {
+            format!(
+                "{} ({} > {})",
+                check.check,
+                check.count,
+                check.threshold.unwrap_or_default()
+            )
+        })
+        .collect();
+
+    Err(anyhow!(
+        "Data quality thresholds exceeded: {}",
+        exceeded.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::api::datarepo::quality::CheckResult;
+
+    #[test]
+    fn thresholds_fail_the_report() {
+        let quality = QualityReport {
+            checks: vec![
+                CheckResult {
+                    check: Check::UnknownCompany,
+                    count: 3,
+                    findings: vec![
+                        Finding {
+                            subject: "company 200".to_owned(),
+                            count: 2,
+                        },
+                        Finding {
+                            subject: "company 300".to_owned(),
+                            count: 1,
+                        },
+                    ],
+                },
+                CheckResult {
+                    check: Check::MissingPlatform,
+                    count: 0,
+                    findings: vec![],
+                },
+            ],
+        };
+        let mut config = VerifyConfig {
+            examples: 1,
+            ..Default::default()
+        };
+
+        assert!(Report::new(&quality, &config).passed);
+
+        config.thresholds.insert(Check::UnknownCompany, 2);
+        config.thresholds.insert(Check::MissingPlatform, 0);
+        let report = Report::new(&quality, &config);
+
+        assert!(!report.passed);
+        assert_eq!(
+            report.to_string(),
+            "# Timetable data quality\n\
+             \n\
+             | Check | Issues | Threshold | Status |\n\
+             | --- | ---: | ---: | --- |\n\
+             | Unknown companies | 3 | 2 | failed |\n\
+             | Stops without platforms | 0 | 0 | ok |\n\
+             \n\
+             ## Unknown companies (3)\n\
+             \n\
+             - company 200 (2)\n"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! Commands with machine readable output, run as their own process so anything else written to stdout shows up
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use pretty_assertions::assert_eq;
+use serde_json::Value;
+use testresult::TestResult;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/standin");
+
+/// Working directory with the stand-in data in the cache layout, removed when dropped
+struct StandinDir(PathBuf);
+
+impl StandinDir {
+    fn new(name: &str) -> Result<Self, Box<dyn Error>> {
+        let dir =
+            std::env::temp_dir().join(format!("rustyrails-cli-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let dir = Self(dir);
+
+        fs::create_dir_all(dir.cache().join("remote"))?;
+        for (fixture, path) in [
+            ("ns-latest.zip", "remote/ns_iff.zip"),
+            ("stations.json", "remote/stations.json"),
+            ("spoorkaart.json", "remote/route.json"),
+        ] {
+            fs::copy(Path::new(FIXTURES).join(fixture), dir.cache().join(path))?;
+        }
+
+        Ok(dir)
+    }
+
+    fn cache(&self) -> PathBuf {
+        self.0.join("cache")
+    }
+}
+
+impl Drop for StandinDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the binary with `args` in `dir`, returning its stdout
+fn run(dir: &StandinDir, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new(env!("CARGO_BIN_EXE_rustyrails"))
+        .args(args)
+        .current_dir(&dir.0)
+        .env("APP_CACHE_DIR", dir.cache())
+        .env("APP_ALLOW_CACHE_OVERWRITE", "false")
+        .env("APP_CORS_DOMAIN", "http://localhost")
+        .env("APP_BIND_ADDR", "localhost:0")
+        .output()?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into());
+    }
+
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn export_geojson_to_stdout_is_geojson() -> TestResult {
+    let dir = StandinDir::new("geojson")?;
+
+    let stdout = run(&dir, &["export", "geojson", "--date", "2024-01-02"])?;
+    let geojson: Value = serde_json::from_str(&stdout)?;
+
+    assert_eq!(geojson["type"], "FeatureCollection");
+    assert_eq!(geojson["features"].as_array().map(Vec::len), Some(2));
+
+    Ok(())
+}
+
+#[test]
+fn verify_json_is_json() -> TestResult {
+    let dir = StandinDir::new("verify")?;
+
+    let stdout = run(&dir, &["verify", "--format", "json"])?;
+    let report: Value = serde_json::from_str(&stdout)?;
+
+    assert_eq!(report["passed"], true);
+    assert!(report["checks"]
+        .as_array()
+        .is_some_and(|checks| !checks.is_empty()));
+
+    Ok(())
+}
+
+#[test]
+fn diff_json_is_json() -> TestResult {
+    let dir = StandinDir::new("diff")?;
+    let archive = dir.cache().join("remote/ns_iff.zip");
+    let archive = archive.to_str().ok_or("temp dir to be UTF-8")?;
+
+    let stdout = run(&dir, &["diff", "--json", archive, archive])?;
+    let diff: Value = serde_json::from_str(&stdout)?;
+
+    assert_eq!(diff["changedRides"], Value::Array(vec![]));
+
+    Ok(())
+}